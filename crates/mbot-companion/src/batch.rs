@@ -0,0 +1,260 @@
+//! Headless simulation runs - the brain against `SimWorld` on a virtual
+//! clock, as fast as the CPU allows
+//!
+//! Each run ticks a seeded brain in a scene at the control loop's rate,
+//! passing readings through the scene's `SensorNoise` as the `sim://` link
+//! does, and sums up how the robot behaved. Nothing here sleeps, so ten
+//! simulated minutes take well under a second in a release build, and
+//! `run_all` spreads a batch of runs over every core.
+
+use anyhow::Result;
+use mbot_core::{MBotBrain, PersonalityConfig, ReflexMode};
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::sim::{Scene, SensorNoise, SimWorld};
+
+/// One simulated session
+#[derive(Clone, Debug)]
+pub struct RunSpec {
+    /// Label for the scene in the results
+    pub scene_name: String,
+    pub scene: Scene,
+    pub personality_id: String,
+    pub personality: PersonalityConfig,
+    /// Seeds both the brain and the scene's sensor noise
+    pub seed: u64,
+    pub duration: Duration,
+    /// Control loop rate in Hz
+    pub freq: u32,
+    /// Spacing of the energy curve's samples
+    pub sample_every: Duration,
+}
+
+/// How the robot behaved over one run
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RunMetrics {
+    pub scene: String,
+    pub personality: String,
+    pub seed: u64,
+    pub seconds: f64,
+    /// Simulated seconds spent in each reflex mode
+    pub calm_s: f64,
+    pub active_s: f64,
+    pub spike_s: f64,
+    pub protect_s: f64,
+    pub collisions: u64,
+    /// Ground truth, not the brain's odometry
+    pub distance_cm: f64,
+    /// Energy at the start and after every sample interval
+    pub energy: Vec<f32>,
+}
+
+/// Run the brain through one simulated session
+pub fn run(spec: &RunSpec) -> RunMetrics {
+    let mut scene = spec.scene.clone();
+    scene.noise.seed = spec.seed;
+    let mut noise = SensorNoise::new(scene.noise.clone());
+    let mut world = SimWorld::with_scene(scene);
+    let mut brain = MBotBrain::seeded(spec.personality, spec.seed);
+
+    let tick_us = 1_000_000 / spec.freq.max(1) as u64;
+    let ticks = spec.duration.as_micros() as u64 / tick_us;
+    let sample_ticks = (spec.sample_every.as_micros() as u64 / tick_us).max(1);
+
+    let mut metrics = RunMetrics {
+        scene: spec.scene_name.clone(),
+        personality: spec.personality_id.clone(),
+        seed: spec.seed,
+        seconds: (ticks * tick_us) as f64 / 1e6,
+        calm_s: 0.0,
+        active_s: 0.0,
+        spike_s: 0.0,
+        protect_s: 0.0,
+        collisions: 0,
+        distance_cm: 0.0,
+        energy: Vec::new(),
+    };
+    let mut energy = brain.snapshot().energy;
+    metrics.energy.push(energy);
+    let tick_s = tick_us as f64 / 1e6;
+    let mut latest = None;
+    let mut last_pose = world.pose();
+
+    for tick in 1..=ticks {
+        let now = Duration::from_micros(tick * tick_us);
+        let clean = world.sensors(now);
+        if let Some(frame) = noise.apply(&clean) {
+            latest = Some(frame);
+        }
+
+        // Until the first frame arrives the brain waits, as it would for a link
+        if let Some(reading) = &latest {
+            let mut reading = reading.clone();
+            reading.timestamp_us = clean.timestamp_us;
+            let (state, cmd) = brain.tick(&reading);
+            world.set_motors(cmd.left, cmd.right, now);
+            energy = state.energy;
+
+            *match state.reflex {
+                ReflexMode::Calm => &mut metrics.calm_s,
+                ReflexMode::Active => &mut metrics.active_s,
+                ReflexMode::Spike => &mut metrics.spike_s,
+                ReflexMode::Protect => &mut metrics.protect_s,
+            } += tick_s;
+        }
+
+        let pose = world.pose();
+        metrics.distance_cm += ((pose.x - last_pose.x) as f64).hypot((pose.y - last_pose.y) as f64);
+        last_pose = pose;
+
+        if tick % sample_ticks == 0 {
+            metrics.energy.push(energy);
+        }
+    }
+
+    metrics.collisions = world.collisions();
+    metrics
+}
+
+/// Run every spec on up to `jobs` threads, calling `done` as each finishes;
+/// results come back in the order of `specs`
+pub fn run_all(
+    specs: &[RunSpec],
+    jobs: usize,
+    done: impl Fn(&RunMetrics) + Sync,
+) -> Vec<RunMetrics> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(vec![None; specs.len()]);
+
+    std::thread::scope(|s| {
+        for _ in 0..jobs.clamp(1, specs.len().max(1)) {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(spec) = specs.get(i) else { break };
+                let metrics = run(spec);
+                done(&metrics);
+                results.lock().unwrap()[i] = Some(metrics);
+            });
+        }
+    });
+
+    results.into_inner().unwrap().into_iter().flatten().collect()
+}
+
+/// Results as a JSON array
+pub fn to_json(results: &[RunMetrics]) -> Result<String> {
+    Ok(serde_json::to_string_pretty(results)?)
+}
+
+/// Results as CSV, one row per run with the energy curve spread over
+/// `energy_<seconds>s` columns
+pub fn to_csv(results: &[RunMetrics], sample_every: Duration) -> String {
+    let samples = results.iter().map(|m| m.energy.len()).max().unwrap_or(0);
+    let mut out = String::from(
+        "scene,personality,seed,seconds,calm_s,active_s,spike_s,protect_s,collisions,distance_cm",
+    );
+    for i in 0..samples {
+        let _ = write!(out, ",energy_{}s", sample_every.as_secs_f64() * i as f64);
+    }
+    out.push('\n');
+
+    for m in results {
+        let _ = write!(
+            out,
+            "{},{},{},{},{:.2},{:.2},{:.2},{:.2},{},{:.1}",
+            csv_field(&m.scene),
+            csv_field(&m.personality),
+            m.seed,
+            m.seconds,
+            m.calm_s,
+            m.active_s,
+            m.spike_s,
+            m.protect_s,
+            m.collisions,
+            m.distance_cm
+        );
+        for i in 0..samples {
+            let _ = match m.energy.get(i) {
+                Some(energy) => write!(out, ",{:.4}", energy),
+                None => write!(out, ","),
+            };
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Quote a CSV field if it needs it
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mbot_core::PRESETS;
+
+    fn spec(personality: usize, seed: u64) -> RunSpec {
+        RunSpec {
+            scene_name: "Living room".to_string(),
+            scene: Scene::default(),
+            personality_id: PRESETS[personality].id.to_string(),
+            personality: PRESETS[personality].config,
+            seed,
+            duration: Duration::from_secs(60),
+            freq: 20,
+            sample_every: Duration::from_secs(10),
+        }
+    }
+
+    #[test]
+    fn test_run_accounts_for_every_tick() {
+        let m = run(&spec(0, 1));
+        assert_eq!(m.seconds, 60.0);
+        let in_modes = m.calm_s + m.active_s + m.spike_s + m.protect_s;
+        assert!((in_modes - 60.0).abs() < 1e-6, "{}", in_modes);
+        assert!(m.distance_cm > 0.0);
+        assert_eq!(m.energy.len(), 7);
+        assert!(m.energy.iter().all(|e| (0.0..=1.0).contains(e)));
+    }
+
+    #[test]
+    fn test_runs_are_reproducible() {
+        assert_eq!(run(&spec(1, 5)), run(&spec(1, 5)));
+        assert_ne!(run(&spec(1, 5)), run(&spec(1, 6)));
+    }
+
+    #[test]
+    fn test_run_all_keeps_spec_order() {
+        let specs: Vec<RunSpec> = (0..6).map(|i| spec(i % PRESETS.len(), i as u64)).collect();
+        let finished = AtomicUsize::new(0);
+        let results = run_all(&specs, 3, |_| {
+            finished.fetch_add(1, Ordering::Relaxed);
+        });
+        assert_eq!(finished.into_inner(), 6);
+        assert_eq!(results.len(), 6);
+        for (spec, m) in specs.iter().zip(&results) {
+            assert_eq!(*m, run(spec));
+        }
+    }
+
+    #[test]
+    fn test_csv_has_a_column_per_energy_sample() {
+        let mut room = run(&spec(0, 1));
+        room.scene = "Room, big".to_string();
+        let csv = to_csv(&[room], Duration::from_secs(10));
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("energy_50s,energy_60s"));
+        assert!(lines[1].starts_with("\"Room, big\",default,1,60,"));
+        assert_eq!(lines[1].matches(',').count(), lines[0].matches(',').count() + 1);
+    }
+}
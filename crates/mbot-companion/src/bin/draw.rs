@@ -4,11 +4,44 @@
 //! its tension/coherence state from the RuVector nervous system.
 
 use anyhow::Result;
-use mbot_core::{MBotBrain, MBotSensors, ReflexMode};
+use clap::Parser;
+use mbot_companion::profile;
+use mbot_companion::transport::MBotTransport;
+use mbot_core::{
+    rng, MBotBrain, MBotSensors, MotorCommand, ReflexMode, RobotProfile, PEN_DOWN, PEN_UP,
+};
 use std::f32::consts::PI;
+use std::path::PathBuf;
 use std::time::{Duration, Instant};
 use tokio::time::sleep;
 
+#[derive(Parser, Debug)]
+#[command(name = "mbot-draw")]
+#[command(about = "mBot2 draws emotional art", long_about = None)]
+struct Args {
+    /// Profile file with personality and calibration (.json or binary)
+    #[arg(long)]
+    profile: Option<PathBuf>,
+
+    /// Robot link URI (sim://, serial:///dev/ttyUSB0, ble://, tcp://host:port, replay://); without one
+    /// the drawing is only previewed on screen
+    #[arg(long)]
+    link: Option<String>,
+
+    /// Seed for the brain's random choices; the same seed draws the same preview
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+/// The saved robot, or a fresh default one
+fn load_brain(args: &Args) -> Result<MBotBrain> {
+    let robot = match &args.profile {
+        Some(path) => profile::load(path)?,
+        None => RobotProfile::default(),
+    };
+    Ok(MBotBrain::with_profile_seeded(&robot, args.seed.unwrap_or(rng::DEFAULT_SEED)))
+}
+
 /// Spirograph parameters - modified by emotional state
 struct SpirographParams {
     outer_radius: f32,
@@ -60,6 +93,7 @@ impl SpirographParams {
 
 struct EmotionalDrawer {
     brain: MBotBrain,
+    link: Option<MBotTransport>,
     center: (f32, f32),
     current_pos: (f32, f32),
     pen_down: bool,
@@ -67,9 +101,10 @@ struct EmotionalDrawer {
 }
 
 impl EmotionalDrawer {
-    fn new(center: (f32, f32)) -> Self {
+    fn new(center: (f32, f32), brain: MBotBrain, link: Option<MBotTransport>) -> Self {
         Self {
-            brain: MBotBrain::new(),
+            brain,
+            link,
             center,
             current_pos: center,
             pen_down: false,
@@ -89,11 +124,22 @@ impl EmotionalDrawer {
         self.set_pen(true).await?;
 
         while start.elapsed().as_secs() < duration_secs as u64 {
-            // Simulate sensor input (in real use, this comes from hardware)
-            let sensors = self.simulate_sensors();
+            // Real sensors when a robot is connected, made-up ones otherwise
+            let sensors = match &mut self.link {
+                Some(link) => match link.read_for_brain().await? {
+                    Some(sensors) => sensors,
+                    // Still waiting for the first encoder counts
+                    None => {
+                        sleep(Duration::from_millis(20)).await;
+                        continue;
+                    }
+                },
+                None => self.simulate_sensors(),
+            };
 
             // Process through brain
-            let (state, _cmd) = self.brain.tick(&sensors);
+            let (state, cmd) = self.brain.tick(&sensors);
+            self.send(&cmd).await?;
 
             // Get spirograph parameters based on emotional state
             let params = SpirographParams::from_reflex(
@@ -114,7 +160,7 @@ impl EmotionalDrawer {
             t += 0.05 * params.speed;
 
             // Print status periodically
-            if (t * 100.0) as u32 % 100 == 0 {
+            if ((t * 100.0) as u32).is_multiple_of(100) {
                 let mode_icon = match state.reflex {
                     ReflexMode::Calm => "😌",
                     ReflexMode::Active => "🔍",
@@ -132,7 +178,7 @@ impl EmotionalDrawer {
             }
 
             // Occasional pen lift for dramatic effect (in Spike mode)
-            if state.reflex == ReflexMode::Spike && rand::random::<f32>() < 0.02 {
+            if state.reflex == ReflexMode::Spike && self.brain.rng_mut().chance(0.02) {
                 self.set_pen(false).await?;
                 sleep(Duration::from_millis(100)).await;
                 self.set_pen(true).await?;
@@ -193,7 +239,7 @@ impl EmotionalDrawer {
             ultrasonic_cm: 50.0 + wave1 * 30.0 - event * 40.0,
             encoder_left: (tick * 3) as i32,
             encoder_right: (tick * 3) as i32,
-            gyro_z: wave2 * 20.0,
+            gyro_z: 0.0, // Both wheels turn together: no rotation
             accel: [wave1 * 2.0, wave2 * 1.5, 9.8],
             sound_level: 0.1 + (wave1 * 0.2).abs(),
             light_level: 0.5 + wave2 * 0.2,
@@ -211,10 +257,31 @@ impl EmotionalDrawer {
     async fn set_pen(&mut self, down: bool) -> Result<()> {
         self.pen_down = down;
         self.brain.set_pen(down);
+        self.send(&MotorCommand {
+            pen_angle: if down { PEN_DOWN } else { PEN_UP },
+            ..Default::default()
+        })
+        .await?;
         sleep(Duration::from_millis(50)).await;
         Ok(())
     }
 
+    /// Send a command to the robot, if one is connected
+    async fn send(&mut self, cmd: &MotorCommand) -> Result<()> {
+        if let Some(link) = &mut self.link {
+            link.send_command(cmd).await?;
+        }
+        Ok(())
+    }
+
+    /// Stop the robot and disconnect
+    async fn close(&mut self) -> Result<()> {
+        if let Some(link) = &mut self.link {
+            link.close().await?;
+        }
+        Ok(())
+    }
+
     fn print_ascii_preview(&self) {
         if self.path.is_empty() {
             return;
@@ -249,6 +316,8 @@ impl EmotionalDrawer {
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = Args::parse();
+
     println!("╔════════════════════════════════════════════════════════════╗");
     println!("║        🎨 mBot2 Emotional Art with RuVector AI 🎨          ║");
     println!("╠════════════════════════════════════════════════════════════╣");
@@ -261,10 +330,16 @@ async fn main() -> Result<()> {
     println!("║  🛡️  Protect = Small, tight defensive circles              ║");
     println!("╚════════════════════════════════════════════════════════════╝\n");
 
-    let mut drawer = EmotionalDrawer::new((0.0, 0.0));
+    let link = match &args.link {
+        Some(uri) => Some(MBotTransport::connect(uri).await?),
+        None => None,
+    };
+    let mut drawer = EmotionalDrawer::new((0.0, 0.0), load_brain(&args)?, link);
 
     // Draw for 30 seconds
-    drawer.draw_emotional_art(30).await?;
+    let result = drawer.draw_emotional_art(30).await;
+    drawer.close().await?;
+    result?;
 
     println!("\n🖼️  Artwork complete! Remove paper and admire your creation.");
 
@@ -0,0 +1,61 @@
+//! mBot2 emulator: a simulated robot speaking the Makeblock protocol over TCP
+//!
+//! Usage:
+//!   mbot-emulator                        # Listen on 127.0.0.1:7000
+//!   mbot-emulator --listen 0.0.0.0:7000  # Reachable from other machines
+//!   mbot-emulator --scene scenes/obstacle_course.json
+//!
+//! Then point any binary at it:
+//!   mbot-companion --link tcp://localhost:7000
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use mbot_companion::emulator;
+use mbot_companion::sim::Scene;
+use std::path::PathBuf;
+use tokio::net::TcpListener;
+use tracing::{info, Level};
+
+#[derive(Parser, Debug)]
+#[command(name = "mbot-emulator")]
+#[command(about = "Simulated mBot2 for the companion's tcp:// link", long_about = None)]
+struct Args {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:7000")]
+    listen: String,
+
+    /// Scene file to put the robot in, instead of the default room
+    #[arg(long)]
+    scene: Option<PathBuf>,
+
+    /// Verbose output
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let log_level = if args.verbose { Level::DEBUG } else { Level::INFO };
+    tracing_subscriber::fmt().with_max_level(log_level).init();
+
+    let scene = match &args.scene {
+        Some(path) => Scene::load(path)?,
+        None => Scene::default(),
+    };
+
+    let listener = TcpListener::bind(&args.listen)
+        .await
+        .with_context(|| format!("Failed to listen on {}", args.listen))?;
+    info!("🤖 mBot2 emulator listening on tcp://{}", listener.local_addr()?);
+    if !scene.name.is_empty() {
+        info!("🗺️  Simulating {}", scene.name);
+    }
+
+    tokio::select! {
+        result = emulator::serve(listener, scene) => result?,
+        _ = tokio::signal::ctrl_c() => info!("👋 Shutting down..."),
+    }
+    Ok(())
+}
@@ -0,0 +1,159 @@
+//! Batch simulation: many headless runs of the brain, faster than real time
+//!
+//! Usage:
+//!   mbot-sim                                   # Every personality, 8 seeds, 10 minutes each
+//!   mbot-sim --scene scenes/patrol.json --scene scenes/obstacle_course.json
+//!   mbot-sim --personality nervous-nellie --seeds 32 --minutes 30
+//!   mbot-sim --out results.csv                 # CSV, or JSON for any other extension
+//!
+//! Run it before and after a brain change and compare the results.
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use mbot_companion::batch::{self, RunSpec};
+use mbot_companion::sim::Scene;
+use mbot_core::{PersonalityPreset, PRESETS};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tracing::{info, Level};
+
+#[derive(Parser, Debug)]
+#[command(name = "mbot-sim")]
+#[command(about = "Simulate the brain headless across scenes, personalities and seeds", long_about = None)]
+struct Args {
+    /// Scene file to run in; repeat for several (default: the living room)
+    #[arg(long)]
+    scene: Vec<PathBuf>,
+
+    /// Personality preset to run; repeat for several (default: all of them)
+    #[arg(long)]
+    personality: Vec<String>,
+
+    /// Seeds per scene and personality, counting from 0
+    #[arg(long, default_value = "8")]
+    seeds: u64,
+
+    /// Simulated minutes per run
+    #[arg(long, default_value = "10")]
+    minutes: f64,
+
+    /// Control loop frequency in Hz
+    #[arg(long, default_value = "20")]
+    freq: u32,
+
+    /// Seconds between samples of the energy curve
+    #[arg(long, default_value = "10")]
+    sample_every: f64,
+
+    /// Runs at once (default: one per CPU)
+    #[arg(long)]
+    jobs: Option<usize>,
+
+    /// Write results here, as CSV for .csv and JSON otherwise (default: JSON on stdout)
+    #[arg(long)]
+    out: Option<PathBuf>,
+
+    /// Verbose output
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    // Results may go to stdout, so progress goes to stderr
+    let log_level = if args.verbose { Level::DEBUG } else { Level::INFO };
+    tracing_subscriber::fmt()
+        .with_max_level(log_level)
+        .with_writer(std::io::stderr)
+        .init();
+
+    let scenes = if args.scene.is_empty() {
+        vec![(Scene::default().name, Scene::default())]
+    } else {
+        let mut scenes = Vec::new();
+        for path in &args.scene {
+            let scene = Scene::load(path)?;
+            let name = if scene.name.is_empty() {
+                path.display().to_string()
+            } else {
+                scene.name.clone()
+            };
+            scenes.push((name, scene));
+        }
+        scenes
+    };
+
+    let personalities: Vec<&PersonalityPreset> = if args.personality.is_empty() {
+        PRESETS.iter().collect()
+    } else {
+        let mut found = Vec::new();
+        for name in &args.personality {
+            let Some(preset) = PersonalityPreset::find(name) else {
+                let known: Vec<&str> = PRESETS.iter().map(|p| p.id).collect();
+                anyhow::bail!("Unknown personality '{}'. Available: {}", name, known.join(", "));
+            };
+            found.push(preset);
+        }
+        found
+    };
+
+    let duration = Duration::try_from_secs_f64(args.minutes * 60.0).context("Invalid --minutes")?;
+    let sample_every = Duration::try_from_secs_f64(args.sample_every).context("Invalid --sample-every")?;
+    let mut specs = Vec::new();
+    for (scene_name, scene) in &scenes {
+        for preset in &personalities {
+            for seed in 0..args.seeds {
+                specs.push(RunSpec {
+                    scene_name: scene_name.clone(),
+                    scene: scene.clone(),
+                    personality_id: preset.id.to_string(),
+                    personality: preset.config,
+                    seed,
+                    duration,
+                    freq: args.freq,
+                    sample_every,
+                });
+            }
+        }
+    }
+
+    let jobs = args
+        .jobs
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+    info!(
+        "🧪 {} runs of {} simulated minutes on {} threads",
+        specs.len(),
+        args.minutes,
+        jobs
+    );
+    let started = Instant::now();
+    let results = batch::run_all(&specs, jobs, |m| {
+        info!(
+            "{} / {} / seed {}: {} collisions, {:.0} cm",
+            m.scene, m.personality, m.seed, m.collisions, m.distance_cm
+        );
+    });
+    let simulated = duration * specs.len() as u32;
+    info!(
+        "✅ Simulated {:.0} minutes in {:.1}s",
+        simulated.as_secs_f64() / 60.0,
+        started.elapsed().as_secs_f64()
+    );
+
+    match &args.out {
+        Some(path) => {
+            let is_csv = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+            let text = if is_csv {
+                batch::to_csv(&results, sample_every)
+            } else {
+                batch::to_json(&results)?
+            };
+            std::fs::write(path, text)
+                .with_context(|| format!("Failed to write results: {}", path.display()))?;
+            info!("💾 Saved results to {}", path.display());
+        }
+        None => println!("{}", batch::to_json(&results)?),
+    }
+    Ok(())
+}
@@ -2,17 +2,65 @@
 //!
 //! The robot draws on paper and plays against you.
 //! It uses SONA learning to improve its strategy over time.
-
-use anyhow::Result;
-use mbot_core::{circle_points, drive_to_point, x_points, MBotBrain, MBotSensors, MotorCommand};
+//!
+//! Start the robot just outside the board's A1 corner, facing from column A
+//! towards C with rows 2 and 3 on its left: strokes are steered from the
+//! brain's pose estimate, which starts there. The estimate is only as good
+//! as the odometry calibration in `--profile`, so calibrate first.
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use mbot_companion::profile;
+use mbot_companion::transport::MBotTransport;
+use mbot_core::{
+    circle_points, drive_to_point, rng, x_points, Calibration, MBotBrain, MotorCommand,
+    RobotProfile, PEN_DOWN, PEN_UP,
+};
 use std::io::{self, Write};
+use std::path::PathBuf;
 use std::time::Duration;
-use tokio::time::sleep;
+use tokio::time::{sleep, Instant};
 
 // Board dimensions (in cm from origin)
 const CELL_SIZE: f32 = 15.0;
 const BOARD_OFFSET: (f32, f32) = (5.0, 5.0);
 
+/// A stroke ends this close to its target (where `drive_to_point` stops)
+const ARRIVE_CM: f32 = 1.0;
+/// Steering loop period
+const CONTROL_PERIOD: Duration = Duration::from_millis(20);
+/// Longest a single stroke may take: the longest one, a 45cm grid line at
+/// drawing speed, takes under 10s in the simulator
+const STROKE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Parser, Debug)]
+#[command(name = "mbot-tictactoe")]
+#[command(about = "mBot2 plays tic-tac-toe with a pen", long_about = None)]
+struct Args {
+    /// Profile file with personality and calibration (.json or binary)
+    #[arg(long)]
+    profile: Option<PathBuf>,
+
+    /// Robot link URI (sim://, serial:///dev/ttyUSB0, ble://, tcp://host:port, replay://); without one
+    /// the game is only played on screen
+    #[arg(long)]
+    link: Option<String>,
+
+    /// Seed for the brain's random choices; the same seed and the same sensor
+    /// frames always give the same run
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+/// The saved robot, or a fresh default one
+fn load_brain(args: &Args) -> Result<MBotBrain> {
+    let robot = match &args.profile {
+        Some(path) => profile::load(path)?,
+        None => RobotProfile::default(),
+    };
+    Ok(MBotBrain::with_profile_seeded(&robot, args.seed.unwrap_or(rng::DEFAULT_SEED)))
+}
+
 #[derive(Clone, Copy, PartialEq, Debug)]
 enum Cell {
     Empty,
@@ -23,7 +71,7 @@ enum Cell {
 struct TicTacToeGame {
     board: [[Cell; 3]; 3],
     brain: MBotBrain,
-    current_pos: (f32, f32),
+    link: Option<MBotTransport>,
     games_played: u32,
     robot_wins: u32,
     human_wins: u32,
@@ -31,11 +79,11 @@ struct TicTacToeGame {
 }
 
 impl TicTacToeGame {
-    fn new() -> Self {
+    fn new(brain: MBotBrain, link: Option<MBotTransport>) -> Self {
         Self {
             board: [[Cell::Empty; 3]; 3],
-            brain: MBotBrain::new(),
-            current_pos: (0.0, 0.0),
+            brain,
+            link,
             games_played: 0,
             robot_wins: 0,
             human_wins: 0,
@@ -165,6 +213,7 @@ impl TicTacToeGame {
         self.check_winner_board(&self.board)
     }
 
+    #[allow(clippy::needless_range_loop)]
     fn check_winner_board(&self, board: &[[Cell; 3]; 3]) -> Option<Cell> {
         // Check rows
         for row in 0..3 {
@@ -275,50 +324,97 @@ impl TicTacToeGame {
         Ok(())
     }
 
+    /// Drive to (x, y), steering from the brain's pose estimate.
+    ///
+    /// Each step reads the sensors and ticks the brain, so the pose comes
+    /// from the encoders and gyro through the profile's calibration. The
+    /// brain's own motor command is ignored: the stroke does the steering.
+    /// A stroke that hasn't arrived within `STROKE_TIMEOUT` stops the robot
+    /// and fails. Without a robot there is nothing to move.
     async fn drive_to(&mut self, x: f32, y: f32, drawing: bool) -> Result<()> {
+        let Some(link) = &mut self.link else {
+            return Ok(());
+        };
         let speed = if drawing { 20.0 } else { 50.0 };
+        let pen_angle = if drawing { PEN_DOWN } else { PEN_UP };
+        let deadline = Instant::now() + STROKE_TIMEOUT;
 
-        // Simulate driving (in real implementation, this would send commands)
-        while (self.current_pos.0 - x).abs() > 0.5 || (self.current_pos.1 - y).abs() > 0.5 {
-            let (left, right) =
-                drive_to_point(self.current_pos, self.brain.heading(), (x, y), speed);
-
-            // Update simulated position
-            let dx = (left + right) as f32 / 200.0;
-            let dtheta = (right - left) as f32 / 500.0;
-
-            self.current_pos.0 += dx * self.brain.heading().cos();
-            self.current_pos.1 += dx * self.brain.heading().sin();
+        loop {
+            if Instant::now() >= deadline {
+                // Lost, stuck or blocked: don't keep driving blind
+                link.send_command(&MotorCommand {
+                    pen_angle: PEN_UP,
+                    ..Default::default()
+                })
+                .await?;
+                let (px, py) = self.brain.position();
+                bail!(
+                    "Gave up driving to ({:.1}, {:.1}) after {:?}; stopped at ({:.1}, {:.1})",
+                    x, y, STROKE_TIMEOUT, px, py
+                );
+            }
+            if let Some(sensors) = link.read_for_brain().await? {
+                self.brain.tick(&sensors);
+            }
 
-            // In real implementation: send motor command
-            let _cmd = MotorCommand {
+            let (px, py) = self.brain.position();
+            if (x - px).hypot(y - py) < ARRIVE_CM {
+                break;
+            }
+            let (left, right) = drive_to_point((px, py), self.brain.heading(), (x, y), speed);
+            link.send_command(&MotorCommand {
                 left,
                 right,
-                pen_angle: if self.brain.position() != (0.0, 0.0) { 90 } else { 45 },
+                pen_angle,
                 ..Default::default()
-            };
+            })
+            .await?;
 
-            sleep(Duration::from_millis(20)).await;
+            sleep(CONTROL_PERIOD).await;
         }
 
-        self.current_pos = (x, y);
-        Ok(())
+        self.stop(drawing).await
     }
 
     async fn pen_up(&mut self) -> Result<()> {
         self.brain.set_pen(false);
-        // In real implementation: send servo command
+        self.stop(false).await?;
         sleep(Duration::from_millis(100)).await;
         Ok(())
     }
 
     async fn pen_down(&mut self) -> Result<()> {
         self.brain.set_pen(true);
-        // In real implementation: send servo command
+        self.stop(true).await?;
         sleep(Duration::from_millis(100)).await;
         Ok(())
     }
 
+    /// Stop the wheels, holding the pen up or down
+    async fn stop(&mut self, pen_down: bool) -> Result<()> {
+        self.send(MotorCommand {
+            pen_angle: if pen_down { PEN_DOWN } else { PEN_UP },
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Send a command to the robot, if one is connected
+    async fn send(&mut self, cmd: MotorCommand) -> Result<()> {
+        if let Some(link) = &mut self.link {
+            link.send_command(&cmd).await?;
+        }
+        Ok(())
+    }
+
+    /// Stop the robot and disconnect
+    async fn close(&mut self) -> Result<()> {
+        if let Some(link) = &mut self.link {
+            link.close().await?;
+        }
+        Ok(())
+    }
+
     async fn victory_dance(&mut self) -> Result<()> {
         println!("🎉 Robot does a victory spin!");
         // Spin 360 degrees
@@ -337,6 +433,8 @@ impl TicTacToeGame {
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = Args::parse();
+
     println!("╔════════════════════════════════════════════════════════════╗");
     println!("║          🤖 mBot2 TIC-TAC-TOE with RuVector AI 🤖          ║");
     println!("╠════════════════════════════════════════════════════════════╣");
@@ -345,8 +443,30 @@ async fn main() -> Result<()> {
     println!("║  The robot will draw on paper!                             ║");
     println!("╚════════════════════════════════════════════════════════════╝");
 
-    let mut game = TicTacToeGame::new();
+    let link = match &args.link {
+        Some(uri) => Some(MBotTransport::connect(uri).await?),
+        None => None,
+    };
+    let brain = load_brain(&args)?;
+    if link.is_some() {
+        // Every stroke is measured with these, so they set the board's size
+        let c = brain.calibration();
+        println!("📐 Odometry: {:.2} ticks/cm, {:.2} cm wheel base", c.ticks_per_cm, c.wheel_base_cm);
+        let simulated = args.link.as_deref().is_some_and(|uri| uri.starts_with("sim://"));
+        if *c == Calibration::DEFAULT && !simulated {
+            println!("⚠️  Uncalibrated: run `mbot-companion --link <uri> --profile <file> calibrate` and pass that --profile here");
+        }
+    }
+    let mut game = TicTacToeGame::new(brain, link);
+
+    // Stop the robot whatever happened, a failed stroke included
+    let result = play(&mut game).await;
+    game.close().await?;
+    result
+}
 
+/// Games until the human quits or declines another
+async fn play(game: &mut TicTacToeGame) -> Result<()> {
     loop {
         game.reset_board();
         game.games_played += 1;
@@ -433,9 +553,7 @@ async fn main() -> Result<()> {
         io::stdin().read_line(&mut input).ok();
         if !input.trim().eq_ignore_ascii_case("y") {
             println!("\n👋 Thanks for playing!");
-            break;
+            return Ok(());
         }
     }
-
-    Ok(())
 }
@@ -0,0 +1,513 @@
+//! Makeblock frames over a Bluetooth LE UART service
+//!
+//! The CyberPi exposes a serial-like GATT service: frames are written to
+//! one characteristic and answers arrive as notifications on another. This
+//! module holds everything about that which doesn't need a radio - finding
+//! the UART characteristics, picking the robot, chunking writes to the MTU,
+//! and polling sensors - behind the `BlePort` trait, so it can be tested
+//! with a fake peripheral. The real port lives in `transport`.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use mbot_core::{MBotSensors, MotorCommand};
+use std::time::{Duration, Instant};
+
+use crate::poller::{Freshness, SensorPoller};
+use crate::protocol;
+use crate::transport::{closed, command_bytes, LinkHealth, RobotLink};
+
+/// ATT MTU every BLE device supports; bigger ones are negotiated
+pub const DEFAULT_ATT_MTU: usize = 23;
+/// Bytes of each ATT packet taken by the opcode and handle
+const ATT_OVERHEAD: usize = 3;
+
+/// Time allowed for one round of sensor reads - inside the 50ms control period
+const POLL_BUDGET: Duration = Duration::from_millis(40);
+
+/// What a characteristic supports
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CharProps {
+    pub write: bool,
+    pub write_without_response: bool,
+    pub notify: bool,
+}
+
+/// A characteristic found during service discovery
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GattCharacteristic {
+    pub service: u128,
+    pub uuid: u128,
+    pub props: CharProps,
+}
+
+/// A known serial-over-BLE service layout
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UartService {
+    pub name: &'static str,
+    pub service: u128,
+    /// Characteristic the companion writes frames to
+    pub write: u128,
+    /// Characteristic the robot notifies answers on
+    pub notify: u128,
+}
+
+/// UART services to look for, best first
+pub const UART_SERVICES: [UartService; 2] = [
+    // Makeblock's own BLE modules and the CyberPi
+    UartService {
+        name: "Makeblock",
+        service: 0x0000ffe1_0000_1000_8000_00805f9b34fb,
+        write: 0x0000ffe3_0000_1000_8000_00805f9b34fb,
+        notify: 0x0000ffe2_0000_1000_8000_00805f9b34fb,
+    },
+    // Nordic UART, used by some firmware builds and bridges
+    UartService {
+        name: "Nordic UART",
+        service: 0x6e400001_b5a3_f393_e0a9_e50e24dcca9e,
+        write: 0x6e400002_b5a3_f393_e0a9_e50e24dcca9e,
+        notify: 0x6e400003_b5a3_f393_e0a9_e50e24dcca9e,
+    },
+];
+
+/// The characteristic pair to talk through
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UartCharacteristics {
+    pub service: &'static str,
+    pub write: GattCharacteristic,
+    pub notify: GattCharacteristic,
+}
+
+/// Pick the UART characteristics from everything a device offers
+pub fn find_uart(characteristics: &[GattCharacteristic]) -> Option<UartCharacteristics> {
+    UART_SERVICES.iter().find_map(|known| {
+        let find = |uuid: u128| {
+            characteristics
+                .iter()
+                .find(|c| c.service == known.service && c.uuid == uuid)
+                .copied()
+        };
+        let write = find(known.write).filter(|c| c.props.write || c.props.write_without_response)?;
+        let notify = find(known.notify).filter(|c| c.props.notify)?;
+        Some(UartCharacteristics {
+            service: known.name,
+            write,
+            notify,
+        })
+    })
+}
+
+/// How to write a packet
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WriteMode {
+    /// Wait for the robot to confirm
+    WithResponse,
+    /// Fire and forget - lower latency, may be dropped
+    WithoutResponse,
+}
+
+impl WriteMode {
+    /// Fire-and-forget where possible, unless the write must not be lost
+    pub fn select(props: CharProps, reliable: bool) -> Self {
+        if reliable && props.write {
+            WriteMode::WithResponse
+        } else if props.write_without_response {
+            WriteMode::WithoutResponse
+        } else {
+            WriteMode::WithResponse
+        }
+    }
+}
+
+/// Split `bytes` into packets that fit an ATT MTU
+pub fn chunks(bytes: &[u8], mtu: usize) -> impl Iterator<Item = &[u8]> {
+    let payload = mtu.max(DEFAULT_ATT_MTU) - ATT_OVERHEAD;
+    bytes.chunks(payload)
+}
+
+/// Which robot a `ble://` URI asks for
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BleTarget {
+    /// The first thing that looks like an mBot2
+    Any,
+    /// A device whose name contains this
+    Name(String),
+    /// A device by address, `AA:BB:CC:DD:EE:FF`
+    Address(String),
+}
+
+impl BleTarget {
+    /// Read the part of a `ble://` URI after the scheme
+    pub fn parse(target: &str) -> Self {
+        let is_address = target.split([':', '-']).count() == 6
+            && target
+                .split([':', '-'])
+                .all(|part| part.len() == 2 && part.chars().all(|c| c.is_ascii_hexdigit()));
+        if target.is_empty() {
+            BleTarget::Any
+        } else if is_address {
+            BleTarget::Address(target.replace('-', ":").to_ascii_uppercase())
+        } else {
+            BleTarget::Name(target.to_string())
+        }
+    }
+
+    /// Whether a device seen while scanning is the one wanted
+    pub fn matches(&self, name: Option<&str>, address: &str) -> bool {
+        match self {
+            BleTarget::Any => name.is_some_and(|n| {
+                n.contains("Makeblock") || n.contains("CyberPi") || n.contains("mBot")
+            }),
+            BleTarget::Name(wanted) => name.is_some_and(|n| n.contains(wanted.as_str())),
+            BleTarget::Address(wanted) => address.eq_ignore_ascii_case(wanted),
+        }
+    }
+}
+
+/// A connected UART characteristic pair
+#[async_trait]
+pub trait BlePort: Send {
+    /// Write one packet of at most `mtu() - 3` bytes
+    async fn write(&mut self, packet: &[u8], mode: WriteMode) -> Result<()>;
+
+    /// The next notification, or None if none arrives within `timeout`
+    async fn notification(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>>;
+
+    /// Negotiated ATT MTU
+    fn mtu(&self) -> usize;
+
+    /// What the write characteristic supports
+    fn write_props(&self) -> CharProps;
+
+    async fn disconnect(&mut self) -> Result<()>;
+}
+
+/// A robot over Bluetooth LE
+pub struct BleLink<P> {
+    port: Option<P>,
+    poller: SensorPoller,
+    freshness: Freshness,
+}
+
+impl<P: BlePort> BleLink<P> {
+    pub fn new(port: P) -> Self {
+        Self {
+            port: Some(port),
+            poller: SensorPoller::new(),
+            freshness: Freshness::default(),
+        }
+    }
+}
+
+/// Write `bytes` in MTU-sized packets
+async fn send<P: BlePort>(port: &mut P, bytes: &[u8], reliable: bool) -> Result<()> {
+    let mode = WriteMode::select(port.write_props(), reliable);
+    let mtu = port.mtu();
+    for packet in chunks(bytes, mtu) {
+        port.write(packet, mode).await?;
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl<P: BlePort> RobotLink for BleLink<P> {
+    async fn read_sensors(&mut self) -> Result<MBotSensors> {
+        let port = self.port.as_mut().ok_or_else(closed)?;
+        let start = Instant::now();
+        let deadline = start + POLL_BUDGET;
+
+        // Same pipelined poll as a serial port, with notifications as the
+        // read side
+        let requests = self.poller.requests(start);
+        send(port, &requests, false).await?;
+        while !self.poller.settled() {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            match port.notification(left).await? {
+                Some(bytes) => self.poller.receive(&bytes, Instant::now()),
+                None => break,
+            }
+        }
+
+        let (sensors, freshness) = self.poller.reading(Instant::now());
+        self.freshness = freshness;
+        Ok(sensors)
+    }
+
+    async fn send_command(&mut self, cmd: &MotorCommand) -> Result<()> {
+        let port = self.port.as_mut().ok_or_else(closed)?;
+        // The next command is 50ms away; a lost one is soon replaced
+        send(port, &command_bytes(cmd), false).await
+    }
+
+    fn health(&self) -> LinkHealth {
+        LinkHealth {
+            connected: self.port.is_some(),
+            freshness: Some(self.freshness.clone()),
+        }
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        if let Some(mut port) = self.port.take() {
+            // The stop must arrive, but a dead link still has to be let go
+            let stopped = send(&mut port, &protocol::motor_cmd(0, 0), true).await;
+            let disconnected = port.disconnect().await;
+            stopped?;
+            disconnected?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+    use crate::poller::SensorField;
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+
+    const WRITE_ONLY: CharProps = CharProps {
+        write: true,
+        write_without_response: false,
+        notify: false,
+    };
+    const BOTH_WRITES: CharProps = CharProps {
+        write: true,
+        write_without_response: true,
+        notify: false,
+    };
+
+    /// A CyberPi in memory: writes go to an emulator, its answers come back
+    /// as MTU-sized notifications
+    struct FakePeripheral {
+        emulator: Emulator,
+        start: Instant,
+        mtu: usize,
+        props: CharProps,
+        notifications: VecDeque<Vec<u8>>,
+        writes: Arc<Mutex<Vec<(usize, WriteMode)>>>,
+        connected: Arc<Mutex<bool>>,
+    }
+
+    impl FakePeripheral {
+        fn new(mtu: usize, props: CharProps) -> Self {
+            Self {
+                emulator: Emulator::new(),
+                start: Instant::now(),
+                mtu,
+                props,
+                notifications: VecDeque::new(),
+                writes: Arc::default(),
+                connected: Arc::new(Mutex::new(true)),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl BlePort for FakePeripheral {
+        async fn write(&mut self, packet: &[u8], mode: WriteMode) -> Result<()> {
+            assert!(packet.len() <= self.mtu - ATT_OVERHEAD, "{} byte packet", packet.len());
+            self.writes.lock().unwrap().push((packet.len(), mode));
+            let reply = self.emulator.receive(packet, self.start.elapsed());
+            self.notifications
+                .extend(chunks(&reply, self.mtu).map(|c| c.to_vec()));
+            Ok(())
+        }
+
+        async fn notification(&mut self, _timeout: Duration) -> Result<Option<Vec<u8>>> {
+            Ok(self.notifications.pop_front())
+        }
+
+        fn mtu(&self) -> usize {
+            self.mtu
+        }
+
+        fn write_props(&self) -> CharProps {
+            self.props
+        }
+
+        async fn disconnect(&mut self) -> Result<()> {
+            *self.connected.lock().unwrap() = false;
+            Ok(())
+        }
+    }
+
+    fn characteristic(service: u128, uuid: u128, props: CharProps) -> GattCharacteristic {
+        GattCharacteristic { service, uuid, props }
+    }
+
+    #[test]
+    fn test_finds_uart_characteristics() {
+        let nordic = UART_SERVICES[1];
+        let notify = CharProps {
+            notify: true,
+            ..Default::default()
+        };
+        let chars = [
+            characteristic(0x1800, 0x2a00, WRITE_ONLY),
+            characteristic(nordic.service, nordic.notify, notify),
+            characteristic(nordic.service, nordic.write, BOTH_WRITES),
+        ];
+        let uart = find_uart(&chars).unwrap();
+        assert_eq!(uart.service, "Nordic UART");
+        assert_eq!(uart.write.uuid, nordic.write);
+        assert_eq!(uart.notify.uuid, nordic.notify);
+
+        // A notify characteristic that can't notify is no use
+        let broken = [
+            characteristic(nordic.service, nordic.notify, WRITE_ONLY),
+            characteristic(nordic.service, nordic.write, BOTH_WRITES),
+        ];
+        assert_eq!(find_uart(&broken), None);
+    }
+
+    #[test]
+    fn test_target_selection() {
+        assert_eq!(BleTarget::parse(""), BleTarget::Any);
+        assert_eq!(
+            BleTarget::parse("aa-bb-cc-dd-ee-0f"),
+            BleTarget::Address("AA:BB:CC:DD:EE:0F".to_string())
+        );
+        assert_eq!(BleTarget::parse("CyberPi-42"), BleTarget::Name("CyberPi-42".to_string()));
+
+        assert!(BleTarget::Any.matches(Some("Makeblock_LE001"), "11:22:33:44:55:66"));
+        assert!(!BleTarget::Any.matches(Some("Headphones"), "11:22:33:44:55:66"));
+        assert!(!BleTarget::Any.matches(None, "11:22:33:44:55:66"));
+
+        let by_name = BleTarget::parse("Pi-42");
+        assert!(by_name.matches(Some("CyberPi-42"), "11:22:33:44:55:66"));
+        assert!(!by_name.matches(Some("CyberPi-7"), "11:22:33:44:55:66"));
+
+        let by_address = BleTarget::parse("11:22:33:44:55:66");
+        assert!(by_address.matches(None, "11:22:33:44:55:66"));
+        assert!(!by_address.matches(Some("CyberPi"), "11:22:33:44:55:67"));
+    }
+
+    #[test]
+    fn test_write_mode_selection() {
+        assert_eq!(WriteMode::select(BOTH_WRITES, false), WriteMode::WithoutResponse);
+        assert_eq!(WriteMode::select(BOTH_WRITES, true), WriteMode::WithResponse);
+        assert_eq!(WriteMode::select(WRITE_ONLY, false), WriteMode::WithResponse);
+        let unacked_only = CharProps {
+            write_without_response: true,
+            ..Default::default()
+        };
+        assert_eq!(WriteMode::select(unacked_only, true), WriteMode::WithoutResponse);
+    }
+
+    #[test]
+    fn test_chunks_fit_mtu() {
+        let bytes: Vec<u8> = (0..100).collect();
+        let sizes: Vec<usize> = chunks(&bytes, DEFAULT_ATT_MTU).map(<[u8]>::len).collect();
+        assert_eq!(sizes, vec![20, 20, 20, 20, 20]);
+        assert_eq!(chunks(&bytes, 247).count(), 1);
+        // A bogus tiny MTU is treated as the minimum
+        assert_eq!(chunks(&bytes, 5).count(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_link_through_fake_peripheral() {
+        let fake = FakePeripheral::new(DEFAULT_ATT_MTU, BOTH_WRITES);
+        let writes = fake.writes.clone();
+        let connected = fake.connected.clone();
+        let mut link = BleLink::new(fake);
+
+        link.send_command(&MotorCommand {
+            left: 60,
+            right: 60,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let first = link.read_sensors().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let second = link.read_sensors().await.unwrap();
+
+        assert!(second.encoder_right > first.encoder_right, "{:?} {:?}", first, second);
+        assert_eq!(second.quad_rgb[2], [200, 200, 200]);
+        let freshness = link.health().freshness.unwrap();
+        assert!(freshness.is_fresh(SensorField::Sound, second.timestamp_us, 50_000));
+        assert!(freshness.stale(second.timestamp_us, 50_000).is_empty());
+
+        // Polls and commands go unacknowledged
+        assert!(writes
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|&(_, mode)| mode == WriteMode::WithoutResponse));
+
+        // ...but the final stop is confirmed
+        link.close().await.unwrap();
+        assert_eq!(writes.lock().unwrap().last().unwrap().1, WriteMode::WithResponse);
+        assert!(!*connected.lock().unwrap());
+        assert!(link.read_sensors().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_close_disconnects_when_stop_fails() {
+        /// Out of range: nothing written gets through
+        struct Gone(FakePeripheral);
+
+        #[async_trait]
+        impl BlePort for Gone {
+            async fn write(&mut self, _packet: &[u8], _mode: WriteMode) -> Result<()> {
+                Err(anyhow::anyhow!("not connected"))
+            }
+            async fn notification(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>> {
+                self.0.notification(timeout).await
+            }
+            fn mtu(&self) -> usize {
+                self.0.mtu()
+            }
+            fn write_props(&self) -> CharProps {
+                self.0.write_props()
+            }
+            async fn disconnect(&mut self) -> Result<()> {
+                self.0.disconnect().await
+            }
+        }
+
+        let fake = FakePeripheral::new(DEFAULT_ATT_MTU, BOTH_WRITES);
+        let connected = fake.connected.clone();
+        let mut link = BleLink::new(Gone(fake));
+        let error = link.close().await.unwrap_err();
+        assert!(format!("{:#}", error).contains("not connected"));
+        assert!(!*connected.lock().unwrap());
+        assert!(!link.health().connected);
+    }
+
+    #[tokio::test]
+    async fn test_silent_sensor_goes_stale() {
+        /// Drops every answer
+        struct Deaf(FakePeripheral);
+
+        #[async_trait]
+        impl BlePort for Deaf {
+            async fn write(&mut self, packet: &[u8], mode: WriteMode) -> Result<()> {
+                self.0.write(packet, mode).await?;
+                self.0.notifications.clear();
+                Ok(())
+            }
+            async fn notification(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>> {
+                self.0.notification(timeout).await
+            }
+            fn mtu(&self) -> usize {
+                self.0.mtu()
+            }
+            fn write_props(&self) -> CharProps {
+                self.0.write_props()
+            }
+            async fn disconnect(&mut self) -> Result<()> {
+                self.0.disconnect().await
+            }
+        }
+
+        let mut link = BleLink::new(Deaf(FakePeripheral::new(DEFAULT_ATT_MTU, WRITE_ONLY)));
+        let sensors = link.read_sensors().await.unwrap();
+        assert_eq!(sensors.distance_cm(), None);
+        let freshness = link.health().freshness.unwrap();
+        assert_eq!(freshness.stale(sensors.timestamp_us, 1_000_000).len(), SensorField::ALL.len());
+    }
+}
@@ -0,0 +1,159 @@
+//! Odometry calibration routine
+//!
+//! Drives a straight line and an in-place spin, asks the user what a ruler
+//! and protractor say actually happened, and turns the encoder counts into
+//! a `Calibration`.
+
+use anyhow::{Context, Result};
+use mbot_core::{Calibration, MotorCommand, PEN_UP};
+use std::time::Duration;
+use tokio::time::{sleep, Instant};
+
+use crate::transport::MBotTransport;
+
+/// Command period while driving
+const DRIVE_TICK: Duration = Duration::from_millis(50);
+/// Time to let the robot coast to a stop before reading the encoders
+const SETTLE: Duration = Duration::from_millis(300);
+
+/// How hard and how long to drive for each measurement
+#[derive(Clone, Copy, Debug)]
+pub struct CalibrationPlan {
+    /// Motor power for both runs (1-100)
+    pub speed: i8,
+    pub straight: Duration,
+    pub spin: Duration,
+}
+
+impl Default for CalibrationPlan {
+    fn default() -> Self {
+        Self {
+            speed: 30,
+            straight: Duration::from_secs(3),
+            spin: Duration::from_secs(2),
+        }
+    }
+}
+
+/// What the user is asked to measure
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Measurement {
+    /// Distance driven in a straight line, cm
+    StraightCm,
+    /// Angle turned on the spot, degrees counter-clockwise
+    SpinDeg,
+}
+
+impl Measurement {
+    pub fn prompt(&self) -> &'static str {
+        match self {
+            Measurement::StraightCm => "How far did the robot drive (cm)? ",
+            Measurement::SpinDeg => "How far did the robot turn (degrees, counter-clockwise)? ",
+        }
+    }
+}
+
+/// Run both calibration drives, starting from `start`'s constants.
+///
+/// `ask` is called after each drive with what to measure.
+pub async fn run(
+    transport: &mut MBotTransport,
+    start: Calibration,
+    plan: CalibrationPlan,
+    mut ask: impl FnMut(Measurement) -> Result<f32>,
+) -> Result<Calibration> {
+    let speed = plan.speed.clamp(1, 100);
+
+    let (left, right) = drive(transport, speed, speed, plan.straight).await?;
+    let distance = ask(Measurement::StraightCm)?;
+    let calibration = start
+        .with_straight_run(left, right, distance)
+        .context("Straight run could not be used")?;
+
+    let (left, right) = drive(transport, -speed, speed, plan.spin).await?;
+    let angle = ask(Measurement::SpinDeg)?;
+    let calibration = calibration
+        .with_spin(left, right, angle)
+        .context("Spin could not be used")?;
+
+    Ok(calibration)
+}
+
+/// Drive with fixed motor power, stop, and return the encoder change
+async fn drive(
+    transport: &mut MBotTransport,
+    left: i8,
+    right: i8,
+    duration: Duration,
+) -> Result<(i32, i32)> {
+    let before = transport.read_sensors().await?;
+
+    let command = MotorCommand {
+        left,
+        right,
+        pen_angle: PEN_UP,
+        ..Default::default()
+    };
+    let deadline = Instant::now() + duration;
+    while Instant::now() < deadline {
+        transport.send_command(&command).await?;
+        sleep(DRIVE_TICK).await;
+    }
+
+    transport
+        .send_command(&MotorCommand {
+            pen_angle: PEN_UP,
+            ..Default::default()
+        })
+        .await?;
+    sleep(SETTLE).await;
+
+    let after = transport.read_sensors().await?;
+    Ok((
+        after.encoder_left.wrapping_sub(before.encoder_left),
+        after.encoder_right.wrapping_sub(before.encoder_right),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_recovers_simulated_constants() {
+        let mut transport = MBotTransport::connect("sim://").await.unwrap();
+
+        // The simulated robot moves 3 ticks/s per % power at 10 ticks/cm with
+        // a 10cm wheel base: 30% for 3s drives 27cm, and spinning for 2s
+        // turns 3.6 radians
+        let plan = CalibrationPlan::default();
+        let start = Calibration {
+            ticks_per_cm: 25.0,
+            wheel_base_cm: 20.0,
+        };
+        let calibration = run(&mut transport, start, plan, |m| {
+            Ok(match m {
+                Measurement::StraightCm => 27.0,
+                Measurement::SpinDeg => 3.6f32.to_degrees(),
+            })
+        })
+        .await
+        .unwrap();
+
+        assert!((calibration.ticks_per_cm - 10.0).abs() < 0.2, "{:?}", calibration);
+        assert!((calibration.wheel_base_cm - 10.0).abs() < 0.2, "{:?}", calibration);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rejects_swapped_answer() {
+        let mut transport = MBotTransport::connect("sim://").await.unwrap();
+        let result = run(&mut transport, Calibration::DEFAULT, CalibrationPlan::default(), |m| {
+            Ok(match m {
+                Measurement::StraightCm => 27.0,
+                Measurement::SpinDeg => -108.0,
+            })
+        })
+        .await;
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,186 @@
+//! The robot's side of the Makeblock protocol, answered from a `SimWorld`
+//!
+//! `mbot-emulator` serves this over TCP, so the companion's stream link -
+//! the same code that drives a serial port - can be exercised end to end
+//! without hardware.
+
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+use crate::protocol::{action, device, Frame, Request, RequestDecoder, Value};
+use crate::sim::{Scene, SimWorld};
+
+/// One emulated robot
+#[derive(Default)]
+pub struct Emulator {
+    world: SimWorld,
+    decoder: RequestDecoder,
+}
+
+impl Emulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_scene(scene: Scene) -> Self {
+        Self {
+            world: SimWorld::with_scene(scene),
+            decoder: RequestDecoder::default(),
+        }
+    }
+
+    /// Bytes from the companion in, reply bytes out; `elapsed` is the time
+    /// since the robot was switched on
+    pub fn receive(&mut self, bytes: &[u8], elapsed: Duration) -> Vec<u8> {
+        self.decoder
+            .decode(bytes)
+            .iter()
+            .filter_map(|request| self.answer(request, elapsed))
+            .flat_map(|frame| frame.encode())
+            .collect()
+    }
+
+    fn answer(&mut self, request: &Request, elapsed: Duration) -> Option<Frame> {
+        match request.action {
+            action::RUN => {
+                if let (device::DC_MOTOR, [_, left, right]) = (request.device, request.args.as_slice()) {
+                    self.world.set_motors(*left as i8, *right as i8, elapsed);
+                }
+                Some(Frame::Ack)
+            }
+            action::GET => {
+                let value = self.read(request, elapsed)?;
+                Some(Frame::Response {
+                    index: request.index,
+                    value,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Value for a GET, or None if this robot has no such sensor - the
+    /// firmware stays silent in that case
+    fn read(&mut self, request: &Request, elapsed: Duration) -> Option<Value> {
+        let sensors = self.world.sensors(elapsed);
+        let value = match (request.device, request.args.as_slice()) {
+            (device::ULTRASONIC, _) => Value::Float(sensors.ultrasonic_cm),
+            (device::ENCODER_MOTOR, [_, 1]) => Value::Long(sensors.encoder_left),
+            (device::ENCODER_MOTOR, [_, 2]) => Value::Long(sensors.encoder_right),
+            // Axes 1-3 are turn rates, 4-6 acceleration
+            (device::GYRO, [_, 1 | 2]) => Value::Float(0.0),
+            (device::GYRO, [_, 3]) => Value::Float(sensors.gyro_z),
+            (device::GYRO, [_, axis @ 4..=6]) => Value::Float(sensors.accel[*axis as usize - 4]),
+            (device::SOUND_SENSOR, _) => Value::Float(sensors.sound_level * 100.0),
+            (device::LIGHT_SENSOR, _) => Value::Float(sensors.light_level * 100.0),
+            (device::QUAD_RGB, [_, probe @ 0..=3]) => {
+                let [r, g, b] = sensors.quad_rgb[*probe as usize];
+                Value::Long(i32::from_be_bytes([0, r, g, b]))
+            }
+            _ => {
+                debug!("No answer for {:?}", request);
+                return None;
+            }
+        };
+        Some(value)
+    }
+}
+
+/// Accept connections forever, each talking to its own fresh robot in
+/// `scene`
+pub async fn serve(listener: TcpListener, scene: Scene) -> io::Result<()> {
+    loop {
+        let (socket, peer) = listener.accept().await?;
+        info!("🔌 {} connected", peer);
+        let emulator = Emulator::with_scene(scene.clone());
+        tokio::spawn(async move {
+            if let Err(e) = run_robot(socket, emulator).await {
+                warn!("{}: {}", peer, e);
+            }
+            info!("👋 {} disconnected", peer);
+        });
+    }
+}
+
+async fn run_robot(mut socket: TcpStream, mut emulator: Emulator) -> io::Result<()> {
+    socket.set_nodelay(true)?;
+    let start = Instant::now();
+    let mut buf = [0u8; 512];
+    loop {
+        let n = socket.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        let reply = emulator.receive(&buf[..n], start.elapsed());
+        if !reply.is_empty() {
+            socket.write_all(&reply).await?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::poller::SensorField;
+    use crate::protocol::{self, FrameDecoder};
+    use crate::transport::MBotTransport;
+    use mbot_core::MotorCommand;
+
+    #[test]
+    fn test_answers_reads_and_follows_motors() {
+        let mut emulator = Emulator::new();
+        let mut decoder = FrameDecoder::new();
+
+        let replies = emulator.receive(&protocol::motor_cmd(40, -40), Duration::ZERO);
+        assert_eq!(decoder.decode(&replies), vec![Frame::Ack]);
+
+        let mut reads = protocol::with_index(protocol::read_encoder_cmd(1), 1);
+        reads.extend(protocol::with_index(protocol::read_gyro_cmd(3), 2));
+        // No such sensor: no answer
+        reads.extend(protocol::with_index(protocol::read_quad_rgb_probe_cmd(9), 3));
+        let replies = decoder.decode(&emulator.receive(&reads, Duration::from_secs(1)));
+
+        assert_eq!(replies.len(), 2);
+        assert_eq!(replies[0], Frame::Response { index: 1, value: Value::Long(120) });
+        let Frame::Response { index: 2, value: Value::Float(turn) } = replies[1] else {
+            panic!("{:?}", replies[1]);
+        };
+        assert!(turn < -10.0, "{}", turn);
+    }
+
+    /// The whole stream link against the server, as CI runs it
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_tcp_link_end_to_end() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, Scene::default()));
+
+        let uri = format!("tcp://{}", addr);
+        let mut transport = MBotTransport::connect(&uri).await.unwrap();
+        transport
+            .send_command(&MotorCommand {
+                left: 50,
+                right: 50,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let first = transport.read_sensors().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let second = transport.read_sensors().await.unwrap();
+
+        assert!(second.encoder_left > first.encoder_left, "{:?} {:?}", first, second);
+        assert_eq!(second.accel[2], 9.8);
+        assert_eq!(second.quad_rgb, [[200, 200, 200]; 4]);
+        let freshness = transport.health().freshness.unwrap();
+        assert!(freshness.stale(second.timestamp_us, 100_000).is_empty());
+        assert!(freshness.updated_us(SensorField::Light).is_some());
+
+        transport.close().await.unwrap();
+        assert!(!transport.health().connected);
+    }
+}
@@ -0,0 +1,15 @@
+//! mBot2 companion library - shared by the companion, tic-tac-toe,
+//! drawing, emulator and batch simulation binaries
+
+pub mod batch;
+pub mod ble;
+pub mod calibrate;
+pub mod emulator;
+pub mod poller;
+pub mod profile;
+pub mod protocol;
+pub mod record;
+pub mod replay;
+pub mod sim;
+pub mod supervisor;
+pub mod transport;
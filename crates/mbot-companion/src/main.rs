@@ -4,40 +4,85 @@
 //!   mbot-companion --bluetooth           # Connect via Bluetooth
 //!   mbot-companion --serial /dev/ttyUSB0 # Connect via USB serial
 //!   mbot-companion --simulate            # Run without hardware (testing)
+//!   mbot-companion --scene scenes/patrol.json  # Simulate in a scene file
+//!   mbot-companion --link serial:///dev/ttyUSB0
+//!   mbot-companion --personality nervous-nellie
+//!   mbot-companion --profile robot.json  # Remember personality and mood
+//!   mbot-companion --serial /dev/ttyUSB0 --profile robot.json calibrate
+//!   mbot-companion --record session.log # Log every tick for replay
+//!   mbot-companion --simulate --seed 42 # Same wander and quirks every run
+//!   mbot-companion replay session.log    # Check the brain still does the same
+//!   mbot-companion --link replay://session.log  # Drive the brain from a recording
 
 use anyhow::{Context, Result};
-use clap::Parser;
-use mbot_core::{HomeostasisState, MBotBrain, MBotSensors, MotorCommand, ReflexMode};
+use clap::{Parser, Subcommand};
+use mbot_core::{
+    rng, HomeostasisState, MBotBrain, MBotSensors, PersonalityPreset, ReflexMode, RobotProfile,
+    PEN_UP, PRESETS,
+};
+use std::io::{self, Write};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
-use tracing::{info, warn, Level};
+use tracing::{debug, info, warn, Level};
 
-mod protocol;
-mod transport;
-
-use transport::{MBotTransport, TransportType};
+use mbot_companion::transport::MBotTransport;
+use mbot_companion::supervisor::{self, LinkStatus, LinkSupervisor, SupervisorConfig};
+use mbot_companion::calibrate::{self, CalibrationPlan, Measurement};
+use mbot_companion::profile;
+use mbot_companion::record::{SessionLog, SessionRecorder};
+use mbot_companion::replay;
 
 #[derive(Parser, Debug)]
 #[command(name = "mbot-companion")]
 #[command(about = "RuVector AI companion for mBot2", long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Connect via Bluetooth
-    #[arg(long)]
+    #[arg(long, global = true)]
     bluetooth: bool,
 
     /// Connect via serial port
-    #[arg(long)]
+    #[arg(long, global = true)]
     serial: Option<String>,
 
     /// Simulate without hardware
-    #[arg(long)]
+    #[arg(long, global = true)]
     simulate: bool,
 
+    /// Scene file for the simulated robot (implies --simulate)
+    #[arg(long, global = true, conflicts_with_all = ["bluetooth", "serial"])]
+    scene: Option<PathBuf>,
+
+    /// Link URI: sim://, serial:///dev/ttyUSB0, ble://, ble://<name>, tcp://host:port or
+    /// replay://session.log
+    #[arg(long, global = true, conflicts_with_all = ["bluetooth", "serial", "simulate", "scene"])]
+    link: Option<String>,
+
     /// Control loop frequency in Hz
     #[arg(long, default_value = "20")]
     freq: u32,
 
+    /// Personality preset (default, curious-george, nervous-nellie, chill-charlie, bouncy-betty)
+    #[arg(long)]
+    personality: Option<String>,
+
+    /// Profile file to load on start and save on exit (.json or binary)
+    #[arg(long, global = true)]
+    profile: Option<PathBuf>,
+
+    /// Seed for the brain's random choices (wander, quirks); the same seed and
+    /// the same sensor frames always give the same run
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Record every tick to a session log
+    #[arg(long)]
+    record: Option<PathBuf>,
+
     /// Enable drawing mode (pen attached)
     #[arg(long)]
     draw: bool,
@@ -47,6 +92,20 @@ struct Args {
     verbose: bool,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Drive a straight line and a spin to measure odometry constants,
+    /// then save them to the --profile file
+    Calibrate,
+    /// Feed session logs through a fresh brain and report any tick where it
+    /// behaves differently from the recording
+    Replay {
+        /// Logs written by --record
+        #[arg(required = true)]
+        logs: Vec<PathBuf>,
+    },
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
@@ -55,53 +114,179 @@ async fn main() -> Result<()> {
     let log_level = if args.verbose { Level::DEBUG } else { Level::INFO };
     tracing_subscriber::fmt().with_max_level(log_level).init();
 
+    if let Some(Command::Replay { logs }) = &args.command {
+        return replay_logs(logs);
+    }
+
     info!("🤖 mBot2 RuVector Companion starting...");
 
-    // Determine transport type
-    let transport_type = if args.simulate {
-        info!("📡 Running in SIMULATION mode");
-        TransportType::Simulated
-    } else if args.bluetooth {
-        #[cfg(feature = "bluetooth")]
-        {
-            info!("📡 Connecting via Bluetooth...");
-            TransportType::Bluetooth
+    // Load who the robot was last time, if we know
+    let mut robot = match &args.profile {
+        Some(path) if path.exists() => {
+            let loaded = profile::load(path)?;
+            info!(
+                "💾 Loaded profile {} ({:.0}s lived, {} startles)",
+                path.display(),
+                loaded.mood.lifetime_us as f64 / 1_000_000.0,
+                loaded.mood.startle_count
+            );
+            loaded
         }
-        #[cfg(not(feature = "bluetooth"))]
-        {
-            anyhow::bail!("Bluetooth support not compiled. Rebuild with: cargo build --features bluetooth");
-        }
-    } else if let Some(port) = &args.serial {
-        #[cfg(feature = "serial")]
-        {
-            info!("📡 Connecting via Serial: {}", port);
-            TransportType::Serial(port.clone())
+        _ => RobotProfile::default(),
+    };
+
+    // An explicit --personality overrides the saved one but keeps the mood
+    if let Some(name) = &args.personality {
+        let Some(preset) = PersonalityPreset::find(name) else {
+            let known: Vec<&str> = PRESETS.iter().map(|p| p.id).collect();
+            anyhow::bail!("Unknown personality '{}'. Available: {}", name, known.join(", "));
+        };
+        info!("{} Personality: {} - {}", preset.icon, preset.name, preset.description);
+        robot.personality = preset.config;
+    }
+
+    // Open the link
+    let uri = link_uri(&args);
+    info!("📡 Connecting to {}", uri);
+    let transport = MBotTransport::connect(&uri).await?;
+
+    if let Some(Command::Calibrate) = args.command {
+        let Some(path) = &args.profile else {
+            anyhow::bail!("calibrate needs --profile <file> to save the result to");
+        };
+        return run_calibration(transport, robot, path).await;
+    }
+
+    // Ride out dropped connections instead of exiting
+    let link = Arc::new(Mutex::new(LinkSupervisor::for_uri(
+        transport,
+        &uri,
+        SupervisorConfig::default(),
+    )));
+
+    // Create brain
+    let brain = MBotBrain::with_profile_seeded(&robot, args.seed.unwrap_or(rng::DEFAULT_SEED));
+    let recorder = match &args.record {
+        Some(path) => {
+            info!("⏺️  Recording session to {}", path.display());
+            Some(SessionRecorder::create(path, &brain.snapshot())?)
         }
-        #[cfg(not(feature = "serial"))]
-        {
-            let _ = port;
-            anyhow::bail!("Serial support not compiled. Rebuild with: cargo build --features serial");
+        None => None,
+    };
+    let brain = Arc::new(Mutex::new(brain));
+
+    // Run main loop until Ctrl-C
+    let result = tokio::select! {
+        result = run_main_loop(link.clone(), brain.clone(), recorder, args.freq, args.draw) => result,
+        _ = tokio::signal::ctrl_c() => {
+            info!("👋 Shutting down...");
+            Ok(())
         }
+    };
+
+    // Stop the robot whether the loop ended cleanly or not
+    if let Err(e) = link.lock().await.close().await {
+        warn!("Failed to close link: {:#}", e);
+    }
+    result?;
+
+    if let Some(path) = &args.profile {
+        let robot = brain.lock().await.profile();
+        profile::save(path, &robot)?;
+        info!("💾 Saved profile to {}", path.display());
+    }
+
+    Ok(())
+}
+
+/// The link URI chosen on the command line, translating the older
+/// --simulate/--bluetooth/--serial flags
+fn link_uri(args: &Args) -> String {
+    if let Some(uri) = &args.link {
+        uri.clone()
+    } else if let Some(scene) = &args.scene {
+        format!("sim://{}", scene.display())
+    } else if args.simulate {
+        "sim://".to_string()
+    } else if args.bluetooth {
+        "ble://".to_string()
+    } else if let Some(port) = &args.serial {
+        format!("serial://{}", port)
     } else {
         info!("📡 No connection specified, running in SIMULATION mode");
-        info!("   Use --bluetooth or --serial <port> for real hardware");
-        TransportType::Simulated
-    };
+        info!("   Use --link <uri>, --bluetooth or --serial <port> for real hardware");
+        "sim://".to_string()
+    }
+}
 
-    // Create transport
-    let transport = MBotTransport::connect(transport_type).await?;
-    let transport = Arc::new(Mutex::new(transport));
+/// Replay each log, failing if any of them diverges
+fn replay_logs(logs: &[PathBuf]) -> Result<()> {
+    let mut diverged = 0;
+    for path in logs {
+        let report = replay::replay(&SessionLog::read(path)?);
+        println!("{}: {}", path.display(), report);
+        if !report.identical() {
+            diverged += 1;
+        }
+    }
+    if diverged > 0 {
+        anyhow::bail!("{} of {} sessions diverged", diverged, logs.len());
+    }
+    Ok(())
+}
 
-    // Create brain
-    let brain = Arc::new(Mutex::new(MBotBrain::new()));
+async fn run_calibration(
+    mut transport: MBotTransport,
+    mut robot: RobotProfile,
+    path: &std::path::Path,
+) -> Result<()> {
+    info!("📐 Calibrating odometry");
+    println!("Put the robot on a flat surface with at least 40cm of clear space");
+    println!("in front of it and mark where it starts. Press Enter to begin.");
+    read_line()?;
+
+    let calibration = calibrate::run(&mut transport, robot.calibration, CalibrationPlan::default(), |m| {
+        if m == Measurement::SpinDeg {
+            println!("Now it will spin on the spot; mark its heading first.");
+        }
+        ask_number(m.prompt())
+    })
+    .await;
+    transport.close().await?;
+    let calibration = calibration?;
+
+    info!(
+        "✅ {:.2} ticks/cm, {:.2} cm wheel base",
+        calibration.ticks_per_cm, calibration.wheel_base_cm
+    );
+    robot.calibration = calibration;
+    profile::save(path, &robot)?;
+    info!("💾 Saved calibration to {}", path.display());
+    Ok(())
+}
 
-    // Run main loop
-    run_main_loop(transport, brain, args.freq, args.draw).await
+fn read_line() -> Result<String> {
+    let mut line = String::new();
+    io::stdin().read_line(&mut line).context("Failed to read input")?;
+    Ok(line)
+}
+
+/// Ask until the user types a number
+fn ask_number(prompt: &str) -> Result<f32> {
+    loop {
+        print!("{}", prompt);
+        io::stdout().flush()?;
+        match read_line()?.trim().parse() {
+            Ok(value) => return Ok(value),
+            Err(_) => println!("Please enter a number."),
+        }
+    }
 }
 
 async fn run_main_loop(
-    transport: Arc<Mutex<MBotTransport>>,
+    link: Arc<Mutex<LinkSupervisor>>,
     brain: Arc<Mutex<MBotBrain>>,
+    mut recorder: Option<SessionRecorder>,
     freq: u32,
     draw_mode: bool,
 ) -> Result<()> {
@@ -118,31 +303,55 @@ async fn run_main_loop(
     let mut total_loop_time = Duration::ZERO;
     let mut max_loop_time = Duration::ZERO;
 
+    // Brain state from when the link dropped, resumed once it's back
+    let mut held = None;
+    let mut last_outage_report = Instant::now();
+
     loop {
         let loop_start = Instant::now();
 
-        // Read sensors
-        let sensors = {
-            let mut t = transport.lock().await;
-            t.read_sensors().await?
+        // Read sensors; while the link is down, or the encoders have yet to
+        // give a first count, the brain waits
+        let sensors = link.lock().await.read_sensors().await;
+        let Some(sensors) = sensors else {
+            if held.is_none() {
+                held = Some(brain.lock().await.snapshot());
+            }
+            if last_outage_report.elapsed() >= Duration::from_secs(1) {
+                println!("{}", link.lock().await.status());
+                last_outage_report = Instant::now();
+            }
+            tokio::time::sleep(tick_duration).await;
+            last_tick = Instant::now();
+            continue;
         };
 
         // Process through brain
         let (state, mut cmd) = {
             let mut b = brain.lock().await;
-            b.tick(&sensors)
+            if let Some(snapshot) = held.take() {
+                supervisor::resume(&mut b, &snapshot);
+                if let Some(recorder) = &mut recorder {
+                    recorder.resumed()?;
+                }
+            }
+            let output = b.tick(&sensors);
+            while let Some(t) = b.next_transition() {
+                debug!("Reflex {:?} → {:?} at tick {}", t.from, t.to, t.tick);
+            }
+            output
         };
+        if let Some(recorder) = &mut recorder {
+            recorder.frame(&sensors, &state, &cmd)?;
+        }
 
         // Override pen state if not in draw mode
         if !draw_mode {
-            cmd.pen_angle = 45; // Keep pen up
+            cmd.pen_angle = PEN_UP;
         }
 
-        // Send motor commands
-        {
-            let mut t = transport.lock().await;
-            t.send_command(&cmd).await?;
-        }
+        // Send motor commands; a failure is picked up on the next read
+        link.lock().await.send_command(&cmd).await;
 
         // Track timing
         let loop_time = loop_start.elapsed();
@@ -151,9 +360,10 @@ async fn run_main_loop(
         tick_count += 1;
 
         // Print status periodically
-        if tick_count % (freq as u64) == 0 {
+        if tick_count.is_multiple_of(freq as u64) {
             // Every second
-            print_status(&sensors, &state, tick_count, total_loop_time, max_loop_time);
+            let status = link.lock().await.status();
+            print_status(&sensors, &state, &status, tick_count, total_loop_time, max_loop_time);
         }
 
         // Maintain loop timing
@@ -170,6 +380,7 @@ async fn run_main_loop(
 fn print_status(
     sensors: &MBotSensors,
     state: &HomeostasisState,
+    link: &LinkStatus,
     tick_count: u64,
     total_time: Duration,
     max_time: Duration,
@@ -209,6 +420,7 @@ fn print_status(
         avg_time.as_micros(),
         max_time.as_micros()
     );
+    println!("║  {}", link);
     println!(
         "╚══════════════════════════════════════════════════════════════╝"
     );
@@ -0,0 +1,470 @@
+//! Polling every mBot2 sensor over a Makeblock byte stream
+//!
+//! All reads for one control period are written back to back, each with its
+//! own index byte, and the replies are matched up as they arrive in any
+//! order. A sensor that doesn't answer in time keeps its previous value, and
+//! `Freshness` records when each field was last actually read so a stale
+//! reading is never mistaken for a real zero. Until a field's first reply it
+//! reads as in `MBotSensors::UNREAD`.
+
+use std::io;
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::timeout_at;
+
+use mbot_core::MBotSensors;
+
+use crate::protocol::{self, Frame, FrameDecoder, RequestTracker, Value};
+
+/// Requests older than this are abandoned and asked again
+const REQUEST_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// One polled sensor reading
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SensorField {
+    Ultrasonic,
+    EncoderLeft,
+    EncoderRight,
+    GyroZ,
+    Accel(u8),
+    Sound,
+    Light,
+    QuadRgb(u8),
+}
+
+impl SensorField {
+    /// Every field, in the order requests are sent
+    pub const ALL: [SensorField; 13] = [
+        SensorField::Ultrasonic,
+        SensorField::EncoderLeft,
+        SensorField::EncoderRight,
+        SensorField::GyroZ,
+        SensorField::Accel(0),
+        SensorField::Accel(1),
+        SensorField::Accel(2),
+        SensorField::Sound,
+        SensorField::Light,
+        SensorField::QuadRgb(0),
+        SensorField::QuadRgb(1),
+        SensorField::QuadRgb(2),
+        SensorField::QuadRgb(3),
+    ];
+
+    /// The GET command that reads this field
+    pub fn request(&self) -> Vec<u8> {
+        match *self {
+            SensorField::Ultrasonic => protocol::read_ultrasonic_cmd(),
+            SensorField::EncoderLeft => protocol::read_encoder_cmd(1),
+            SensorField::EncoderRight => protocol::read_encoder_cmd(2),
+            SensorField::GyroZ => protocol::read_gyro_cmd(3),
+            SensorField::Accel(axis) => protocol::read_accel_cmd(axis + 1),
+            SensorField::Sound => protocol::read_sound_cmd(),
+            SensorField::Light => protocol::read_light_cmd(),
+            SensorField::QuadRgb(probe) => protocol::read_quad_rgb_probe_cmd(probe),
+        }
+    }
+
+    fn slot(&self) -> usize {
+        match *self {
+            SensorField::Ultrasonic => 0,
+            SensorField::EncoderLeft => 1,
+            SensorField::EncoderRight => 2,
+            SensorField::GyroZ => 3,
+            SensorField::Accel(axis) => 4 + axis as usize,
+            SensorField::Sound => 7,
+            SensorField::Light => 8,
+            SensorField::QuadRgb(probe) => 9 + probe as usize,
+        }
+    }
+}
+
+/// When each field was last read, in the same microsecond clock as
+/// `MBotSensors::timestamp_us`; None if it never has been
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Freshness {
+    updated_us: [Option<u64>; SensorField::ALL.len()],
+}
+
+impl Freshness {
+    pub fn updated_us(&self, field: SensorField) -> Option<u64> {
+        self.updated_us[field.slot()]
+    }
+
+    /// Whether `field` was read within `max_age_us` of `now_us`
+    pub fn is_fresh(&self, field: SensorField, now_us: u64, max_age_us: u64) -> bool {
+        self.updated_us(field)
+            .is_some_and(|t| now_us.saturating_sub(t) <= max_age_us)
+    }
+
+    /// Fields not read within `max_age_us` of `now_us`
+    pub fn stale(&self, now_us: u64, max_age_us: u64) -> Vec<SensorField> {
+        SensorField::ALL
+            .into_iter()
+            .filter(|&f| !self.is_fresh(f, now_us, max_age_us))
+            .collect()
+    }
+
+    /// When any field was last read
+    pub fn newest_us(&self) -> Option<u64> {
+        self.updated_us.iter().flatten().copied().max()
+    }
+
+    /// Whether `field` has ever been read
+    pub fn has_read(&self, field: SensorField) -> bool {
+        self.updated_us(field).is_some()
+    }
+
+    /// Record `field` as read at `now_us`
+    pub(crate) fn mark(&mut self, field: SensorField, now_us: u64) {
+        self.updated_us[field.slot()] = Some(now_us);
+    }
+
+    /// Record every field as read at `now_us`, for links that get whole
+    /// frames
+    pub(crate) fn mark_all(&mut self, now_us: u64) {
+        self.updated_us = [Some(now_us); SensorField::ALL.len()];
+    }
+}
+
+/// Protocol state for polling, independent of how bytes are moved
+pub struct SensorPoller {
+    start: Instant,
+    decoder: FrameDecoder,
+    tracker: RequestTracker<SensorField>,
+    sensors: MBotSensors,
+    freshness: Freshness,
+}
+
+impl SensorPoller {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            decoder: FrameDecoder::new(),
+            tracker: RequestTracker::new(),
+            sensors: MBotSensors::UNREAD,
+            freshness: Freshness::default(),
+        }
+    }
+
+    /// Bytes requesting every field that isn't already awaiting a reply
+    pub fn requests(&mut self, now: Instant) -> Vec<u8> {
+        self.tracker.expire(now, REQUEST_TIMEOUT);
+        let mut out = Vec::new();
+        for field in SensorField::ALL {
+            if self.awaiting(field) {
+                continue;
+            }
+            if let Some(index) = self.tracker.issue(field, now) {
+                out.extend(protocol::with_index(field.request(), index));
+            }
+        }
+        out
+    }
+
+    /// Feed bytes read from the robot
+    pub fn receive(&mut self, bytes: &[u8], now: Instant) {
+        let now_us = self.micros(now);
+        for frame in self.decoder.decode(bytes) {
+            let Frame::Response { index, value } = frame else {
+                continue;
+            };
+            if let Some(field) = self.tracker.resolve(index) {
+                if self.apply(field, &value) {
+                    self.freshness.mark(field, now_us);
+                }
+            }
+        }
+    }
+
+    /// Whether every request sent has been answered
+    pub fn settled(&self) -> bool {
+        self.tracker.in_flight() == 0
+    }
+
+    /// Latest value of every field, stamped `now`
+    pub fn reading(&self, now: Instant) -> (MBotSensors, Freshness) {
+        let sensors = MBotSensors {
+            timestamp_us: self.micros(now),
+            ..self.sensors.clone()
+        };
+        (sensors, self.freshness.clone())
+    }
+
+    fn awaiting(&self, field: SensorField) -> bool {
+        self.tracker.pending().any(|&f| f == field)
+    }
+
+    fn micros(&self, now: Instant) -> u64 {
+        now.saturating_duration_since(self.start).as_micros() as u64
+    }
+
+    /// Store a reply; false if the value had the wrong shape
+    fn apply(&mut self, field: SensorField, value: &Value) -> bool {
+        let s = &mut self.sensors;
+        let integer = || match value {
+            Value::Long(v) => Some(*v),
+            Value::Short(v) => Some(*v as i32),
+            Value::Byte(v) => Some(*v as i32),
+            other => other.as_f32().map(|v| v as i32),
+        };
+        let Some(number) = value.as_f32() else {
+            return false;
+        };
+        match field {
+            SensorField::Ultrasonic => s.ultrasonic_cm = number,
+            SensorField::EncoderLeft => s.encoder_left = integer().unwrap_or_default(),
+            SensorField::EncoderRight => s.encoder_right = integer().unwrap_or_default(),
+            SensorField::GyroZ => s.gyro_z = number,
+            SensorField::Accel(axis) => s.accel[axis as usize] = number,
+            SensorField::Sound => s.sound_level = (number / 100.0).clamp(0.0, 1.0),
+            SensorField::Light => s.light_level = (number / 100.0).clamp(0.0, 1.0),
+            SensorField::QuadRgb(probe) => {
+                let rgb = integer().unwrap_or_default() as u32;
+                s.quad_rgb[probe as usize] = [(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8];
+            }
+        }
+        true
+    }
+}
+
+impl Default for SensorPoller {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Request every sensor and collect replies for up to `budget`.
+///
+/// Nothing here blocks: a robot that stops answering, or a stream that
+/// won't take the requests, costs at most `budget` of waiting on the
+/// runtime. Requests that couldn't be written in time are an error;
+/// replies that didn't arrive just leave their fields stale.
+pub async fn poll<P: AsyncRead + AsyncWrite + Unpin + ?Sized>(
+    port: &mut P,
+    poller: &mut SensorPoller,
+    budget: Duration,
+) -> io::Result<(MBotSensors, Freshness)> {
+    let deadline = tokio::time::Instant::now() + budget;
+
+    let requests = poller.requests(Instant::now());
+    let write = async {
+        port.write_all(&requests).await?;
+        port.flush().await
+    };
+    timeout_at(deadline, write)
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "Timed out sending sensor requests"))??;
+
+    let mut buf = [0u8; 256];
+    while !poller.settled() {
+        let Ok(read) = timeout_at(deadline, port.read(&mut buf)).await else {
+            break;
+        };
+        match read {
+            Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "The robot closed the connection")),
+            Ok(n) => poller.receive(&buf[..n], Instant::now()),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(poller.reading(Instant::now()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{device, RequestDecoder};
+    use tokio::io::{AsyncRead, AsyncWrite};
+
+    /// What a healthy robot answers to each request
+    fn answer(request: &protocol::Request) -> Option<Value> {
+        Some(match (request.device, request.args.as_slice()) {
+            (device::ULTRASONIC, _) => Value::Float(42.5),
+            (device::ENCODER_MOTOR, [_, 1]) => Value::Long(1200),
+            (device::ENCODER_MOTOR, [_, 2]) => Value::Long(-1180),
+            (device::GYRO, [_, 3]) => Value::Float(12.5),
+            (device::GYRO, [_, axis]) => Value::Float(*axis as f32),
+            (device::SOUND_SENSOR, _) => Value::Short(30),
+            (device::LIGHT_SENSOR, _) => Value::Byte(80),
+            (device::QUAD_RGB, [_, probe]) => Value::Long(0x10_20_30 + *probe as i32),
+            _ => return None,
+        })
+    }
+
+    /// Fake robot: answer `requests`, optionally skipping some fields
+    fn reply(bytes: &[u8], skip: &[u8]) -> Vec<u8> {
+        RequestDecoder::new()
+            .decode(bytes)
+            .iter()
+            .filter(|r| !skip.contains(&r.device))
+            .filter_map(|r| answer(r).map(|value| Frame::Response { index: r.index, value }))
+            // The robot may answer out of order
+            .rev()
+            .flat_map(|f| f.encode())
+            .collect()
+    }
+
+    #[test]
+    fn test_fills_every_field() {
+        let mut poller = SensorPoller::new();
+        let now = Instant::now();
+        let requests = poller.requests(now);
+        poller.receive(&reply(&requests, &[]), now);
+        assert!(poller.settled());
+
+        let (sensors, freshness) = poller.reading(now);
+        assert_eq!(sensors.ultrasonic_cm, 42.5);
+        assert_eq!((sensors.encoder_left, sensors.encoder_right), (1200, -1180));
+        assert_eq!(sensors.gyro_z, 12.5);
+        assert_eq!(sensors.accel, [4.0, 5.0, 6.0]);
+        assert_eq!(sensors.sound_level, 0.3);
+        assert_eq!(sensors.light_level, 0.8);
+        assert_eq!(sensors.quad_rgb[3], [0x10, 0x20, 0x33]);
+        assert!(freshness.stale(sensors.timestamp_us, 0).is_empty());
+    }
+
+    #[test]
+    fn test_missing_reply_is_stale_not_zero() {
+        let mut poller = SensorPoller::new();
+        let t0 = Instant::now();
+        let requests = poller.requests(t0);
+        poller.receive(&reply(&requests, &[]), t0);
+
+        // Next period the gyro stops answering
+        let t1 = t0 + Duration::from_millis(50);
+        let requests = poller.requests(t1);
+        poller.receive(&reply(&requests, &[device::GYRO]), t1);
+        assert!(!poller.settled());
+
+        let (sensors, freshness) = poller.reading(t1);
+        assert_eq!(sensors.gyro_z, 12.5);
+        assert!(!freshness.is_fresh(SensorField::GyroZ, sensors.timestamp_us, 10_000));
+        assert!(freshness.is_fresh(SensorField::Ultrasonic, sensors.timestamp_us, 10_000));
+        assert!(freshness.stale(sensors.timestamp_us, 10_000).contains(&SensorField::Accel(2)));
+    }
+
+    #[test]
+    fn test_never_read_field_has_no_timestamp() {
+        let poller = SensorPoller::new();
+        let (sensors, freshness) = poller.reading(Instant::now());
+        assert!(sensors.ultrasonic_cm.is_nan());
+        assert_eq!(sensors.distance_cm(), None);
+        assert_eq!(freshness.updated_us(SensorField::Ultrasonic), None);
+        assert!(!freshness.has_read(SensorField::EncoderLeft));
+    }
+
+    #[test]
+    fn test_field_that_never_answers_stays_unread() {
+        let mut poller = SensorPoller::new();
+        let t0 = Instant::now();
+        for period in 0..3 {
+            let now = t0 + Duration::from_millis(50 * period);
+            let requests = poller.requests(now);
+            poller.receive(&reply(&requests, &[device::GYRO]), now);
+        }
+
+        // No gyro on this robot: the brain gets no rate rather than a still one
+        let (sensors, freshness) = poller.reading(t0 + Duration::from_millis(100));
+        assert_eq!(sensors.gyro_dps(), None);
+        assert_eq!(sensors.acceleration(), None);
+        assert_eq!(sensors.distance_cm(), Some(42.5));
+        assert!(!freshness.has_read(SensorField::GyroZ));
+        assert!(freshness.has_read(SensorField::EncoderRight));
+    }
+
+    #[test]
+    fn test_does_not_re_request_in_flight_fields() {
+        let mut poller = SensorPoller::new();
+        let now = Instant::now();
+        let first = poller.requests(now);
+        assert_eq!(RequestDecoder::new().decode(&first).len(), SensorField::ALL.len());
+        assert!(poller.requests(now).is_empty());
+
+        // Once abandoned they are asked again
+        let later = now + REQUEST_TIMEOUT * 2;
+        assert_eq!(
+            RequestDecoder::new().decode(&poller.requests(later)).len(),
+            SensorField::ALL.len()
+        );
+    }
+
+    /// Fake robot on the far end of a stream: answers `periods` rounds of
+    /// requests, dribbling each reply out in small pieces
+    async fn robot<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, periods: usize) -> S {
+        let mut decoder = RequestDecoder::new();
+        let mut buf = [0u8; 512];
+        let mut answered = 0;
+        while answered < SensorField::ALL.len() * periods {
+            let n = stream.read(&mut buf).await.unwrap();
+            for request in decoder.decode(&buf[..n]) {
+                let value = answer(&request).unwrap();
+                let frame = Frame::Response { index: request.index, value }.encode();
+                for chunk in frame.chunks(3) {
+                    stream.write_all(chunk).await.unwrap();
+                }
+                answered += 1;
+            }
+        }
+        // Hang on to the stream: dropping it hangs up the other end
+        stream
+    }
+
+    async fn poll_twice<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S) {
+        let mut poller = SensorPoller::new();
+        for _ in 0..2 {
+            let (sensors, freshness) = poll(stream, &mut poller, Duration::from_secs(2)).await.unwrap();
+            assert_eq!(sensors.encoder_right, -1180);
+            assert_eq!(sensors.quad_rgb[0], [0x10, 0x20, 0x30]);
+            assert!(freshness.stale(sensors.timestamp_us, 2_000_000).is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_loopback() {
+        let (mut ours, theirs) = tokio::io::duplex(64);
+        let robot = tokio::spawn(robot(theirs, 2));
+        poll_twice(&mut ours).await;
+        robot.await.unwrap();
+    }
+
+    /// A pty pair stands in for the serial port
+    #[cfg(all(unix, feature = "serial"))]
+    #[tokio::test]
+    async fn test_pty_loopback() {
+        let (master, mut slave) = tokio_serial::SerialStream::pair().unwrap();
+        let robot = tokio::spawn(robot(master, 2));
+        poll_twice(&mut slave).await;
+        robot.await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_silent_robot_costs_only_the_budget() {
+        // Requests go out but nothing ever comes back
+        let (mut ours, _theirs) = tokio::io::duplex(4096);
+        let mut poller = SensorPoller::new();
+        let start = tokio::time::Instant::now();
+        let (sensors, freshness) = poll(&mut ours, &mut poller, Duration::from_millis(40)).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_millis(40));
+        assert_eq!(sensors.distance_cm(), None);
+        assert_eq!(freshness.newest_us(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stuck_write_times_out() {
+        // The robot isn't reading, and the requests don't fit in the pipe
+        let (mut ours, _theirs) = tokio::io::duplex(8);
+        let mut poller = SensorPoller::new();
+        let err = poll(&mut ours, &mut poller, Duration::from_millis(40)).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[tokio::test]
+    async fn test_hang_up_is_an_error() {
+        let (mut ours, theirs) = tokio::io::duplex(4096);
+        drop(theirs);
+        let mut poller = SensorPoller::new();
+        assert!(poll(&mut ours, &mut poller, Duration::from_secs(1)).await.is_err());
+    }
+}
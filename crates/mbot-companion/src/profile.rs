@@ -0,0 +1,213 @@
+//! Robot profile files - who the robot is between runs
+//!
+//! Profiles ending in `.json` are stored as human-editable JSON, anything
+//! else uses the compact checksummed binary layout from `mbot_core::persist`
+//! (the same bytes the CyberPi keeps in flash).
+//!
+//! JSON profiles carry a top-level `"version"`, 1 for the personality,
+//! mood and calibration layout this build writes. A file without one is
+//! read as version 1; newer versions are refused rather than misread.
+
+use anyhow::{bail, Context, Result};
+use mbot_core::RobotProfile;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// JSON profile layout written by this build
+pub const JSON_PROFILE_VERSION: u64 = 1;
+
+/// Whether a path should be read and written as JSON
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+/// Load a profile, rejecting corrupted or out-of-bounds files
+pub fn load(path: &Path) -> Result<RobotProfile> {
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("Failed to read profile: {}", path.display()))?;
+
+    if is_json(path) {
+        let profile = decode_json(&bytes)
+            .with_context(|| format!("Invalid JSON profile: {}", path.display()))?;
+        profile
+            .personality
+            .validate()
+            .with_context(|| format!("Unsafe personality in {}", path.display()))?;
+        profile
+            .calibration
+            .validate()
+            .with_context(|| format!("Implausible calibration in {}", path.display()))?;
+        Ok(RobotProfile {
+            mood: profile.mood.clamped(),
+            ..profile
+        })
+    } else {
+        RobotProfile::decode(&bytes)
+            .with_context(|| format!("Invalid profile: {}", path.display()))
+    }
+}
+
+/// Read a JSON profile of any version this build understands
+fn decode_json(bytes: &[u8]) -> Result<RobotProfile> {
+    let mut value: Value = serde_json::from_slice(bytes)?;
+    let Some(fields) = value.as_object_mut() else {
+        bail!("profile is not a JSON object");
+    };
+    let version = match fields.remove("version") {
+        None => 1,
+        Some(v) => v
+            .as_u64()
+            .with_context(|| format!("profile version {} is not a number", v))?,
+    };
+    if version == 0 || version > JSON_PROFILE_VERSION {
+        bail!("unsupported profile version {}", version);
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+/// The JSON form of a profile, stamped with its version
+fn encode_json(profile: &RobotProfile) -> Result<Vec<u8>> {
+    let mut value = serde_json::to_value(profile)?;
+    if let Some(fields) = value.as_object_mut() {
+        fields.insert("version".to_string(), JSON_PROFILE_VERSION.into());
+    }
+    Ok(serde_json::to_vec_pretty(&value)?)
+}
+
+/// Save a profile in the format chosen by the file extension
+pub fn save(path: &Path, profile: &RobotProfile) -> Result<()> {
+    let bytes = if is_json(path) {
+        encode_json(profile)?
+    } else {
+        profile.encode().to_vec()
+    };
+
+    // Write then rename so a crash mid-save never leaves a half-written profile
+    let tmp = temp_file(path);
+    std::fs::write(&tmp, bytes)
+        .with_context(|| format!("Failed to write profile: {}", tmp.display()))?;
+    std::fs::rename(&tmp, path)
+        .with_context(|| format!("Failed to replace profile: {}", path.display()))?;
+    Ok(())
+}
+
+/// Where `save` writes before renaming into place: the whole file name
+/// plus ".tmp", so robot.json and robot.mbp never share one
+fn temp_file(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".tmp");
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mbot_core::{Calibration, MoodMemory, PersonalityConfig};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("mbot-profile-{}-{}", std::process::id(), name))
+    }
+
+    fn sample() -> RobotProfile {
+        RobotProfile {
+            personality: PersonalityConfig::BOUNCY_BETTY,
+            mood: MoodMemory {
+                energy: 0.8,
+                long_tension: 0.25,
+                long_coherence: 0.9,
+                startle_count: 3,
+                lifetime_us: 200_000_000,
+            },
+            calibration: Calibration {
+                ticks_per_cm: 17.8,
+                wheel_base_cm: 11.4,
+            },
+        }
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let path = temp_path("robot.mbp");
+        save(&path, &sample()).unwrap();
+        assert_eq!(load(&path).unwrap(), sample());
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_formats_save_through_separate_temp_files() {
+        let json = temp_path("robot.json");
+        let binary = temp_path("robot.mbp");
+        assert_eq!(temp_file(&json), temp_path("robot.json.tmp"));
+        assert_ne!(temp_file(&json), temp_file(&binary));
+
+        save(&json, &sample()).unwrap();
+        save(&binary, &sample()).unwrap();
+        assert_eq!(load(&json).unwrap(), sample());
+        assert_eq!(load(&binary).unwrap(), sample());
+        assert!(!temp_file(&json).exists() && !temp_file(&binary).exists());
+        std::fs::remove_file(&json).ok();
+        std::fs::remove_file(&binary).ok();
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let path = temp_path("robot.json");
+        save(&path, &sample()).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.contains("\"movement_expressiveness\""));
+        assert!(text.contains(&format!("\"version\": {}", JSON_PROFILE_VERSION)));
+        assert_eq!(load(&path).unwrap(), sample());
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_rejects_json_without_calibration() {
+        let path = temp_path("partial.json");
+        let mut value = serde_json::to_value(sample()).unwrap();
+        value.as_object_mut().unwrap().remove("calibration");
+        std::fs::write(&path, value.to_string()).unwrap();
+        assert!(load(&path).is_err());
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_json_versions() {
+        let path = temp_path("versioned.json");
+
+        // Hand-written without a version: read as version 1
+        let mut value = serde_json::to_value(sample()).unwrap();
+        std::fs::write(&path, value.to_string()).unwrap();
+        assert_eq!(load(&path).unwrap(), sample());
+        value["version"] = 1.into();
+        std::fs::write(&path, value.to_string()).unwrap();
+        assert_eq!(load(&path).unwrap(), sample());
+
+        for version in [Value::from(0), Value::from(JSON_PROFILE_VERSION + 1), Value::from("1")] {
+            value["version"] = version.clone();
+            std::fs::write(&path, value.to_string()).unwrap();
+            assert!(load(&path).is_err(), "version {} was accepted", version);
+        }
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_rejects_corrupted_binary() {
+        let path = temp_path("corrupt.mbp");
+        let mut bytes = sample().encode();
+        bytes[20] ^= 0xff;
+        std::fs::write(&path, bytes).unwrap();
+        assert!(load(&path).is_err());
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_rejects_unsafe_json() {
+        let path = temp_path("unsafe.json");
+        let mut profile = sample();
+        profile.personality.smoothing = 50.0;
+        std::fs::write(&path, serde_json::to_vec(&profile).unwrap()).unwrap();
+        assert!(load(&path).is_err());
+        std::fs::remove_file(&path).ok();
+    }
+}
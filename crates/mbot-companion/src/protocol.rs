@@ -3,12 +3,20 @@
 //! Based on Makeblock's protocol documentation.
 //! Reference: https://github.com/Makeblock-official/Makeblock-Libraries
 
+pub mod frame;
+pub mod request;
+pub mod tracker;
+
+pub use frame::{Frame, FrameDecoder, Value};
+pub use request::{Request, RequestDecoder};
+pub use tracker::RequestTracker;
+
 /// Protocol header
 const HEADER: [u8; 2] = [0xff, 0x55];
 
 /// Device IDs
 #[allow(dead_code)]
-mod device {
+pub mod device {
     pub const ULTRASONIC: u8 = 0x01;
     pub const LIGHT_SENSOR: u8 = 0x03;
     pub const SOUND_SENSOR: u8 = 0x07;
@@ -23,11 +31,20 @@ mod device {
 
 /// Action codes
 #[allow(dead_code)]
-mod action {
+pub mod action {
     pub const GET: u8 = 0x01;
     pub const RUN: u8 = 0x02;
 }
 
+/// Byte offset of the index in every command frame
+const INDEX_OFFSET: usize = 3;
+
+/// Tag a command with the index its response will echo
+pub fn with_index(mut cmd: Vec<u8>, index: u8) -> Vec<u8> {
+    cmd[INDEX_OFFSET] = index;
+    cmd
+}
+
 /// Build ultrasonic sensor read command
 pub fn read_ultrasonic_cmd() -> Vec<u8> {
     vec![
@@ -66,7 +83,7 @@ pub fn motor_cmd(left: i8, right: i8) -> Vec<u8> {
     vec![
         HEADER[0],
         HEADER[1],
-        0x08,              // Length
+        0x06,              // Length
         0x00,              // Index
         action::RUN,       // Action: RUN
         device::DC_MOTOR,  // Device: DC Motor
@@ -122,7 +139,7 @@ pub fn led_cmd(rgb: [u8; 3]) -> Vec<u8> {
     vec![
         HEADER[0],
         HEADER[1],
-        0x08,           // Length
+        0x09,           // Length
         0x00,           // Index
         action::RUN,    // Action: RUN
         device::RGBLED, // Device: RGB LED
@@ -140,7 +157,7 @@ pub fn buzzer_cmd(frequency: u16, duration_ms: u16) -> Vec<u8> {
     vec![
         HEADER[0],
         HEADER[1],
-        0x08,            // Length
+        0x07,            // Length
         0x00,            // Index
         action::RUN,     // Action: RUN
         device::BUZZER,  // Device: Buzzer
@@ -166,6 +183,68 @@ pub fn read_gyro_cmd(axis: u8) -> Vec<u8> {
     ]
 }
 
+/// Build accelerometer read command (mBot2 firmware reports acceleration
+/// in m/s² on the gyro device's axes 4-6)
+pub fn read_accel_cmd(axis: u8) -> Vec<u8> {
+    // axis: 1=X, 2=Y, 3=Z
+    read_gyro_cmd(axis + 3)
+}
+
+/// Build encoder position read command (slot 1 = left, 2 = right)
+pub fn read_encoder_cmd(slot: u8) -> Vec<u8> {
+    vec![
+        HEADER[0],
+        HEADER[1],
+        0x05,                   // Length
+        0x00,                   // Index
+        action::GET,            // Action: GET
+        device::ENCODER_MOTOR,  // Device: Encoder Motor
+        0x00,                   // Port (onboard)
+        slot,                   // Slot
+    ]
+}
+
+/// Build sound level read command (0-100)
+pub fn read_sound_cmd() -> Vec<u8> {
+    vec![
+        HEADER[0],
+        HEADER[1],
+        0x04,                 // Length
+        0x00,                 // Index
+        action::GET,          // Action: GET
+        device::SOUND_SENSOR, // Device: Sound sensor
+        0x00,                 // Port (onboard)
+    ]
+}
+
+/// Build light level read command (0-100)
+pub fn read_light_cmd() -> Vec<u8> {
+    vec![
+        HEADER[0],
+        HEADER[1],
+        0x04,                 // Length
+        0x00,                 // Index
+        action::GET,          // Action: GET
+        device::LIGHT_SENSOR, // Device: Light sensor
+        0x00,                 // Port (onboard)
+    ]
+}
+
+/// Build read command for one quad RGB probe (0-3); the reply is a long
+/// packed as 0x00RRGGBB
+pub fn read_quad_rgb_probe_cmd(probe: u8) -> Vec<u8> {
+    vec![
+        HEADER[0],
+        HEADER[1],
+        0x05,             // Length
+        0x00,             // Index
+        action::GET,      // Action: GET
+        device::QUAD_RGB, // Device: Quad RGB
+        0x01,             // Port 1
+        probe,            // Probe
+    ]
+}
+
 /// Build quad RGB sensor read command
 pub fn read_quad_rgb_cmd() -> Vec<u8> {
     vec![
@@ -214,6 +293,39 @@ mod tests {
         assert_eq!(cmd[7], 90);
     }
 
+    #[test]
+    fn test_length_bytes_match_payload() {
+        let commands = [
+            read_ultrasonic_cmd(),
+            motor_cmd(10, -10),
+            encoder_motor_cmd(1, 100, Some(360)),
+            servo_cmd(1, 90),
+            led_cmd([1, 2, 3]),
+            buzzer_cmd(440, 100),
+            read_gyro_cmd(3),
+            read_accel_cmd(1),
+            read_encoder_cmd(2),
+            read_sound_cmd(),
+            read_light_cmd(),
+            read_quad_rgb_probe_cmd(0),
+            read_quad_rgb_cmd(),
+        ];
+        for cmd in commands {
+            assert_eq!(cmd[2] as usize, cmd.len() - 3, "{:02x?}", cmd);
+        }
+    }
+
+    #[test]
+    fn test_with_index_round_trips_through_response() {
+        let cmd = with_index(read_gyro_cmd(3), 0x2a);
+        assert_eq!(cmd[3], 0x2a);
+
+        // The robot echoes the index back
+        let reply = Frame::Response { index: cmd[3], value: Value::Float(-3.5) }.encode();
+        let frames = FrameDecoder::new().decode(&reply);
+        assert_eq!(frames, vec![Frame::Response { index: 0x2a, value: Value::Float(-3.5) }]);
+    }
+
     #[test]
     fn test_parse_ultrasonic() {
         // Simulate response: 25.5 cm
@@ -0,0 +1,363 @@
+//! Streaming decoder for Makeblock response frames
+//!
+//! The robot answers every GET with a frame tagged by the request's index
+//! byte, and every RUN with a bare acknowledgement:
+//!
+//! ```text
+//! ff 55 <index> <type> <value...> 0d 0a   response
+//! ff 55 0d 0a                             ack
+//! ```
+//!
+//! Serial reads split and merge frames arbitrarily and line noise inserts
+//! garbage, so the decoder buffers input, pulls out whole frames, and
+//! resynchronises on the next `ff 55` whenever a frame doesn't check out.
+
+use super::HEADER;
+
+const TERMINATOR: [u8; 2] = [0x0d, 0x0a];
+
+/// Value type codes
+mod value_type {
+    pub const BYTE: u8 = 1;
+    pub const FLOAT: u8 = 2;
+    pub const SHORT: u8 = 3;
+    pub const STRING: u8 = 4;
+    pub const DOUBLE: u8 = 5;
+    pub const LONG: u8 = 6;
+}
+
+/// A decoded sensor value
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Byte(u8),
+    Float(f32),
+    Short(i16),
+    Str(String),
+    /// The firmware's `double` is 4 bytes on the Arduino-class boards
+    Double(f32),
+    Long(i32),
+}
+
+impl Value {
+    /// Numeric view of the value, if it has one
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            Value::Byte(v) => Some(*v as f32),
+            Value::Float(v) | Value::Double(v) => Some(*v),
+            Value::Short(v) => Some(*v as f32),
+            Value::Long(v) => Some(*v as f32),
+            Value::Str(_) => None,
+        }
+    }
+}
+
+/// One frame from the robot
+#[derive(Clone, Debug, PartialEq)]
+pub enum Frame {
+    /// A RUN command was carried out
+    Ack,
+    /// Answer to the GET request sent with `index`
+    Response { index: u8, value: Value },
+}
+
+impl Frame {
+    /// Wire bytes for this frame (what the robot would send)
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = HEADER.to_vec();
+        if let Frame::Response { index, value } = self {
+            out.push(*index);
+            match value {
+                Value::Byte(v) => {
+                    out.push(value_type::BYTE);
+                    out.push(*v);
+                }
+                Value::Float(v) => {
+                    out.push(value_type::FLOAT);
+                    out.extend_from_slice(&v.to_le_bytes());
+                }
+                Value::Short(v) => {
+                    out.push(value_type::SHORT);
+                    out.extend_from_slice(&v.to_le_bytes());
+                }
+                Value::Str(s) => {
+                    // Strings longer than a length byte allows are cut short
+                    let bytes = &s.as_bytes()[..s.len().min(255)];
+                    out.push(value_type::STRING);
+                    out.push(bytes.len() as u8);
+                    out.extend_from_slice(bytes);
+                }
+                Value::Double(v) => {
+                    out.push(value_type::DOUBLE);
+                    out.extend_from_slice(&v.to_le_bytes());
+                }
+                Value::Long(v) => {
+                    out.push(value_type::LONG);
+                    out.extend_from_slice(&v.to_le_bytes());
+                }
+            }
+        }
+        out.extend_from_slice(&TERMINATOR);
+        out
+    }
+}
+
+/// Outcome of trying to read a frame at the start of the buffer
+enum Parse {
+    Frame(Frame, usize),
+    NeedMore,
+    Invalid,
+}
+
+/// Reassembles frames from arbitrary chunks of a byte stream
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    buf: Vec<u8>,
+    discarded: usize,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append bytes read from the link
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Append bytes and return every frame now complete
+    pub fn decode(&mut self, bytes: &[u8]) -> Vec<Frame> {
+        self.push(bytes);
+        core::iter::from_fn(|| self.next_frame()).collect()
+    }
+
+    /// Next complete frame, skipping any garbage before it
+    pub fn next_frame(&mut self) -> Option<Frame> {
+        loop {
+            self.skip_to_header();
+            match parse(&self.buf) {
+                Parse::Frame(frame, len) => {
+                    self.buf.drain(..len);
+                    return Some(frame);
+                }
+                Parse::NeedMore => return None,
+                Parse::Invalid => {
+                    // Not a real frame: look for the next header after this one
+                    self.buf.drain(..1);
+                    self.discarded += 1;
+                }
+            }
+        }
+    }
+
+    /// Bytes thrown away while resynchronising
+    pub fn discarded(&self) -> usize {
+        self.discarded
+    }
+
+    /// Bytes held waiting for the rest of a frame
+    pub fn pending(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Drop everything before the first possible header
+    fn skip_to_header(&mut self) {
+        let start = self
+            .buf
+            .windows(2)
+            .position(|w| w == HEADER)
+            // A trailing 0xff may be the first half of a header
+            .unwrap_or(if self.buf.last() == Some(&HEADER[0]) {
+                self.buf.len() - 1
+            } else {
+                self.buf.len()
+            });
+        self.buf.drain(..start);
+        self.discarded += start;
+    }
+}
+
+/// Try to read one frame from a buffer that starts with a header
+fn parse(buf: &[u8]) -> Parse {
+    if buf.len() < 4 {
+        return Parse::NeedMore;
+    }
+    if buf[2..4] == TERMINATOR {
+        return Parse::Frame(Frame::Ack, 4);
+    }
+
+    let index = buf[2];
+    let body = &buf[4..];
+    let (value, len) = match buf[3] {
+        value_type::BYTE => match body {
+            [v, ..] => (Value::Byte(*v), 1),
+            _ => return Parse::NeedMore,
+        },
+        value_type::FLOAT => match take::<4>(body) {
+            Some(b) => (Value::Float(f32::from_le_bytes(b)), 4),
+            None => return Parse::NeedMore,
+        },
+        value_type::SHORT => match take::<2>(body) {
+            Some(b) => (Value::Short(i16::from_le_bytes(b)), 2),
+            None => return Parse::NeedMore,
+        },
+        value_type::STRING => {
+            let Some(&n) = body.first() else {
+                return Parse::NeedMore;
+            };
+            let n = n as usize;
+            if body.len() < 1 + n {
+                return Parse::NeedMore;
+            }
+            let text = String::from_utf8_lossy(&body[1..1 + n]).into_owned();
+            (Value::Str(text), 1 + n)
+        }
+        value_type::DOUBLE => match take::<4>(body) {
+            Some(b) => (Value::Double(f32::from_le_bytes(b)), 4),
+            None => return Parse::NeedMore,
+        },
+        value_type::LONG => match take::<4>(body) {
+            Some(b) => (Value::Long(i32::from_le_bytes(b)), 4),
+            None => return Parse::NeedMore,
+        },
+        _ => return Parse::Invalid,
+    };
+
+    let end = 4 + len;
+    let terminator = &buf[end..buf.len().min(end + 2)];
+    if terminator != &TERMINATOR[..terminator.len()] {
+        Parse::Invalid
+    } else if terminator.len() < TERMINATOR.len() {
+        Parse::NeedMore
+    } else {
+        Parse::Frame(Frame::Response { index, value }, end + 2)
+    }
+}
+
+fn take<const N: usize>(bytes: &[u8]) -> Option<[u8; N]> {
+    bytes.get(..N).map(|b| b.try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mbot_core::Rng;
+
+    /// Longest possible frame: header, index, type, string length, 255 bytes, terminator
+    const MAX_FRAME_LEN: usize = 2 + 2 + 1 + 255 + 2;
+
+    fn every_type(index: u8) -> Vec<Frame> {
+        [
+            Value::Byte(0xff),
+            Value::Float(25.5),
+            Value::Short(-1234),
+            Value::Str("mBot2 \r\n ok".to_string()),
+            Value::Double(-0.125),
+            Value::Long(-987_654_321),
+        ]
+        .into_iter()
+        .map(|value| Frame::Response { index, value })
+        .chain([Frame::Ack])
+        .collect()
+    }
+
+    #[test]
+    fn test_decodes_every_value_type() {
+        let frames = every_type(7);
+        let bytes: Vec<u8> = frames.iter().flat_map(Frame::encode).collect();
+
+        let mut decoder = FrameDecoder::new();
+        assert_eq!(decoder.decode(&bytes), frames);
+        assert_eq!(decoder.pending(), 0);
+        assert_eq!(decoder.discarded(), 0);
+    }
+
+    #[test]
+    fn test_reassembles_byte_by_byte() {
+        let frames = every_type(42);
+        let bytes: Vec<u8> = frames.iter().flat_map(Frame::encode).collect();
+
+        let mut decoder = FrameDecoder::new();
+        let mut decoded = Vec::new();
+        for byte in bytes {
+            decoded.extend(decoder.decode(&[byte]));
+        }
+        assert_eq!(decoded, frames);
+    }
+
+    #[test]
+    fn test_resyncs_after_garbage() {
+        let good = Frame::Response { index: 3, value: Value::Float(12.0) };
+        let mut bytes = vec![0x00, 0xff, 0x13, 0xff, 0x55, 0x01, 0x09, 0x37];
+        bytes.extend(good.encode());
+        // A frame with a broken terminator, then another good one
+        bytes.extend([0xff, 0x55, 0x04, 0x01, 0x10, 0x0d, 0x0b]);
+        bytes.extend(good.encode());
+
+        let mut decoder = FrameDecoder::new();
+        assert_eq!(decoder.decode(&bytes), vec![good.clone(), good]);
+        assert!(decoder.discarded() > 0);
+    }
+
+    #[test]
+    fn test_waits_for_split_terminator() {
+        let frame = Frame::Response { index: 1, value: Value::Byte(9) };
+        let bytes = frame.encode();
+        let mut decoder = FrameDecoder::new();
+        assert!(decoder.decode(&bytes[..bytes.len() - 1]).is_empty());
+        assert_eq!(decoder.decode(&bytes[bytes.len() - 1..]), vec![frame]);
+    }
+
+    #[test]
+    fn test_never_panics_on_random_streams() {
+        let mut rng = Rng::new(0xf0220);
+        for _ in 0..2_000 {
+            let mut decoder = FrameDecoder::new();
+            let len = (rng.next_u32() % 400) as usize;
+            let mut stream: Vec<u8> = (0..len).map(|_| rng.next_u32() as u8).collect();
+            // Bias towards bytes that look like frame structure
+            for byte in stream.iter_mut() {
+                if rng.chance(0.3) {
+                    *byte = [0xff, 0x55, 0x0d, 0x0a, 0x01, 0x04][(rng.next_u32() % 6) as usize];
+                }
+            }
+
+            let mut fed = 0;
+            while fed < stream.len() {
+                let chunk = 1 + (rng.next_u32() as usize % 32);
+                let end = (fed + chunk).min(stream.len());
+                decoder.decode(&stream[fed..end]);
+                fed = end;
+            }
+            // Nothing can sit in the buffer that isn't a possible frame start
+            assert!(decoder.pending() < MAX_FRAME_LEN);
+        }
+    }
+
+    #[test]
+    fn test_recovers_every_frame_between_random_noise() {
+        let mut rng = Rng::new(11);
+        for _ in 0..300 {
+            let mut stream = Vec::new();
+            let mut expected = Vec::new();
+            for i in 0..10 {
+                // Noise without 0xff can never look like a header
+                let noise = rng.next_u32() % 8;
+                stream.extend((0..noise).map(|_| (rng.next_u32() % 0xff) as u8));
+                let frame = every_type(i)[(rng.next_u32() % 7) as usize].clone();
+                stream.extend(frame.encode());
+                expected.push(frame);
+            }
+
+            let mut decoder = FrameDecoder::new();
+            let mut decoded = Vec::new();
+            let mut fed = 0;
+            while fed < stream.len() {
+                let end = (fed + 1 + rng.next_u32() as usize % 16).min(stream.len());
+                decoded.extend(decoder.decode(&stream[fed..end]));
+                fed = end;
+            }
+            assert_eq!(decoded, expected);
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Decoding command frames - the robot's side of the protocol
+//!
+//! ```text
+//! ff 55 <len> <index> <action> <device> <args...>
+//! ```
+//!
+//! `len` counts every byte after itself. Test stand-ins and emulators use
+//! this to understand what the companion is asking for.
+
+use super::HEADER;
+
+/// Shortest body: index, action, device
+const MIN_LEN: usize = 3;
+
+/// One command from the companion
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Request {
+    pub index: u8,
+    pub action: u8,
+    pub device: u8,
+    /// Port, slot and value bytes, device-specific
+    pub args: Vec<u8>,
+}
+
+/// Reassembles command frames from a byte stream
+#[derive(Debug, Default)]
+pub struct RequestDecoder {
+    buf: Vec<u8>,
+}
+
+impl RequestDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append bytes and return every command now complete
+    pub fn decode(&mut self, bytes: &[u8]) -> Vec<Request> {
+        self.buf.extend_from_slice(bytes);
+        let mut out = Vec::new();
+        loop {
+            // Resynchronise on the next header
+            let start = self
+                .buf
+                .windows(2)
+                .position(|w| w == HEADER)
+                .unwrap_or(self.buf.len().saturating_sub(1));
+            self.buf.drain(..start);
+
+            if self.buf.len() < 3 {
+                return out;
+            }
+            let len = self.buf[2] as usize;
+            if len < MIN_LEN {
+                self.buf.drain(..1);
+                continue;
+            }
+            if self.buf.len() < 3 + len {
+                return out;
+            }
+
+            let body = &self.buf[3..3 + len];
+            out.push(Request {
+                index: body[0],
+                action: body[1],
+                device: body[2],
+                args: body[3..].to_vec(),
+            });
+            self.buf.drain(..3 + len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    fn test_decodes_pipelined_commands() {
+        let mut bytes = with_index(read_encoder_cmd(2), 9);
+        bytes.extend(motor_cmd(-20, 20));
+        bytes.extend(with_index(read_ultrasonic_cmd(), 10));
+
+        let mut decoder = RequestDecoder::new();
+        let requests = decoder.decode(&bytes[..5]);
+        assert!(requests.is_empty());
+        let requests = decoder.decode(&bytes[5..]);
+
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].index, 9);
+        assert_eq!(requests[0].device, device::ENCODER_MOTOR);
+        assert_eq!(requests[0].args, vec![0x00, 2]);
+        assert_eq!(requests[1].action, action::RUN);
+        assert_eq!(requests[1].args, vec![0x00, -20i8 as u8, 20]);
+        assert_eq!(requests[2].index, 10);
+    }
+
+    #[test]
+    fn test_skips_garbage() {
+        let mut bytes = vec![0x12, 0xff, 0x00, 0xff, 0x55, 0x01];
+        bytes.extend(read_light_cmd());
+        let requests = RequestDecoder::new().decode(&bytes);
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].device, device::LIGHT_SENSOR);
+    }
+}
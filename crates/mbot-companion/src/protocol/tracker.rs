@@ -0,0 +1,143 @@
+//! Matching responses to requests by index byte
+//!
+//! Each GET carries an index byte that the robot echoes in its response, so
+//! several requests can be in flight at once. The tracker hands out free
+//! indices, remembers what each one asked for, and expires requests whose
+//! answer never came so their index can be reused.
+
+use std::time::{Duration, Instant};
+
+/// Index 0 is what the fire-and-forget builders send; tracked requests never use it
+const FIRST_INDEX: u8 = 1;
+
+struct Pending<T> {
+    tag: T,
+    sent: Instant,
+}
+
+/// In-flight requests keyed by index byte
+pub struct RequestTracker<T> {
+    slots: Vec<Option<Pending<T>>>,
+    next: u8,
+}
+
+impl<T> RequestTracker<T> {
+    pub fn new() -> Self {
+        Self {
+            slots: (0..=u8::MAX).map(|_| None).collect(),
+            next: FIRST_INDEX,
+        }
+    }
+
+    /// Reserve an index for a request about `tag`, or None if all 255 are in flight
+    pub fn issue(&mut self, tag: T, now: Instant) -> Option<u8> {
+        // Rotate through indices so a late reply to an expired request is
+        // unlikely to be mistaken for the answer to a fresh one
+        for _ in FIRST_INDEX..=u8::MAX {
+            let index = self.next;
+            self.next = if self.next == u8::MAX { FIRST_INDEX } else { self.next + 1 };
+            let slot = &mut self.slots[index as usize];
+            if slot.is_none() {
+                *slot = Some(Pending { tag, sent: now });
+                return Some(index);
+            }
+        }
+        None
+    }
+
+    /// The request answered by a response with `index`, if one is pending
+    pub fn resolve(&mut self, index: u8) -> Option<T> {
+        self.slots[index as usize].take().map(|p| p.tag)
+    }
+
+    /// Give up on requests older than `max_age`, returning what they asked for
+    pub fn expire(&mut self, now: Instant, max_age: Duration) -> Vec<T> {
+        self.slots
+            .iter_mut()
+            .filter(|slot| {
+                slot.as_ref()
+                    .is_some_and(|p| now.saturating_duration_since(p.sent) > max_age)
+            })
+            .filter_map(|slot| slot.take().map(|p| p.tag))
+            .collect()
+    }
+
+    /// What every request awaiting a response asked for
+    pub fn pending(&self) -> impl Iterator<Item = &T> {
+        self.slots.iter().flatten().map(|p| &p.tag)
+    }
+
+    /// Number of requests awaiting a response
+    pub fn in_flight(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.is_some()).count()
+    }
+}
+
+impl<T> Default for RequestTracker<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolves_by_index() {
+        let now = Instant::now();
+        let mut tracker = RequestTracker::new();
+        let a = tracker.issue("distance", now).unwrap();
+        let b = tracker.issue("gyro", now).unwrap();
+        assert_ne!(a, b);
+        assert_ne!(a, 0);
+
+        // Answers can arrive in any order
+        assert_eq!(tracker.resolve(b), Some("gyro"));
+        assert_eq!(tracker.resolve(a), Some("distance"));
+        assert_eq!(tracker.resolve(a), None);
+        assert_eq!(tracker.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_unknown_index_is_ignored() {
+        let mut tracker: RequestTracker<u32> = RequestTracker::new();
+        assert_eq!(tracker.resolve(77), None);
+    }
+
+    #[test]
+    fn test_runs_out_of_indices() {
+        let now = Instant::now();
+        let mut tracker = RequestTracker::new();
+        for i in 0..255 {
+            assert!(tracker.issue(i, now).is_some());
+        }
+        assert_eq!(tracker.issue(999, now), None);
+
+        tracker.resolve(200);
+        assert_eq!(tracker.issue(999, now), Some(200));
+    }
+
+    #[test]
+    fn test_expires_stale_requests() {
+        let start = Instant::now();
+        let mut tracker = RequestTracker::new();
+        let old = tracker.issue("old", start).unwrap();
+        let fresh = tracker.issue("fresh", start + Duration::from_millis(80)).unwrap();
+
+        let expired = tracker.expire(start + Duration::from_millis(100), Duration::from_millis(50));
+        assert_eq!(expired, vec!["old"]);
+        assert_eq!(tracker.resolve(old), None);
+        assert_eq!(tracker.resolve(fresh), Some("fresh"));
+    }
+
+    #[test]
+    fn test_indices_rotate() {
+        let now = Instant::now();
+        let mut tracker = RequestTracker::new();
+        let first = tracker.issue((), now).unwrap();
+        tracker.resolve(first);
+        let second = tracker.issue((), now).unwrap();
+        assert_ne!(first, second);
+    }
+}
@@ -0,0 +1,439 @@
+//! Session logs - what the robot saw, felt and did, frame by frame
+//!
+//! A log starts with the brain's snapshot from before the first tick, then
+//! holds one record per tick: the sensor frame, the homeostasis state and
+//! the motor command the brain answered with. `replay` feeds a log back
+//! through a fresh brain to check that it still behaves the same way.
+//!
+//! ```text
+//! header   "MBSL", major u8, minor u8, snapshot length u32, snapshot JSON
+//! record   tag u8, payload length u16, payload
+//! ```
+//!
+//! All numbers are little-endian. Readers skip payload bytes past the
+//! fields they know and records with unknown tags, so a minor version may
+//! append fields or add records. A record cut short (the recorder was
+//! killed mid-write) ends the log.
+
+use anyhow::{bail, Context, Result};
+use mbot_core::{BrainSnapshot, HomeostasisState, MBotSensors, MotorCommand, ReflexMode};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+pub const LOG_MAGIC: [u8; 4] = *b"MBSL";
+pub const LOG_VERSION_MAJOR: u8 = 1;
+pub const LOG_VERSION_MINOR: u8 = 0;
+
+const TAG_FRAME: u8 = 1;
+const TAG_RESUME: u8 = 2;
+
+/// Frames between flushes, about a second at the default loop rate
+const FLUSH_EVERY: u32 = 20;
+
+/// One tick of the control loop
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub sensors: MBotSensors,
+    pub state: HomeostasisState,
+    /// The brain's command, before the app overrides the pen
+    pub command: MotorCommand,
+}
+
+#[derive(Clone, Debug)]
+pub enum Record {
+    Frame(Frame),
+    /// The link dropped before the next frame and the brain was resumed
+    /// with `supervisor::resume`
+    Resume,
+}
+
+/// Writes a session log as the control loop runs
+pub struct SessionRecorder<W: Write = BufWriter<File>> {
+    out: W,
+    unflushed: u32,
+}
+
+impl SessionRecorder {
+    /// Start a log file, replacing any already at `path`
+    pub fn create(path: &Path, start: &BrainSnapshot) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create session log: {}", path.display()))?;
+        Self::new(BufWriter::new(file), start)
+    }
+}
+
+impl<W: Write> SessionRecorder<W> {
+    /// Write the header for a brain starting from `start`
+    pub fn new(mut out: W, start: &BrainSnapshot) -> Result<Self> {
+        let snapshot = serde_json::to_vec(start)?;
+        out.write_all(&LOG_MAGIC)?;
+        out.write_all(&[LOG_VERSION_MAJOR, LOG_VERSION_MINOR])?;
+        out.write_all(&(snapshot.len() as u32).to_le_bytes())?;
+        out.write_all(&snapshot)?;
+        Ok(Self { out, unflushed: 0 })
+    }
+
+    /// Record one tick
+    pub fn frame(
+        &mut self,
+        sensors: &MBotSensors,
+        state: &HomeostasisState,
+        command: &MotorCommand,
+    ) -> Result<()> {
+        let mut payload = Vec::with_capacity(96);
+        encode_sensors(&mut payload, sensors);
+        encode_state(&mut payload, state);
+        encode_command(&mut payload, command);
+        self.record(TAG_FRAME, &payload)?;
+
+        self.unflushed += 1;
+        if self.unflushed >= FLUSH_EVERY {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Record that the brain was resumed after an outage
+    pub fn resumed(&mut self) -> Result<()> {
+        self.record(TAG_RESUME, &[])
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.out.flush()?;
+        self.unflushed = 0;
+        Ok(())
+    }
+
+    /// Flush and hand back the writer
+    pub fn into_inner(mut self) -> Result<W> {
+        self.flush()?;
+        Ok(self.out)
+    }
+
+    fn record(&mut self, tag: u8, payload: &[u8]) -> Result<()> {
+        self.out.write_all(&[tag])?;
+        self.out.write_all(&(payload.len() as u16).to_le_bytes())?;
+        self.out.write_all(payload)?;
+        Ok(())
+    }
+}
+
+/// A session log read back
+#[derive(Clone, Debug)]
+pub struct SessionLog {
+    /// Minor version the log was written with
+    pub minor: u8,
+    /// The brain before the first frame
+    pub start: BrainSnapshot,
+    pub records: Vec<Record>,
+    /// Whether the last record was cut short
+    pub truncated: bool,
+}
+
+impl SessionLog {
+    pub fn read(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Failed to read session log: {}", path.display()))?;
+        Self::decode(&bytes).with_context(|| format!("Invalid session log: {}", path.display()))
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut r = Reader { buf: bytes };
+        if r.take::<4>() != Some(LOG_MAGIC) {
+            bail!("not a session log");
+        }
+        let Some([major, minor]) = r.take() else {
+            bail!("truncated header");
+        };
+        if major != LOG_VERSION_MAJOR {
+            bail!("unsupported version {}.{}", major, minor);
+        }
+        let Some(len) = r.u32() else {
+            bail!("truncated header");
+        };
+        let Some(snapshot) = r.slice(len as usize) else {
+            bail!("truncated header");
+        };
+        let start = serde_json::from_slice(snapshot).context("bad starting snapshot")?;
+
+        let mut records = Vec::new();
+        let mut truncated = false;
+        while !r.buf.is_empty() {
+            let payload = match (r.u8(), r.u16()) {
+                (Some(tag), Some(len)) => r.slice(len as usize).map(|payload| (tag, payload)),
+                _ => None,
+            };
+            let Some((tag, payload)) = payload else {
+                truncated = true;
+                break;
+            };
+            match tag {
+                TAG_FRAME => {
+                    let frame = decode_frame(&mut Reader { buf: payload })
+                        .with_context(|| format!("bad frame record {}", records.len()))?;
+                    records.push(Record::Frame(frame));
+                }
+                TAG_RESUME => records.push(Record::Resume),
+                _ => {}
+            }
+        }
+
+        Ok(Self { minor, start, records, truncated })
+    }
+
+    pub fn frames(&self) -> impl Iterator<Item = &Frame> {
+        self.records.iter().filter_map(|record| match record {
+            Record::Frame(frame) => Some(frame),
+            Record::Resume => None,
+        })
+    }
+}
+
+fn encode_sensors(out: &mut Vec<u8>, s: &MBotSensors) {
+    out.extend_from_slice(&s.timestamp_us.to_le_bytes());
+    out.extend_from_slice(&s.ultrasonic_cm.to_le_bytes());
+    out.extend_from_slice(&s.encoder_left.to_le_bytes());
+    out.extend_from_slice(&s.encoder_right.to_le_bytes());
+    out.extend(s.quad_rgb.iter().flatten());
+    out.extend_from_slice(&s.gyro_z.to_le_bytes());
+    for axis in s.accel {
+        out.extend_from_slice(&axis.to_le_bytes());
+    }
+    out.extend_from_slice(&s.sound_level.to_le_bytes());
+    out.extend_from_slice(&s.light_level.to_le_bytes());
+}
+
+fn encode_state(out: &mut Vec<u8>, s: &HomeostasisState) {
+    out.extend_from_slice(&s.tension.to_le_bytes());
+    out.extend_from_slice(&s.coherence.to_le_bytes());
+    out.push(match s.reflex {
+        ReflexMode::Calm => 0,
+        ReflexMode::Active => 1,
+        ReflexMode::Spike => 2,
+        ReflexMode::Protect => 3,
+    });
+    out.extend_from_slice(&s.energy.to_le_bytes());
+    out.extend_from_slice(&s.curiosity.to_le_bytes());
+}
+
+fn encode_command(out: &mut Vec<u8>, c: &MotorCommand) {
+    out.extend_from_slice(&c.left.to_le_bytes());
+    out.extend_from_slice(&c.right.to_le_bytes());
+    out.push(c.pen_angle);
+    out.extend_from_slice(&c.led_color);
+    out.extend_from_slice(&c.buzzer_hz.to_le_bytes());
+}
+
+fn decode_frame(r: &mut Reader) -> Result<Frame> {
+    let sensors = decode_sensors(r).context("short sensor frame")?;
+    let state = decode_state(r)?;
+    let command = decode_command(r).context("short motor command")?;
+    Ok(Frame { sensors, state, command })
+}
+
+fn decode_sensors(r: &mut Reader) -> Option<MBotSensors> {
+    Some(MBotSensors {
+        timestamp_us: u64::from_le_bytes(r.take()?),
+        ultrasonic_cm: r.f32()?,
+        encoder_left: i32::from_le_bytes(r.take()?),
+        encoder_right: i32::from_le_bytes(r.take()?),
+        quad_rgb: [r.take()?, r.take()?, r.take()?, r.take()?],
+        gyro_z: r.f32()?,
+        accel: [r.f32()?, r.f32()?, r.f32()?],
+        sound_level: r.f32()?,
+        light_level: r.f32()?,
+    })
+}
+
+fn decode_state(r: &mut Reader) -> Result<HomeostasisState> {
+    const SHORT: &str = "short homeostasis state";
+    let tension = r.f32().context(SHORT)?;
+    let coherence = r.f32().context(SHORT)?;
+    let reflex = match r.u8().context(SHORT)? {
+        0 => ReflexMode::Calm,
+        1 => ReflexMode::Active,
+        2 => ReflexMode::Spike,
+        3 => ReflexMode::Protect,
+        other => bail!("unknown reflex mode {}", other),
+    };
+    Ok(HomeostasisState {
+        tension,
+        coherence,
+        reflex,
+        energy: r.f32().context(SHORT)?,
+        curiosity: r.f32().context(SHORT)?,
+    })
+}
+
+fn decode_command(r: &mut Reader) -> Option<MotorCommand> {
+    Some(MotorCommand {
+        left: i8::from_le_bytes(r.take()?),
+        right: i8::from_le_bytes(r.take()?),
+        pen_angle: r.u8()?,
+        led_color: r.take()?,
+        buzzer_hz: r.u16()?,
+    })
+}
+
+/// Reads fixed-size fields off the front of a byte slice
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn slice(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.buf.len() < len {
+            return None;
+        }
+        let (head, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Some(head)
+    }
+
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.slice(N)?.try_into().ok()
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take::<1>().map(|[b]| b)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take().map(u16::from_le_bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        self.take().map(f32::from_le_bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mbot_core::MBotBrain;
+
+    fn sample_frame(t: u64) -> Frame {
+        Frame {
+            sensors: MBotSensors {
+                timestamp_us: t,
+                ultrasonic_cm: 42.5,
+                encoder_left: -120,
+                encoder_right: 75_000,
+                quad_rgb: [[1, 2, 3], [4, 5, 6], [7, 8, 9], [10, 11, 12]],
+                gyro_z: -3.25,
+                accel: [0.1, -0.2, 9.8],
+                sound_level: 0.3,
+                light_level: 0.7,
+            },
+            state: HomeostasisState {
+                tension: 0.6,
+                coherence: 0.9,
+                reflex: ReflexMode::Spike,
+                energy: 0.4,
+                curiosity: 0.2,
+            },
+            command: MotorCommand {
+                left: -40,
+                right: 100,
+                pen_angle: mbot_core::PEN_DOWN,
+                led_color: [255, 128, 0],
+                buzzer_hz: 880,
+            },
+        }
+    }
+
+    fn encode(records: &[Record]) -> Vec<u8> {
+        let start = MBotBrain::new().snapshot();
+        let mut recorder = SessionRecorder::new(Vec::new(), &start).unwrap();
+        for record in records {
+            match record {
+                Record::Frame(f) => recorder.frame(&f.sensors, &f.state, &f.command).unwrap(),
+                Record::Resume => recorder.resumed().unwrap(),
+            }
+        }
+        recorder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bytes = encode(&[
+            Record::Frame(sample_frame(50_000)),
+            Record::Resume,
+            Record::Frame(sample_frame(1_250_000)),
+        ]);
+        let log = SessionLog::decode(&bytes).unwrap();
+
+        assert_eq!(log.start, MBotBrain::new().snapshot());
+        assert_eq!(log.records.len(), 3);
+        assert!(matches!(log.records[1], Record::Resume));
+        assert!(!log.truncated);
+
+        let frames: Vec<&Frame> = log.frames().collect();
+        let (f, expected) = (frames[1], sample_frame(1_250_000));
+        assert_eq!(f.sensors.timestamp_us, 1_250_000);
+        assert_eq!(f.sensors.encoder_right, 75_000);
+        assert_eq!(f.sensors.quad_rgb, expected.sensors.quad_rgb);
+        assert_eq!(f.sensors.accel, expected.sensors.accel);
+        assert_eq!(f.state.reflex, ReflexMode::Spike);
+        assert_eq!(f.state.curiosity, 0.2);
+        assert_eq!(f.command.left, -40);
+        assert_eq!(f.command.led_color, [255, 128, 0]);
+        assert_eq!(f.command.buzzer_hz, 880);
+    }
+
+    #[test]
+    fn test_truncated_tail_is_dropped() {
+        let bytes = encode(&[Record::Frame(sample_frame(1)), Record::Frame(sample_frame(2))]);
+        let log = SessionLog::decode(&bytes[..bytes.len() - 5]).unwrap();
+        assert_eq!(log.records.len(), 1);
+        assert!(log.truncated);
+
+        assert!(SessionLog::decode(&bytes[..10]).is_err());
+        assert!(SessionLog::decode(b"PK\x03\x04").is_err());
+    }
+
+    #[test]
+    fn test_rejects_unknown_reflex_mode() {
+        let mut bytes = encode(&[Record::Frame(sample_frame(1))]);
+        // After the reflex byte come energy and curiosity, then the 8-byte command
+        let at = bytes.len() - 1 - 8 - 8;
+        assert_eq!(bytes[at], 2, "not the Spike byte");
+        bytes[at] = 4;
+
+        let error = SessionLog::decode(&bytes).unwrap_err();
+        let message = format!("{:#}", error);
+        assert!(message.contains("unknown reflex mode 4"), "{}", message);
+    }
+
+    #[test]
+    fn test_newer_minor_versions_are_readable() {
+        let mut bytes = encode(&[]);
+        bytes[5] = LOG_VERSION_MINOR + 1;
+
+        // A frame with an extra trailing field, then an unknown record
+        let mut payload = Vec::new();
+        let f = sample_frame(7);
+        encode_sensors(&mut payload, &f.sensors);
+        encode_state(&mut payload, &f.state);
+        encode_command(&mut payload, &f.command);
+        payload.extend_from_slice(&[0xAA; 6]);
+        for (tag, payload) in [(TAG_FRAME, &payload[..]), (99, &[1, 2, 3][..])] {
+            bytes.push(tag);
+            bytes.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(payload);
+        }
+
+        let log = SessionLog::decode(&bytes).unwrap();
+        assert_eq!(log.minor, LOG_VERSION_MINOR + 1);
+        assert_eq!(log.records.len(), 1);
+        assert_eq!(log.frames().next().unwrap().command.buzzer_hz, 880);
+
+        bytes[4] = LOG_VERSION_MAJOR + 1;
+        assert!(SessionLog::decode(&bytes).is_err());
+    }
+}
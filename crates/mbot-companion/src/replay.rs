@@ -0,0 +1,392 @@
+//! Replaying session logs through a fresh brain
+//!
+//! The brain is deterministic: restored from a log's starting snapshot and
+//! fed the same frames, it must feel and answer exactly as it did when the
+//! log was recorded. Any difference is a change in behaviour, which makes
+//! the logs in `sessions/` a regression corpus for brain changes.
+//!
+//! `replay` checks a log on its own. `ReplayLink` (`replay://session.log`)
+//! instead stands in for the robot, so any brain binary can be run against
+//! a recorded session.
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use mbot_core::{HomeostasisState, MBotBrain, MBotSensors, MotorCommand};
+use std::collections::VecDeque;
+use std::fmt;
+use tracing::{info, warn};
+
+use crate::record::{Frame, Record, SessionLog};
+use crate::supervisor;
+use crate::transport::{closed, LinkHealth, RobotLink};
+
+/// Differences shown per report
+const SHOWN_DIFFS: usize = 5;
+
+/// Where a replayed tick parted from the recording
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameDiff {
+    /// Frame number, counting from 0
+    pub frame: usize,
+    pub timestamp_us: u64,
+    /// Each field that changed, as "name recorded → replayed"
+    pub fields: Vec<String>,
+}
+
+impl fmt::Display for FrameDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "frame {} ({:.2}s): {}",
+            self.frame,
+            self.timestamp_us as f64 / 1e6,
+            self.fields.join(", ")
+        )
+    }
+}
+
+/// How a replay compared with its recording
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReplayReport {
+    pub frames: usize,
+    pub resumes: usize,
+    pub diffs: Vec<FrameDiff>,
+    /// Whether the log ended in a partly written record
+    pub truncated: bool,
+}
+
+impl ReplayReport {
+    /// Whether every frame matched the recording
+    pub fn identical(&self) -> bool {
+        self.diffs.is_empty()
+    }
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} frames, {} resumes", self.frames, self.resumes)?;
+        if self.truncated {
+            write!(f, ", truncated")?;
+        }
+        if self.identical() {
+            return write!(f, ": identical");
+        }
+        write!(f, ": {} differ", self.diffs.len())?;
+        for diff in self.diffs.iter().take(SHOWN_DIFFS) {
+            write!(f, "\n  {}", diff)?;
+        }
+        if self.diffs.len() > SHOWN_DIFFS {
+            write!(f, "\n  ...")?;
+        }
+        Ok(())
+    }
+}
+
+/// Tick a fresh brain through `log` and compare each tick with the recording
+pub fn replay(log: &SessionLog) -> ReplayReport {
+    let mut brain = MBotBrain::new();
+    brain.restore(&log.start);
+    let mut report = ReplayReport {
+        truncated: log.truncated,
+        ..Default::default()
+    };
+
+    for record in &log.records {
+        match record {
+            Record::Resume => {
+                let snapshot = brain.snapshot();
+                supervisor::resume(&mut brain, &snapshot);
+                report.resumes += 1;
+            }
+            Record::Frame(recorded) => {
+                let (state, command) = brain.tick(&recorded.sensors);
+                let fields = diff(recorded, &state, &command);
+                if !fields.is_empty() {
+                    report.diffs.push(FrameDiff {
+                        frame: report.frames,
+                        timestamp_us: recorded.sensors.timestamp_us,
+                        fields,
+                    });
+                }
+                report.frames += 1;
+            }
+        }
+    }
+    report
+}
+
+/// Fields that changed between a recorded tick and its replay
+fn diff(recorded: &Frame, state: &HomeostasisState, command: &MotorCommand) -> Vec<String> {
+    let mut fields = Vec::new();
+    // Bit-for-bit, so that even the last place of a float counts
+    let mut real = |name: &str, was: f32, now: f32| {
+        if was.to_bits() != now.to_bits() {
+            fields.push(format!("{} {} → {}", name, was, now));
+        }
+    };
+    let s = &recorded.state;
+    real("tension", s.tension, state.tension);
+    real("coherence", s.coherence, state.coherence);
+    real("energy", s.energy, state.energy);
+    real("curiosity", s.curiosity, state.curiosity);
+
+    let reflex = (format!("{:?}", s.reflex), format!("{:?}", state.reflex));
+    if reflex.0 != reflex.1 {
+        fields.push(format!("reflex {} → {}", reflex.0, reflex.1));
+    }
+    fields.extend(diff_command(&recorded.command, command));
+    fields
+}
+
+/// Command fields that changed between a recording and its replay
+fn diff_command(was: &MotorCommand, now: &MotorCommand) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut other = |name: &str, was: String, now: String| {
+        if was != now {
+            fields.push(format!("{} {} → {}", name, was, now));
+        }
+    };
+    other("left", was.left.to_string(), now.left.to_string());
+    other("right", was.right.to_string(), now.right.to_string());
+    other("pen", was.pen_angle.to_string(), now.pen_angle.to_string());
+    other("led", format!("{:?}", was.led_color), format!("{:?}", now.led_color));
+    other("buzzer", was.buzzer_hz.to_string(), now.buzzer_hz.to_string());
+    fields
+}
+
+/// A recorded session standing in for the robot.
+///
+/// Reads hand out the logged sensor frames in order, and the first command
+/// sent after each read is compared with the one the recorded brain gave
+/// for that frame. The pen is left out, since apps set it after the brain
+/// has answered. Outages in the log are passed over: whatever brain is on
+/// the other end isn't resumed, so it may part from the recording there.
+/// Once the log runs out, reads fail as if the robot had gone away.
+pub struct ReplayLink {
+    records: VecDeque<Record>,
+    /// The frame last read, until a command answers it
+    answering: Option<Frame>,
+    report: ReplayReport,
+    open: bool,
+}
+
+impl ReplayLink {
+    pub fn new(log: SessionLog) -> Self {
+        Self {
+            records: log.records.into(),
+            answering: None,
+            report: ReplayReport {
+                truncated: log.truncated,
+                ..Default::default()
+            },
+            open: true,
+        }
+    }
+
+    /// How the commands sent so far compare with the recording
+    pub fn report(&self) -> &ReplayReport {
+        &self.report
+    }
+}
+
+#[async_trait]
+impl RobotLink for ReplayLink {
+    async fn read_sensors(&mut self) -> Result<MBotSensors> {
+        if !self.open {
+            return Err(closed());
+        }
+        loop {
+            match self.records.pop_front() {
+                Some(Record::Resume) => self.report.resumes += 1,
+                Some(Record::Frame(frame)) => {
+                    let sensors = frame.sensors.clone();
+                    self.answering = Some(frame);
+                    self.report.frames += 1;
+                    return Ok(sensors);
+                }
+                None => bail!("Session log ended after {} frames", self.report.frames),
+            }
+        }
+    }
+
+    async fn send_command(&mut self, cmd: &MotorCommand) -> Result<()> {
+        if !self.open {
+            return Err(closed());
+        }
+        if let Some(recorded) = self.answering.take() {
+            let sent = MotorCommand {
+                pen_angle: recorded.command.pen_angle,
+                ..cmd.clone()
+            };
+            let fields = diff_command(&recorded.command, &sent);
+            if !fields.is_empty() {
+                self.report.diffs.push(FrameDiff {
+                    frame: self.report.frames - 1,
+                    timestamp_us: recorded.sensors.timestamp_us,
+                    fields,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn health(&self) -> LinkHealth {
+        LinkHealth {
+            connected: self.open,
+            freshness: None,
+        }
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        if self.open {
+            self.open = false;
+            if self.report.identical() {
+                info!("📼 Replayed {}", self.report);
+            } else {
+                warn!("📼 Replayed {}", self.report);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::SessionRecorder;
+    use crate::sim::SimWorld;
+    use std::path::Path;
+    use std::time::Duration;
+
+    /// A minute in the simulated room with a two-second outage halfway
+    fn record_session() -> SessionLog {
+        SessionLog::decode(&record_bytes(true)).unwrap()
+    }
+
+    /// A minute in the simulated room, as a log file's bytes
+    fn record_bytes(outage: bool) -> Vec<u8> {
+        let mut world = SimWorld::new();
+        let mut brain = MBotBrain::seeded(Default::default(), 11);
+        let mut recorder = SessionRecorder::new(Vec::new(), &brain.snapshot()).unwrap();
+
+        for tick in 0..1_200u64 {
+            let now = Duration::from_millis(tick * 50);
+            if outage && (600..640).contains(&tick) {
+                world.set_motors(0, 0, now);
+                continue;
+            }
+            if outage && tick == 640 {
+                let snapshot = brain.snapshot();
+                supervisor::resume(&mut brain, &snapshot);
+                recorder.resumed().unwrap();
+            }
+            let sensors = world.sensors(now);
+            let (state, cmd) = brain.tick(&sensors);
+            recorder.frame(&sensors, &state, &cmd).unwrap();
+            world.set_motors(cmd.left, cmd.right, now);
+        }
+        recorder.into_inner().unwrap()
+    }
+
+    /// Drive `brain` from `link` until the log runs out
+    async fn drive(link: &mut dyn RobotLink, brain: &mut MBotBrain) -> usize {
+        let mut ticks = 0;
+        while let Ok(sensors) = link.read_sensors().await {
+            let (_, mut cmd) = brain.tick(&sensors);
+            // The app's own choice, which the link doesn't judge
+            cmd.pen_angle = 0;
+            link.send_command(&cmd).await.unwrap();
+            ticks += 1;
+        }
+        ticks
+    }
+
+    #[test]
+    fn test_replay_is_identical() {
+        let log = record_session();
+        let report = replay(&log);
+        assert_eq!(report.frames, 1_160);
+        assert_eq!(report.resumes, 1);
+        assert!(report.identical(), "{}", report);
+    }
+
+    #[test]
+    fn test_replay_reports_divergence() {
+        let mut log = record_session();
+        // As if the brain had once answered differently
+        if let Record::Frame(frame) = &mut log.records[300] {
+            frame.state.tension += 0.01;
+            frame.command.left = frame.command.left.wrapping_add(1);
+        }
+        let report = replay(&log);
+        assert_eq!(report.diffs.len(), 1);
+        let diff = &report.diffs[0];
+        assert_eq!(diff.frame, 300);
+        assert_eq!(diff.fields.len(), 2);
+        assert!(diff.fields[0].starts_with("tension"));
+        assert!(report.to_string().contains("1 differ"));
+
+        // Skipping the resume changes what the brain makes of the gap
+        log.records.retain(|r| !matches!(r, Record::Resume));
+        assert!(!replay(&log).identical());
+    }
+
+    #[tokio::test]
+    async fn test_replay_link_feeds_a_brain() {
+        let path = std::env::temp_dir().join(format!("mbot-replay-{}.log", std::process::id()));
+        std::fs::write(&path, record_bytes(false)).unwrap();
+        let uri = format!("replay://{}", path.display());
+        let mut link = crate::transport::LinkRegistry::new().connect(&uri).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut brain = MBotBrain::seeded(Default::default(), 11);
+        assert_eq!(drive(link.as_mut(), &mut brain).await, 1_200);
+        let error = link.read_sensors().await.unwrap_err();
+        assert!(error.to_string().contains("ended after 1200 frames"), "{}", error);
+        link.close().await.unwrap();
+        assert!(link.read_sensors().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_replay_link_reports_divergence() {
+        let log = SessionLog::decode(&record_bytes(false)).unwrap();
+
+        let mut link = ReplayLink::new(log.clone());
+        drive(&mut link, &mut MBotBrain::seeded(Default::default(), 11)).await;
+        assert!(link.report().identical(), "{}", link.report());
+        assert_eq!(link.report().frames, 1_200);
+
+        // A different robot reacts differently to the same room
+        let mut link = ReplayLink::new(log);
+        let nervous = mbot_core::PersonalityConfig::NERVOUS_NELLIE;
+        drive(&mut link, &mut MBotBrain::seeded(nervous, 11)).await;
+        let report = link.report();
+        assert!(!report.identical());
+        assert!(report.diffs.iter().all(|d| d.fields.iter().all(|f| !f.starts_with("pen"))));
+    }
+
+    #[tokio::test]
+    async fn test_replay_link_passes_over_outages() {
+        let mut link = ReplayLink::new(record_session());
+        let ticks = drive(&mut link, &mut MBotBrain::seeded(Default::default(), 11)).await;
+        assert_eq!(ticks, 1_160);
+        assert_eq!(link.report().resumes, 1);
+        // Everything up to the outage matches
+        assert!(link.report().diffs.iter().all(|d| d.frame >= 600), "{}", link.report());
+    }
+
+    #[test]
+    fn test_session_corpus_replays_identically() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("sessions");
+        let mut replayed = 0;
+        for entry in std::fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "log") {
+                let report = replay(&SessionLog::read(&path).unwrap());
+                assert!(report.frames > 0 && report.identical(), "{}: {}", path.display(), report);
+                replayed += 1;
+            }
+        }
+        assert!(replayed > 0, "no session logs in {}", dir.display());
+    }
+}
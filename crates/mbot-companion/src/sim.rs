@@ -0,0 +1,346 @@
+//! The simulated robot and its surroundings
+//!
+//! A kinematic differential-drive robot in a `Scene`: motor commands move
+//! it, the ultrasonic is ray-cast against the walls and obstacles, and the
+//! encoders, gyro, accelerometer and quad RGB sensor read what the motion
+//! and the floor under it imply. Shared by the `sim://` link and the
+//! `mbot-emulator` server so both behave the same way.
+//!
+//! World coordinates are centimetres with the arena's corner at the origin,
+//! x to the right and y up; headings are counter-clockwise from +x, as in
+//! the brain.
+
+pub mod noise;
+pub mod scene;
+
+pub use noise::{NoiseConfig, SensorNoise};
+pub use scene::{Scene, Shape};
+
+use mbot_core::{Calibration, MBotSensors};
+use std::f32::consts::PI;
+use std::time::Duration;
+
+/// Simulated encoder ticks per second at 1% motor power
+pub const SIM_TICKS_PER_POWER: f32 = 3.0;
+
+/// Radius of the circle the robot's body fits in
+pub const ROBOT_RADIUS_CM: f32 = 9.0;
+
+/// Furthest the ultrasonic reports; also its reading when nothing echoes
+pub const ULTRASONIC_MAX_CM: f32 = 400.0;
+
+/// The ultrasonic sits this far ahead of the wheel axle
+const ULTRASONIC_OFFSET_CM: f32 = 7.0;
+
+/// Half the width of the ultrasonic's beam, and rays cast across it
+const BEAM_HALF_ANGLE_DEG: f32 = 15.0;
+const BEAM_RAYS: usize = 5;
+
+/// Quad RGB probes as (forward, left) of the axle: front left, front right,
+/// back left, back right
+const RGB_PROBES_CM: [(f32, f32); 4] = [(7.0, 2.0), (7.0, -2.0), (3.0, 2.0), (3.0, -2.0)];
+
+/// Physics steps at most this long, so fast robots don't tunnel into walls
+const STEP_US: u64 = 5_000;
+
+/// The accelerometer's low-pass time constant
+const ACCEL_SMOOTHING_S: f32 = 0.02;
+
+const GRAVITY: f32 = 9.8;
+
+/// Sound from the motors at full power, on top of the room's
+const MOTOR_HUM: f32 = 0.05;
+
+/// Where the simulated robot really is
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SimPose {
+    pub x: f32,
+    pub y: f32,
+    /// Radians counter-clockwise from +x
+    pub heading: f32,
+}
+
+impl SimPose {
+    /// A point `forward` ahead of and `left` of the axle
+    fn offset(&self, forward: f32, left: f32) -> (f32, f32) {
+        let (sin, cos) = self.heading.sin_cos();
+        (self.x + forward * cos - left * sin, self.y + forward * sin + left * cos)
+    }
+}
+
+/// A robot whose wheels follow motor commands, moving around a `Scene`
+pub struct SimWorld {
+    scene: Scene,
+    pose: SimPose,
+    motors: [i8; 2],
+    /// Motor power integrated over time, in %·µs, so encoder counts are exact
+    wheel_power_us: [i64; 2],
+    now_us: u64,
+    speed_cm_s: f32,
+    /// Filtered body-frame acceleration, forward and left, m/s²
+    accel: [f32; 2],
+    blocked: bool,
+    collisions: u64,
+}
+
+impl Default for SimWorld {
+    fn default() -> Self {
+        Self::with_scene(Scene::default())
+    }
+}
+
+impl SimWorld {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_scene(scene: Scene) -> Self {
+        Self {
+            pose: SimPose {
+                x: scene.start.x,
+                y: scene.start.y,
+                heading: scene.start.heading_deg.to_radians(),
+            },
+            scene,
+            motors: [0, 0],
+            wheel_power_us: [0, 0],
+            now_us: 0,
+            speed_cm_s: 0.0,
+            accel: [0.0, 0.0],
+            blocked: false,
+            collisions: 0,
+        }
+    }
+
+    pub fn scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    /// Where the robot really is, as opposed to where it thinks it is
+    pub fn pose(&self) -> SimPose {
+        self.pose
+    }
+
+    /// Times the robot has run into something
+    pub fn collisions(&self) -> u64 {
+        self.collisions
+    }
+
+    /// Change motor power `elapsed` after the world started
+    pub fn set_motors(&mut self, left: i8, right: i8, elapsed: Duration) {
+        self.advance(elapsed);
+        self.motors = [left, right];
+    }
+
+    /// Every sensor `elapsed` after the world started
+    pub fn sensors(&mut self, elapsed: Duration) -> MBotSensors {
+        self.advance(elapsed);
+        let (left_cm_s, right_cm_s) = self.wheel_speeds();
+        let c = Calibration::DEFAULT;
+        let encoder = |power_us: i64| (power_us as f64 * SIM_TICKS_PER_POWER as f64 / 1e6) as i32;
+        let power = (self.motors[0].unsigned_abs() as f32 + self.motors[1].unsigned_abs() as f32) / 200.0;
+        let here = (self.pose.x, self.pose.y);
+        let t = self.now_s();
+
+        MBotSensors {
+            timestamp_us: self.now_us,
+            ultrasonic_cm: self.ultrasonic(),
+            encoder_left: encoder(self.wheel_power_us[0]),
+            encoder_right: encoder(self.wheel_power_us[1]),
+            gyro_z: ((right_cm_s - left_cm_s) / c.wheel_base_cm).to_degrees(),
+            accel: [self.accel[0], self.accel[1], GRAVITY],
+            sound_level: (self.scene.sound.level_at(here, t) + power * MOTOR_HUM).min(1.0),
+            light_level: self.scene.light.level_at(here, t),
+            quad_rgb: RGB_PROBES_CM.map(|(forward, left)| self.scene.floor_at(self.pose.offset(forward, left))),
+        }
+    }
+
+    /// Surface speed of each wheel at the current motor power
+    fn wheel_speeds(&self) -> (f32, f32) {
+        let speed = |power: i8| power as f32 * SIM_TICKS_PER_POWER / Calibration::DEFAULT.ticks_per_cm;
+        (speed(self.motors[0]), speed(self.motors[1]))
+    }
+
+    /// Nearest echo across the beam
+    fn ultrasonic(&self) -> f32 {
+        let origin = self.pose.offset(ULTRASONIC_OFFSET_CM, 0.0);
+        let half = BEAM_HALF_ANGLE_DEG.to_radians();
+        (0..BEAM_RAYS)
+            .map(|i| {
+                let angle = self.pose.heading - half + 2.0 * half * i as f32 / (BEAM_RAYS - 1) as f32;
+                self.scene.ray(origin, (angle.cos(), angle.sin()), self.now_s())
+            })
+            .fold(ULTRASONIC_MAX_CM, f32::min)
+    }
+
+    /// Run the physics up to `elapsed`
+    fn advance(&mut self, elapsed: Duration) {
+        let until_us = elapsed.as_micros() as u64;
+        while self.now_us < until_us {
+            let step_us = (until_us - self.now_us).min(STEP_US);
+            self.step(step_us);
+            self.now_us += step_us;
+        }
+    }
+
+    fn now_s(&self) -> f32 {
+        self.now_us as f32 / 1e6
+    }
+
+    fn step(&mut self, step_us: u64) {
+        let dt = step_us as f32 / 1e6;
+        for (travel, power) in self.wheel_power_us.iter_mut().zip(self.motors) {
+            *travel += power as i64 * step_us as i64;
+        }
+
+        // The simulated robot has exactly the default dimensions
+        let (left, right) = self.wheel_speeds();
+        let forward = (left + right) / 2.0;
+        let turn = (right - left) / Calibration::DEFAULT.wheel_base_cm;
+
+        // Turning on the spot never hits anything; moving is refused if it
+        // would bring the body closer to something it's touching, and the
+        // wheels spin in place
+        self.pose.heading = normalize(self.pose.heading + turn * dt);
+        let t = self.now_s() + dt;
+        let moved = self.pose.offset(forward * dt, 0.0);
+        let clearance = self.scene.clearance(moved, t);
+        let blocked = clearance < ROBOT_RADIUS_CM && clearance < self.scene.clearance((self.pose.x, self.pose.y), t);
+        if blocked && !self.blocked {
+            self.collisions += 1;
+        }
+        self.blocked = blocked;
+        let speed = if blocked {
+            0.0
+        } else {
+            (self.pose.x, self.pose.y) = moved;
+            forward
+        };
+
+        // What an accelerometer riding along feels, in m/s²
+        let along = (speed - self.speed_cm_s) / dt / 100.0;
+        let centripetal = speed * turn / 100.0;
+        self.speed_cm_s = speed;
+        let k = (dt / ACCEL_SMOOTHING_S).min(1.0);
+        self.accel[0] += (along - self.accel[0]) * k;
+        self.accel[1] += (centripetal - self.accel[1]) * k;
+    }
+}
+
+/// Wrap an angle to [-PI, PI]
+fn normalize(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(2.0 * PI) - PI
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scene::{FloorLine, Mover, PathMode};
+
+    fn open_arena() -> Scene {
+        Scene {
+            obstacles: Vec::new(),
+            floor: Vec::new(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_ultrasonic_sees_the_wall_it_drives_at() {
+        let mut world = SimWorld::with_scene(open_arena());
+        // Sensor 7cm ahead of the axle, 200cm from the far wall
+        let first = world.sensors(Duration::ZERO).ultrasonic_cm;
+        assert!((first - 193.0).abs() < 0.5, "{}", first);
+
+        // 50% is 15cm/s
+        world.set_motors(50, 50, Duration::ZERO);
+        let later = world.sensors(Duration::from_secs(4)).ultrasonic_cm;
+        assert!((later - 133.0).abs() < 0.5, "{}", later);
+        assert!((world.pose().x - 260.0).abs() < 0.1);
+        assert_eq!(world.collisions(), 0);
+    }
+
+    #[test]
+    fn test_bumps_into_wall_and_stays() {
+        let mut world = SimWorld::with_scene(open_arena());
+        world.set_motors(100, 100, Duration::ZERO);
+
+        // 30cm/s covers the 191cm to the wall in under 7s
+        let mut bump = 0.0f32;
+        for tick in 1..=200 {
+            let sensors = world.sensors(Duration::from_millis(tick * 50));
+            bump = bump.min(sensors.accel[0]);
+        }
+        let pose = world.pose();
+        assert!((pose.x - (400.0 - ROBOT_RADIUS_CM)).abs() < 0.2, "{:?}", pose);
+        assert_eq!(world.collisions(), 1);
+        assert!(bump < -1.0, "{}", bump);
+
+        // Wheels spin against the wall; backing off frees it
+        let sensors = world.sensors(Duration::from_secs(10));
+        assert_eq!(sensors.encoder_left, 3_000);
+        assert!(sensors.ultrasonic_cm < 3.0);
+        world.set_motors(-50, -50, Duration::from_secs(10));
+        world.sensors(Duration::from_secs(11));
+        assert!(world.pose().x < 400.0 - ROBOT_RADIUS_CM - 10.0);
+        assert_eq!(world.collisions(), 1);
+    }
+
+    #[test]
+    fn test_spin_matches_gyro_and_encoders() {
+        let mut world = SimWorld::with_scene(open_arena());
+        world.set_motors(-30, 30, Duration::ZERO);
+        let sensors = world.sensors(Duration::from_secs(2));
+
+        // 9cm/s each way on a 10cm wheel base: 1.8 rad/s
+        assert_eq!((sensors.encoder_left, sensors.encoder_right), (-180, 180));
+        assert!((sensors.gyro_z - 1.8f32.to_degrees()).abs() < 0.01);
+        assert!((world.pose().heading - 3.6 + 2.0 * PI).abs() < 0.001, "{:?}", world.pose());
+        assert!((world.pose().x - 200.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_quad_rgb_reads_floor_under_each_probe() {
+        let mut scene = open_arena();
+        // A red line under the left-hand probes only
+        scene.lines.push(FloorLine {
+            points: vec![(0.0, 155.0), (400.0, 155.0)],
+            width_cm: 8.0,
+            color: [255, 0, 0],
+        });
+        let mut world = SimWorld::with_scene(scene);
+        let rgb = world.sensors(Duration::ZERO).quad_rgb;
+        assert_eq!(rgb, [[255, 0, 0], [200, 200, 200], [255, 0, 0], [200, 200, 200]]);
+    }
+
+    #[test]
+    fn test_default_room_has_room_to_calibrate() {
+        let mut world = SimWorld::new();
+        world.set_motors(30, 30, Duration::ZERO);
+        world.set_motors(0, 0, Duration::from_secs(3));
+        world.set_motors(-30, 30, Duration::from_secs(3));
+        let sensors = world.sensors(Duration::from_secs(5));
+        assert_eq!(world.collisions(), 0);
+        assert_eq!(sensors.encoder_left, 270 - 180);
+    }
+
+    #[test]
+    fn test_sees_a_mover_cross() {
+        let mut scene = open_arena();
+        // Walks across 100cm ahead of the robot, level with it at 5s
+        scene.movers.push(Mover {
+            shape: Shape::Circle {
+                center: (0.0, 0.0),
+                radius: 10.0,
+            },
+            path: vec![(300.0, 50.0), (300.0, 250.0)],
+            speed_cm_s: 20.0,
+            mode: PathMode::Once,
+        });
+        let mut world = SimWorld::with_scene(scene);
+        assert!(world.sensors(Duration::ZERO).ultrasonic_cm > 190.0);
+        let crossing = world.sensors(Duration::from_secs(5)).ultrasonic_cm;
+        assert!((crossing - 83.0).abs() < 0.5, "{}", crossing);
+        assert!(world.sensors(Duration::from_secs(10)).ultrasonic_cm > 190.0);
+    }
+}
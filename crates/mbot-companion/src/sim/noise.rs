@@ -0,0 +1,362 @@
+//! Imperfect sensors and links for the simulated robot
+//!
+//! Real ultrasonics echo spuriously, encoders slip, gyros drift and BLE
+//! drops frames. `SensorNoise` does the same to the simulator's clean
+//! readings, driven by a seeded `Rng` so a noisy run can be replayed
+//! exactly. It is configured by a scene's `noise` section:
+//!
+//! ```json
+//! "noise": {
+//!   "seed": 7,
+//!   "ultrasonic": { "gaussian": 1.5, "spike": 0.02, "spike_values": [0, 400] },
+//!   "encoders": { "drift": 0.3 },
+//!   "gyro": { "gaussian": 0.5, "drift": 0.02, "stuck": 0.001, "stuck_s": 2 },
+//!   "frame_loss": 0.05,
+//!   "latency_ms": 40
+//! }
+//! ```
+//!
+//! Only the `sim://` link applies it; `mbot-emulator` serves clean readings.
+
+use mbot_core::{MBotSensors, Rng};
+use serde::Deserialize;
+use std::collections::VecDeque;
+
+/// How one sensor reading goes wrong; every chance is per reading
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FieldNoise {
+    /// Standard deviation of noise added to each reading
+    pub gaussian: f32,
+    /// Standard deviation of each step of a random walk added to every
+    /// later reading: gyro bias drift, encoder slip
+    pub drift: f32,
+    /// Chance the reading isn't updated and repeats the last one
+    pub dropout: f32,
+    /// Chance the reading is replaced by one of `spike_values`
+    pub spike: f32,
+    pub spike_values: Vec<f32>,
+    /// Chance the sensor freezes on its current reading for `stuck_s`
+    pub stuck: f32,
+    pub stuck_s: f32,
+}
+
+/// Everything that can go wrong between the world and the brain
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NoiseConfig {
+    pub seed: u64,
+    pub ultrasonic: FieldNoise,
+    /// Both wheels, independently
+    pub encoders: FieldNoise,
+    pub gyro: FieldNoise,
+    /// Each axis independently
+    pub accel: FieldNoise,
+    pub sound: FieldNoise,
+    pub light: FieldNoise,
+    /// Each colour channel of each probe independently
+    pub quad_rgb: FieldNoise,
+    /// Chance a whole frame never arrives
+    pub frame_loss: f32,
+    /// How late frames arrive
+    pub latency_ms: u32,
+}
+
+impl NoiseConfig {
+    /// Check every chance is a probability and every spread is non-negative
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let fields = [
+            ("ultrasonic", &self.ultrasonic),
+            ("encoders", &self.encoders),
+            ("gyro", &self.gyro),
+            ("accel", &self.accel),
+            ("sound", &self.sound),
+            ("light", &self.light),
+            ("quad_rgb", &self.quad_rgb),
+        ];
+        for (name, field) in fields {
+            let chances = [field.dropout, field.spike, field.stuck];
+            let spreads = [field.gaussian, field.drift, field.stuck_s];
+            if !chances.iter().all(|p| (0.0..=1.0).contains(p)) || !spreads.iter().all(|s| (0.0..f32::INFINITY).contains(s)) {
+                anyhow::bail!("{} noise has a chance outside 0.0-1.0 or a negative spread", name);
+            }
+        }
+        if !(0.0..=1.0).contains(&self.frame_loss) {
+            anyhow::bail!("frame_loss {} is outside 0.0-1.0", self.frame_loss);
+        }
+        Ok(())
+    }
+}
+
+/// Readings that make up a frame: ultrasonic, two encoders, gyro, three
+/// accel axes, sound, light and twelve colour channels
+const FIELDS: usize = 21;
+
+/// What one reading's noise remembers between frames
+#[derive(Clone, Copy, Debug, Default)]
+struct FieldState {
+    walk: f32,
+    stuck_until_us: u64,
+    last: f32,
+}
+
+/// Corrupts clean readings as configured
+pub struct SensorNoise {
+    config: NoiseConfig,
+    rng: Rng,
+    fields: [FieldState; FIELDS],
+    /// Frames on their way, with when they arrive
+    in_flight: VecDeque<(u64, MBotSensors)>,
+}
+
+impl SensorNoise {
+    pub fn new(config: NoiseConfig) -> Self {
+        Self {
+            rng: Rng::new(config.seed),
+            config,
+            fields: [FieldState::default(); FIELDS],
+            in_flight: VecDeque::new(),
+        }
+    }
+
+    pub fn config(&self) -> &NoiseConfig {
+        &self.config
+    }
+
+    /// Pass a clean frame read at its timestamp through the noise; returns
+    /// the newest frame to have arrived by then, or None if none has
+    pub fn apply(&mut self, clean: &MBotSensors) -> Option<MBotSensors> {
+        let now_us = clean.timestamp_us;
+        let noisy = self.corrupt(clean);
+        if !self.rng.chance(self.config.frame_loss) {
+            let arrives_us = now_us + self.config.latency_ms as u64 * 1_000;
+            self.in_flight.push_back((arrives_us, noisy));
+        }
+
+        let mut arrived = None;
+        while self.in_flight.front().is_some_and(|(at, _)| *at <= now_us) {
+            arrived = self.in_flight.pop_front().map(|(_, frame)| frame);
+        }
+        arrived
+    }
+
+    fn corrupt(&mut self, clean: &MBotSensors) -> MBotSensors {
+        let now_us = clean.timestamp_us;
+        let c = &self.config;
+        let rng = &mut self.rng;
+        let mut fields = self.fields.iter_mut();
+        let mut read = |noise: &FieldNoise, value: f32| fields.next().unwrap().read(noise, rng, value, now_us);
+
+        let ultrasonic_cm = read(&c.ultrasonic, clean.ultrasonic_cm).clamp(0.0, 400.0);
+        let encoder_left = read(&c.encoders, clean.encoder_left as f32).round() as i32;
+        let encoder_right = read(&c.encoders, clean.encoder_right as f32).round() as i32;
+        let gyro_z = read(&c.gyro, clean.gyro_z);
+        let accel = clean.accel.map(|axis| read(&c.accel, axis));
+        let sound_level = read(&c.sound, clean.sound_level).clamp(0.0, 1.0);
+        let light_level = read(&c.light, clean.light_level).clamp(0.0, 1.0);
+        let quad_rgb = clean
+            .quad_rgb
+            .map(|probe| probe.map(|channel| read(&c.quad_rgb, channel as f32).round().clamp(0.0, 255.0) as u8));
+
+        MBotSensors {
+            timestamp_us: now_us,
+            ultrasonic_cm,
+            encoder_left,
+            encoder_right,
+            quad_rgb,
+            gyro_z,
+            accel,
+            sound_level,
+            light_level,
+        }
+    }
+}
+
+impl FieldState {
+    fn read(&mut self, noise: &FieldNoise, rng: &mut Rng, value: f32, now_us: u64) -> f32 {
+        if now_us < self.stuck_until_us || rng.chance(noise.dropout) {
+            return self.last;
+        }
+        self.walk += gaussian(rng, noise.drift);
+        let mut reading = value + self.walk + gaussian(rng, noise.gaussian);
+        if !noise.spike_values.is_empty() && rng.chance(noise.spike) {
+            reading = noise.spike_values[rng.next_u32() as usize % noise.spike_values.len()];
+        }
+        if rng.chance(noise.stuck) {
+            self.stuck_until_us = now_us + (noise.stuck_s * 1e6) as u64;
+        }
+        self.last = reading;
+        reading
+    }
+}
+
+/// Normally distributed with mean zero (Box-Muller)
+fn gaussian(rng: &mut Rng, sigma: f32) -> f32 {
+    if sigma == 0.0 {
+        return 0.0;
+    }
+    let u1 = 1.0 - rng.next_f32();
+    let u2 = rng.next_f32();
+    sigma * (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::SimWorld;
+    use mbot_core::MBotBrain;
+    use std::time::Duration;
+
+    fn frames(config: NoiseConfig, clean: impl Fn(u64) -> MBotSensors, count: u64) -> Vec<Option<MBotSensors>> {
+        let mut noise = SensorNoise::new(config);
+        (0..count).map(|i| noise.apply(&clean(i))).collect()
+    }
+
+    fn wall(i: u64) -> MBotSensors {
+        MBotSensors {
+            timestamp_us: i * 50_000,
+            ultrasonic_cm: 100.0,
+            encoder_left: i as i32 * 10,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_clean_config_changes_nothing() {
+        let out = frames(NoiseConfig::default(), wall, 100);
+        for (i, frame) in out.into_iter().enumerate() {
+            let frame = frame.unwrap();
+            assert_eq!((frame.ultrasonic_cm, frame.encoder_left), (100.0, i as i32 * 10));
+        }
+    }
+
+    #[test]
+    fn test_same_seed_same_noise() {
+        let config = |seed| NoiseConfig {
+            seed,
+            ultrasonic: FieldNoise {
+                gaussian: 2.0,
+                spike: 0.1,
+                spike_values: vec![0.0, 400.0],
+                ..Default::default()
+            },
+            frame_loss: 0.1,
+            ..Default::default()
+        };
+        let distances = |seed| -> Vec<Option<f32>> {
+            frames(config(seed), wall, 200).into_iter().map(|f| f.map(|f| f.ultrasonic_cm)).collect()
+        };
+        assert_eq!(distances(1), distances(1));
+        assert_ne!(distances(1), distances(2));
+    }
+
+    #[test]
+    fn test_spikes_and_loss_at_configured_rates() {
+        let config = NoiseConfig {
+            ultrasonic: FieldNoise {
+                spike: 0.1,
+                spike_values: vec![0.0, 400.0],
+                ..Default::default()
+            },
+            frame_loss: 0.2,
+            ..Default::default()
+        };
+        let out = frames(config, wall, 5_000);
+        let arrived: Vec<_> = out.iter().flatten().collect();
+        let spikes = arrived.iter().filter(|f| f.ultrasonic_cm != 100.0).count();
+
+        assert!((3_800..4_200).contains(&arrived.len()), "{}", arrived.len());
+        assert!((320..480).contains(&spikes), "{}", spikes);
+        assert!(arrived.iter().all(|f| [0.0, 100.0, 400.0].contains(&f.ultrasonic_cm)));
+    }
+
+    #[test]
+    fn test_stuck_gyro_and_late_frames() {
+        let config = NoiseConfig {
+            gyro: FieldNoise {
+                stuck: 1.0,
+                stuck_s: 0.5,
+                ..Default::default()
+            },
+            latency_ms: 100,
+            ..Default::default()
+        };
+        let turning = |i: u64| MBotSensors {
+            timestamp_us: i * 50_000,
+            gyro_z: i as f32,
+            ..Default::default()
+        };
+        let out = frames(config, turning, 30);
+
+        // Nothing for the first 100ms, then each frame two ticks late
+        assert!(out[0].is_none() && out[1].is_none());
+        assert_eq!(out[2].as_ref().unwrap().timestamp_us, 0);
+        // Freezes for 500ms (10 ticks) at a time
+        let gyro: Vec<f32> = out[2..].iter().map(|f| f.as_ref().unwrap().gyro_z).collect();
+        assert_eq!(&gyro[..12], &[0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 10.0, 10.0]);
+    }
+
+    #[test]
+    fn test_encoder_slip_accumulates() {
+        let config = NoiseConfig {
+            encoders: FieldNoise {
+                drift: 1.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let out = frames(config, wall, 400);
+        let error = |f: &Option<MBotSensors>, i: usize| (f.as_ref().unwrap().encoder_left - i as i32 * 10).abs();
+        // A random walk of 400 unit steps wanders about 20 ticks
+        let wandered = out.iter().enumerate().map(|(i, f)| error(f, i)).max().unwrap();
+        assert!(wandered > 5, "{}", wandered);
+    }
+
+    /// The brain keeps its outputs sane on a robot with every fault at once
+    #[test]
+    fn test_brain_degrades_gracefully() {
+        let field = |gaussian: f32, spike_values: Vec<f32>| FieldNoise {
+            gaussian,
+            drift: gaussian / 10.0,
+            dropout: 0.05,
+            spike: 0.05,
+            spike_values,
+            stuck: 0.005,
+            stuck_s: 1.0,
+        };
+        let config = NoiseConfig {
+            seed: 3,
+            ultrasonic: field(3.0, vec![0.0, 400.0]),
+            encoders: field(2.0, vec![0.0]),
+            gyro: field(2.0, vec![250.0, -250.0]),
+            accel: field(0.5, vec![40.0]),
+            sound: field(0.1, vec![1.0]),
+            light: field(0.1, vec![0.0]),
+            quad_rgb: field(10.0, vec![0.0, 255.0]),
+            frame_loss: 0.1,
+            latency_ms: 60,
+        };
+        let mut world = SimWorld::new();
+        let mut noise = SensorNoise::new(config);
+        let mut brain = MBotBrain::new();
+        let mut last = None;
+
+        for tick in 0..2_400u64 {
+            let now = Duration::from_millis(tick * 50);
+            let clean = world.sensors(now);
+            if let Some(frame) = noise.apply(&clean) {
+                last = Some(frame);
+            }
+            let Some(mut reading) = last.clone() else { continue };
+            reading.timestamp_us = clean.timestamp_us;
+
+            let (state, cmd) = brain.tick(&reading);
+            assert!((0.0..=1.0).contains(&state.tension) && (0.0..=1.0).contains(&state.coherence));
+            assert!(state.energy.is_finite() && state.curiosity.is_finite());
+            assert!((-100..=100).contains(&cmd.left) && (-100..=100).contains(&cmd.right));
+            world.set_motors(cmd.left, cmd.right, now);
+        }
+        let pose = brain.pose();
+        assert!(pose.x.is_finite() && pose.y.is_finite() && pose.heading.is_finite());
+    }
+}
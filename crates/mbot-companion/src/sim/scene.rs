@@ -0,0 +1,645 @@
+//! What the simulated robot's world contains, and the JSON it's loaded from
+//!
+//! A scene file describes the arena, the robot's starting pose, obstacles,
+//! the floor (coloured zones and lines to follow), sound and light sources
+//! with on/off schedules, objects that move along scripted paths, and how
+//! the sensors are corrupted (see `noise`). Only `arena` and `start` are
+//! required:
+//!
+//! ```json
+//! {
+//!   "name": "Cat in the hall",
+//!   "arena": { "width_cm": 300, "height_cm": 120 },
+//!   "start": { "x": 30, "y": 60, "heading_deg": 0 },
+//!   "obstacles": [{ "circle": { "center": [150, 100], "radius": 12 } }],
+//!   "floor": [{ "shape": { "rect": { "min": [250, 0], "max": [300, 120] } }, "color": [30, 30, 200] }],
+//!   "lines": [{ "points": [[20, 20], [280, 20]], "width_cm": 2, "color": [0, 0, 0] }],
+//!   "sound": { "ambient": 0.1, "sources": [{ "position": [300, 60], "level": 0.8, "schedule": { "from_s": 5, "for_s": 1, "every_s": 20 } }] },
+//!   "movers": [{ "shape": { "circle": { "center": [0, 0], "radius": 8 } }, "path": [[100, 20], [100, 100]], "speed_cm_s": 15, "mode": "bounce" }]
+//! }
+//! ```
+//!
+//! Reference scenes live in this crate's `scenes/` directory.
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+
+use super::noise::NoiseConfig;
+use super::ROBOT_RADIUS_CM;
+
+/// A region of the floor or an obstacle's footprint
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Shape {
+    /// Axis-aligned rectangle between two corners
+    Rect { min: (f32, f32), max: (f32, f32) },
+    Circle { center: (f32, f32), radius: f32 },
+}
+
+impl Shape {
+    pub fn contains(&self, p: (f32, f32)) -> bool {
+        self.distance(p) <= 0.0
+    }
+
+    /// Distance from `p` to the outside of the shape; zero or less inside
+    pub fn distance(&self, p: (f32, f32)) -> f32 {
+        match *self {
+            Shape::Rect { min, max } => {
+                let dx = (min.0 - p.0).max(p.0 - max.0);
+                let dy = (min.1 - p.1).max(p.1 - max.1);
+                if dx <= 0.0 && dy <= 0.0 {
+                    dx.max(dy)
+                } else {
+                    dx.max(0.0).hypot(dy.max(0.0))
+                }
+            }
+            Shape::Circle { center, radius } => (p.0 - center.0).hypot(p.1 - center.1) - radius,
+        }
+    }
+
+    /// How far along the unit direction `dir` a ray from `origin` meets the
+    /// shape; zero if it starts inside
+    pub fn ray(&self, origin: (f32, f32), dir: (f32, f32)) -> Option<f32> {
+        match *self {
+            Shape::Rect { min, max } => {
+                let mut near = f32::NEG_INFINITY;
+                let mut far = f32::INFINITY;
+                for (o, d, lo, hi) in [(origin.0, dir.0, min.0, max.0), (origin.1, dir.1, min.1, max.1)] {
+                    if d == 0.0 {
+                        if o < lo || o > hi {
+                            return None;
+                        }
+                    } else {
+                        let (a, b) = ((lo - o) / d, (hi - o) / d);
+                        near = near.max(a.min(b));
+                        far = far.min(a.max(b));
+                    }
+                }
+                (far >= near.max(0.0)).then_some(near.max(0.0))
+            }
+            Shape::Circle { center, radius } => {
+                let (ox, oy) = (origin.0 - center.0, origin.1 - center.1);
+                let along = ox * dir.0 + oy * dir.1;
+                let c = ox * ox + oy * oy - radius * radius;
+                if c <= 0.0 {
+                    return Some(0.0);
+                }
+                let discriminant = along * along - c;
+                (along < 0.0 && discriminant >= 0.0).then(|| -along - discriminant.sqrt())
+            }
+        }
+    }
+
+    /// The same shape moved by `by`
+    pub fn translated(self, by: (f32, f32)) -> Self {
+        let shift = |p: (f32, f32)| (p.0 + by.0, p.1 + by.1);
+        match self {
+            Shape::Rect { min, max } => Shape::Rect {
+                min: shift(min),
+                max: shift(max),
+            },
+            Shape::Circle { center, radius } => Shape::Circle {
+                center: shift(center),
+                radius,
+            },
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        match *self {
+            Shape::Rect { min, max } if !positive(max.0 - min.0) || !positive(max.1 - min.1) => {
+                bail!("rect min {:?} is not below and left of max {:?}", min, max)
+            }
+            Shape::Circle { radius, .. } if !positive(radius) => bail!("circle radius {} is not positive", radius),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A patch of floor in a colour other than the arena's
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FloorZone {
+    pub shape: Shape,
+    pub color: [u8; 3],
+}
+
+/// A line painted on the floor, for line following
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FloorLine {
+    /// Corners of the line, in order; repeat the first to close a loop
+    pub points: Vec<(f32, f32)>,
+    pub width_cm: f32,
+    pub color: [u8; 3],
+}
+
+impl FloorLine {
+    pub fn contains(&self, p: (f32, f32)) -> bool {
+        self.points
+            .windows(2)
+            .any(|pair| segment_distance(p, pair[0], pair[1]) <= self.width_cm / 2.0)
+    }
+}
+
+/// The walled rectangle everything happens in, corner at the origin
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Arena {
+    pub width_cm: f32,
+    pub height_cm: f32,
+}
+
+/// Where the robot is put down
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Start {
+    pub x: f32,
+    pub y: f32,
+    /// Counter-clockwise from +x
+    #[serde(default)]
+    pub heading_deg: f32,
+}
+
+/// When a source is on
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schedule {
+    /// First switched on this long after the start
+    #[serde(default)]
+    pub from_s: f32,
+    /// Stays on this long
+    pub for_s: f32,
+    /// Comes back on this often; once only if absent
+    #[serde(default)]
+    pub every_s: Option<f32>,
+}
+
+impl Schedule {
+    pub fn is_on(&self, t_s: f32) -> bool {
+        let since = t_s - self.from_s;
+        if since < 0.0 {
+            return false;
+        }
+        let phase = self.every_s.map_or(since, |every| since % every);
+        phase < self.for_s
+    }
+}
+
+/// Something loud or bright at a point
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Source {
+    pub position: (f32, f32),
+    /// 0.0-1.0, as heard or seen from within `radius_cm`
+    pub level: f32,
+    /// Beyond this the level falls with the square of distance
+    #[serde(default = "Source::default_radius")]
+    pub radius_cm: f32,
+    /// Always on if absent
+    #[serde(default)]
+    pub schedule: Option<Schedule>,
+}
+
+impl Source {
+    fn default_radius() -> f32 {
+        50.0
+    }
+
+    /// What the robot senses of this source from `p` at `t_s`
+    pub fn level_at(&self, p: (f32, f32), t_s: f32) -> f32 {
+        if self.schedule.is_some_and(|s| !s.is_on(t_s)) {
+            return 0.0;
+        }
+        let distance = (p.0 - self.position.0).hypot(p.1 - self.position.1);
+        let falloff = (self.radius_cm / distance.max(self.radius_cm)).powi(2);
+        self.level * falloff
+    }
+}
+
+/// Background level of a sense plus sources of it
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Ambience {
+    pub ambient: f32,
+    #[serde(default)]
+    pub sources: Vec<Source>,
+}
+
+impl Ambience {
+    fn quiet() -> Self {
+        Self {
+            ambient: 0.1,
+            sources: Vec::new(),
+        }
+    }
+
+    fn lit() -> Self {
+        Self {
+            ambient: 0.5,
+            sources: Vec::new(),
+        }
+    }
+
+    /// Everything sensed from `p` at `t_s`, 0.0-1.0
+    pub fn level_at(&self, p: (f32, f32), t_s: f32) -> f32 {
+        let sources: f32 = self.sources.iter().map(|s| s.level_at(p, t_s)).sum();
+        (self.ambient + sources).min(1.0)
+    }
+
+    fn validate(&self) -> Result<()> {
+        check_level("ambient", self.ambient)?;
+        for source in &self.sources {
+            check_level("source level", source.level)?;
+            if !positive(source.radius_cm) {
+                bail!("source radius {} is not positive", source.radius_cm);
+            }
+            if let Some(schedule) = source.schedule {
+                if schedule.for_s < 0.0 || schedule.every_s.is_some_and(|every| !positive(every)) {
+                    bail!("schedule {:?} has a negative duration or zero period", schedule);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How a mover carries on once it reaches the end of its path
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PathMode {
+    /// Back to the first point and round again
+    #[default]
+    Loop,
+    /// Back and forth along the path
+    Bounce,
+    /// Stop at the last point
+    Once,
+}
+
+/// An obstacle that moves along a scripted path, like a cat or a foot
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Mover {
+    /// Footprint with the path point at the origin
+    pub shape: Shape,
+    pub path: Vec<(f32, f32)>,
+    pub speed_cm_s: f32,
+    #[serde(default)]
+    pub mode: PathMode,
+}
+
+impl Mover {
+    /// Where the path has taken it by `t_s`
+    pub fn position(&self, t_s: f32) -> (f32, f32) {
+        let mut points = self.path.clone();
+        if self.mode == PathMode::Loop {
+            points.extend(self.path.first().copied());
+        }
+        let length: f32 = points.windows(2).map(|pair| dist(pair[0], pair[1])).sum();
+        let travelled = self.speed_cm_s * t_s.max(0.0);
+        let mut along = match self.mode {
+            _ if length == 0.0 => 0.0,
+            PathMode::Loop => travelled % length,
+            PathMode::Bounce => length - (travelled % (2.0 * length) - length).abs(),
+            PathMode::Once => travelled.min(length),
+        };
+        for pair in points.windows(2) {
+            let segment = dist(pair[0], pair[1]);
+            if along <= segment && segment > 0.0 {
+                let k = along / segment;
+                return (pair[0].0 + (pair[1].0 - pair[0].0) * k, pair[0].1 + (pair[1].1 - pair[0].1) * k);
+            }
+            along -= segment;
+        }
+        points.last().copied().unwrap_or_default()
+    }
+
+    /// Its footprint at `t_s`
+    pub fn shape_at(&self, t_s: f32) -> Shape {
+        self.shape.translated(self.position(t_s))
+    }
+}
+
+/// Where the robot is and what surrounds it
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scene {
+    #[serde(default)]
+    pub name: String,
+    pub arena: Arena,
+    pub start: Start,
+    /// Things the robot bumps into and the ultrasonic sees
+    #[serde(default)]
+    pub obstacles: Vec<Shape>,
+    #[serde(default = "Scene::default_floor_color")]
+    pub floor_color: [u8; 3],
+    /// Coloured floor; later zones are painted over earlier ones, and lines
+    /// over all of them
+    #[serde(default)]
+    pub floor: Vec<FloorZone>,
+    #[serde(default)]
+    pub lines: Vec<FloorLine>,
+    #[serde(default = "Ambience::quiet")]
+    pub sound: Ambience,
+    #[serde(default = "Ambience::lit")]
+    pub light: Ambience,
+    #[serde(default)]
+    pub movers: Vec<Mover>,
+    /// Clean sensors if absent
+    #[serde(default)]
+    pub noise: NoiseConfig,
+}
+
+impl Default for Scene {
+    /// A 4m x 3m room with a box, a bin and a red rug, the robot in the
+    /// middle facing the far wall
+    fn default() -> Self {
+        Self {
+            name: "Living room".to_string(),
+            arena: Arena {
+                width_cm: 400.0,
+                height_cm: 300.0,
+            },
+            start: Start {
+                x: 200.0,
+                y: 150.0,
+                heading_deg: 0.0,
+            },
+            obstacles: vec![
+                Shape::Rect {
+                    min: (60.0, 40.0),
+                    max: (120.0, 90.0),
+                },
+                Shape::Circle {
+                    center: (320.0, 230.0),
+                    radius: 20.0,
+                },
+            ],
+            floor_color: Self::default_floor_color(),
+            floor: vec![FloorZone {
+                shape: Shape::Rect {
+                    min: (260.0, 40.0),
+                    max: (340.0, 100.0),
+                },
+                color: [200, 30, 30],
+            }],
+            lines: Vec::new(),
+            sound: Ambience::quiet(),
+            light: Ambience::lit(),
+            movers: Vec::new(),
+            noise: NoiseConfig::default(),
+        }
+    }
+}
+
+impl Scene {
+    fn default_floor_color() -> [u8; 3] {
+        [200, 200, 200]
+    }
+
+    /// Load and check a scene file
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path).with_context(|| format!("Failed to read scene: {}", path.display()))?;
+        let scene: Scene =
+            serde_json::from_slice(&bytes).with_context(|| format!("Invalid scene: {}", path.display()))?;
+        scene
+            .validate()
+            .with_context(|| format!("Unusable scene: {}", path.display()))?;
+        Ok(scene)
+    }
+
+    /// Check the scene makes sense and the robot fits where it starts
+    pub fn validate(&self) -> Result<()> {
+        let Arena { width_cm, height_cm } = self.arena;
+        if !positive(width_cm - 2.0 * ROBOT_RADIUS_CM) || !positive(height_cm - 2.0 * ROBOT_RADIUS_CM) {
+            bail!("arena {}x{}cm is too small for the robot", width_cm, height_cm);
+        }
+        for shape in &self.obstacles {
+            shape.validate()?;
+        }
+        for zone in &self.floor {
+            zone.shape.validate()?;
+        }
+        for line in &self.lines {
+            if line.points.len() < 2 || !positive(line.width_cm) {
+                bail!("a line needs at least two points and a positive width");
+            }
+        }
+        for mover in &self.movers {
+            mover.shape.validate()?;
+            if mover.path.is_empty() || !(mover.speed_cm_s == 0.0 || positive(mover.speed_cm_s)) {
+                bail!("a mover needs a path and a speed of zero or more");
+            }
+        }
+        self.sound.validate().context("sound")?;
+        self.light.validate().context("light")?;
+        self.noise.validate()?;
+
+        let start = (self.start.x, self.start.y);
+        let clearance = self.clearance(start, 0.0);
+        if clearance < ROBOT_RADIUS_CM {
+            bail!("the robot doesn't fit at its start {:?}: {:.1}cm from something", start, clearance);
+        }
+        Ok(())
+    }
+
+    /// Everything solid at `t_s`
+    pub fn obstacles_at(&self, t_s: f32) -> impl Iterator<Item = Shape> + '_ {
+        self.obstacles
+            .iter()
+            .copied()
+            .chain(self.movers.iter().map(move |m| m.shape_at(t_s)))
+    }
+
+    /// How far `p` is from the nearest wall or obstacle at `t_s`
+    pub fn clearance(&self, p: (f32, f32), t_s: f32) -> f32 {
+        let walls = p.0.min(p.1).min(self.arena.width_cm - p.0).min(self.arena.height_cm - p.1);
+        self.obstacles_at(t_s).map(|o| o.distance(p)).fold(walls, f32::min)
+    }
+
+    /// How far a ray from `origin` along `dir` travels at `t_s` before it
+    /// hits anything, walls included
+    pub fn ray(&self, origin: (f32, f32), dir: (f32, f32), t_s: f32) -> f32 {
+        let exit = |o: f32, d: f32, size: f32| match d {
+            d if d > 0.0 => (size - o) / d,
+            d if d < 0.0 => -o / d,
+            _ => f32::INFINITY,
+        };
+        let walls = exit(origin.0, dir.0, self.arena.width_cm).min(exit(origin.1, dir.1, self.arena.height_cm));
+        self.obstacles_at(t_s)
+            .filter_map(|o| o.ray(origin, dir))
+            .fold(walls.max(0.0), f32::min)
+    }
+
+    /// Colour of the floor at `p`
+    pub fn floor_at(&self, p: (f32, f32)) -> [u8; 3] {
+        if let Some(line) = self.lines.iter().rev().find(|line| line.contains(p)) {
+            return line.color;
+        }
+        self.floor
+            .iter()
+            .rev()
+            .find(|zone| zone.shape.contains(p))
+            .map_or(self.floor_color, |zone| zone.color)
+    }
+}
+
+fn dist(a: (f32, f32), b: (f32, f32)) -> f32 {
+    (b.0 - a.0).hypot(b.1 - a.1)
+}
+
+/// Distance from `p` to the segment `a`-`b`
+fn segment_distance(p: (f32, f32), a: (f32, f32), b: (f32, f32)) -> f32 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length_sq = dx * dx + dy * dy;
+    let k = if length_sq == 0.0 {
+        0.0
+    } else {
+        (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / length_sq).clamp(0.0, 1.0)
+    };
+    dist(p, (a.0 + dx * k, a.1 + dy * k))
+}
+
+/// Above zero and not NaN or infinite
+fn positive(x: f32) -> bool {
+    x > 0.0 && x.is_finite()
+}
+
+fn check_level(name: &str, level: f32) -> Result<()> {
+    if !(0.0..=1.0).contains(&level) {
+        bail!("{} {} is outside 0.0-1.0", name, level);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene(json: &str) -> Result<Scene> {
+        let scene: Scene = serde_json::from_str(json)?;
+        scene.validate()?;
+        Ok(scene)
+    }
+
+    #[test]
+    fn test_ray_meets_shapes() {
+        let rect = Shape::Rect {
+            min: (10.0, -5.0),
+            max: (20.0, 5.0),
+        };
+        assert_eq!(rect.ray((0.0, 0.0), (1.0, 0.0)), Some(10.0));
+        assert_eq!(rect.ray((0.0, 0.0), (-1.0, 0.0)), None);
+        assert_eq!(rect.ray((0.0, 10.0), (1.0, 0.0)), None);
+        assert_eq!(rect.ray((15.0, 0.0), (0.0, 1.0)), Some(0.0));
+
+        let circle = Shape::Circle {
+            center: (0.0, 30.0),
+            radius: 10.0,
+        };
+        assert!((circle.ray((0.0, 0.0), (0.0, 1.0)).unwrap() - 20.0).abs() < 1e-4);
+        assert_eq!(circle.ray((0.0, 0.0), (0.0, -1.0)), None);
+        assert_eq!(circle.ray((20.0, 0.0), (0.0, 1.0)), None);
+        assert!((circle.distance((0.0, 0.0)) - 20.0).abs() < 1e-4);
+        assert!((rect.distance((23.0, 9.0)) - 5.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_minimal_scene_gets_defaults() {
+        let scene = scene(r#"{ "arena": { "width_cm": 100, "height_cm": 80 }, "start": { "x": 50, "y": 40 } }"#).unwrap();
+        assert_eq!(scene.start.heading_deg, 0.0);
+        assert!(scene.obstacles.is_empty() && scene.movers.is_empty());
+        assert_eq!(scene.floor_at((10.0, 10.0)), [200, 200, 200]);
+        assert_eq!(scene.sound.level_at((0.0, 0.0), 0.0), 0.1);
+        assert_eq!(scene.light.level_at((0.0, 0.0), 0.0), 0.5);
+    }
+
+    #[test]
+    fn test_rejects_bad_scenes() {
+        let arena = r#""arena": { "width_cm": 100, "height_cm": 80 }"#;
+        let bad = [
+            // Typo
+            format!(r#"{{ {arena}, "start": {{ "x": 50, "y": 40 }}, "obstacle": [] }}"#),
+            // Starts inside a box
+            format!(r#"{{ {arena}, "start": {{ "x": 50, "y": 40 }}, "obstacles": [{{ "rect": {{ "min": [45, 35], "max": [60, 50] }} }}] }}"#),
+            // Against the wall
+            format!(r#"{{ {arena}, "start": {{ "x": 5, "y": 40 }} }}"#),
+            format!(r#"{{ {arena}, "start": {{ "x": 50, "y": 40 }}, "sound": {{ "ambient": 1.5 }} }}"#),
+            format!(r#"{{ {arena}, "start": {{ "x": 50, "y": 40 }}, "movers": [{{ "shape": {{ "circle": {{ "center": [0, 0], "radius": 5 }} }}, "path": [], "speed_cm_s": 5 }}] }}"#),
+        ];
+        for json in bad {
+            assert!(scene(&json).is_err(), "{}", json);
+        }
+    }
+
+    #[test]
+    fn test_sources_follow_schedule_and_distance() {
+        let source = Source {
+            position: (0.0, 0.0),
+            level: 0.8,
+            radius_cm: 50.0,
+            schedule: Some(Schedule {
+                from_s: 5.0,
+                for_s: 1.0,
+                every_s: Some(10.0),
+            }),
+        };
+        assert_eq!(source.level_at((10.0, 0.0), 4.9), 0.0);
+        assert_eq!(source.level_at((10.0, 0.0), 5.5), 0.8);
+        assert_eq!(source.level_at((100.0, 0.0), 15.5), 0.2);
+        assert_eq!(source.level_at((10.0, 0.0), 16.5), 0.0);
+    }
+
+    #[test]
+    fn test_movers_follow_their_paths() {
+        let mover = |mode| Mover {
+            shape: Shape::Circle {
+                center: (0.0, 0.0),
+                radius: 5.0,
+            },
+            path: vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)],
+            speed_cm_s: 5.0,
+            mode,
+        };
+        let near = |a: (f32, f32), b: (f32, f32)| dist(a, b) < 1e-3;
+
+        assert!(near(mover(PathMode::Once).position(3.0), (10.0, 5.0)));
+        assert!(near(mover(PathMode::Once).position(30.0), (10.0, 10.0)));
+        assert!(near(mover(PathMode::Bounce).position(5.0), (10.0, 5.0)));
+        // Loops close back to the start: 20cm out, ~14.1cm back
+        assert!(near(mover(PathMode::Loop).position(5.0), (10.0 - 5.0 / 2f32.sqrt(), 10.0 - 5.0 / 2f32.sqrt())));
+        let cat = mover(PathMode::Loop).shape_at(1.0);
+        assert!(cat.contains((5.0, 4.0)) && !cat.contains((5.0, 6.0)));
+    }
+
+    #[test]
+    fn test_lines_paint_over_zones() {
+        let scene = scene(
+            r#"{
+                "arena": { "width_cm": 100, "height_cm": 100 },
+                "start": { "x": 50, "y": 50 },
+                "floor": [{ "shape": { "circle": { "center": [20, 20], "radius": 15 } }, "color": [0, 200, 0] }],
+                "lines": [{ "points": [[0, 20], [100, 20]], "width_cm": 2, "color": [0, 0, 0] }]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(scene.floor_at((20.0, 20.5)), [0, 0, 0]);
+        assert_eq!(scene.floor_at((20.0, 25.0)), [0, 200, 0]);
+        assert_eq!(scene.floor_at((50.0, 25.0)), [200, 200, 200]);
+    }
+
+    #[test]
+    fn test_reference_scenes_load() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        let mut loaded = 0;
+        for entry in std::fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            let scene = Scene::load(&path).unwrap();
+            assert!(!scene.name.is_empty(), "{}", path.display());
+            loaded += 1;
+        }
+        assert!(loaded >= 3);
+    }
+}
@@ -0,0 +1,660 @@
+//! Keeping the link to the robot alive
+//!
+//! The supervisor sits between the control loop and the transport. Reads
+//! and writes that fail, hang, or stop bringing fresh data count as a lost
+//! link: the robot is told to stop (if it can still hear us), the link is
+//! closed, and reconnection is retried with exponential backoff while the
+//! loop carries on. The brain is paused for the outage and resumed from a
+//! snapshot, so the robot keeps its pose and mood across a reconnect.
+
+use anyhow::{anyhow, Result};
+use mbot_core::{BrainSnapshot, MBotBrain, MBotSensors, MotorCommand, PEN_UP};
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::{timeout, Instant};
+use tracing::{info, warn};
+
+use crate::transport::{ConnectFuture, LinkHealth, LinkRegistry, MBotTransport};
+
+/// Opens a fresh link each time it's called
+pub type Reconnect = Box<dyn FnMut() -> ConnectFuture + Send>;
+
+/// Motors off, pen up
+const STOP: MotorCommand = MotorCommand {
+    left: 0,
+    right: 0,
+    pen_angle: PEN_UP,
+    led_color: [0, 0, 0],
+    buzzer_hz: 0,
+};
+
+/// When to give up on a link and how eagerly to retry
+#[derive(Clone, Copy, Debug)]
+pub struct SupervisorConfig {
+    /// Longest a single read or write may take
+    pub io_timeout: Duration,
+    /// Longest a single reconnection attempt may take. Opening a link is
+    /// much slower than using one: a BLE link scans for up to 10s before it
+    /// even connects, and a TCP connect may take 3s.
+    pub connect_timeout: Duration,
+    /// Longest the robot may go without answering a single sensor read
+    pub silence_timeout: Duration,
+    /// Wait before the first reconnection attempt, doubled after each failure
+    pub backoff_min: Duration,
+    pub backoff_max: Duration,
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        Self {
+            io_timeout: Duration::from_millis(500),
+            connect_timeout: Duration::from_secs(20),
+            silence_timeout: Duration::from_secs(1),
+            backoff_min: Duration::from_millis(250),
+            backoff_max: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Where the link stands
+#[derive(Clone, Debug, PartialEq)]
+pub enum LinkState {
+    Connected,
+    Reconnecting {
+        /// Failed attempts since the link was lost
+        attempts: u32,
+        /// When the next attempt is due
+        retry_at: Instant,
+    },
+}
+
+/// Link state and history, for the status display
+#[derive(Clone, Debug)]
+pub struct LinkStatus {
+    pub state: LinkState,
+    pub reconnects: u32,
+    pub last_error: Option<String>,
+    pub health: Option<LinkHealth>,
+}
+
+impl fmt::Display for LinkStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.state {
+            LinkState::Connected => {
+                write!(f, "🔗 Link: connected")?;
+                if self.reconnects > 0 {
+                    write!(f, " ({} reconnects)", self.reconnects)?;
+                }
+                Ok(())
+            }
+            LinkState::Reconnecting { attempts, retry_at } => {
+                let wait = retry_at.saturating_duration_since(Instant::now());
+                write!(
+                    f,
+                    "❌ Link lost: {}  │  retry #{} in {:.1}s",
+                    self.last_error.as_deref().unwrap_or("unknown error"),
+                    attempts + 1,
+                    wait.as_secs_f32()
+                )
+            }
+        }
+    }
+}
+
+/// Watches a link and replaces it when it fails
+pub struct LinkSupervisor {
+    config: SupervisorConfig,
+    transport: Option<MBotTransport>,
+    reconnect: Reconnect,
+    state: LinkState,
+    reconnects: u32,
+    last_error: Option<String>,
+    /// When the robot last answered anything
+    last_heard: Instant,
+    /// Newest per-field read time seen, to tell fresh data from repeats
+    newest_update_us: Option<u64>,
+}
+
+impl LinkSupervisor {
+    /// Supervise `transport`, reopening it with `reconnect` when it fails
+    pub fn new(transport: MBotTransport, reconnect: Reconnect, config: SupervisorConfig) -> Self {
+        Self {
+            config,
+            transport: Some(transport),
+            reconnect,
+            state: LinkState::Connected,
+            reconnects: 0,
+            last_error: None,
+            last_heard: Instant::now(),
+            newest_update_us: None,
+        }
+    }
+
+    /// Supervise `transport`, reopening `uri` with the built-in links
+    pub fn for_uri(transport: MBotTransport, uri: &str, config: SupervisorConfig) -> Self {
+        let uri = uri.to_string();
+        let reconnect: Reconnect = Box::new(move || {
+            let uri = uri.clone();
+            Box::pin(async move { LinkRegistry::new().connect(&uri).await })
+        });
+        Self::new(transport, reconnect, config)
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state == LinkState::Connected
+    }
+
+    pub fn status(&self) -> LinkStatus {
+        LinkStatus {
+            state: self.state.clone(),
+            reconnects: self.reconnects,
+            last_error: self.last_error.clone(),
+            health: self.transport.as_ref().map(MBotTransport::health),
+        }
+    }
+
+    /// Latest sensors, or None while the link is down.
+    ///
+    /// A reconnection attempt is made here when one is due. A polling link
+    /// also gives None until it has read both encoders: odometry measures
+    /// from the first count, and a placeholder 0 would turn into a jump.
+    pub async fn read_sensors(&mut self) -> Option<MBotSensors> {
+        if !self.is_connected() && !self.try_reconnect().await {
+            return None;
+        }
+        let transport = self.transport.as_mut()?;
+        let result = with_timeout(self.config.io_timeout, transport.read_sensors()).await;
+        match result {
+            Ok(sensors) => {
+                let health = transport.health();
+                if !self.heard_from(&health) {
+                    let silence = self.config.silence_timeout;
+                    self.lost(anyhow!("No answer from the robot for {:?}", silence)).await;
+                    return None;
+                }
+                health.encoders_read().then_some(sensors)
+            }
+            Err(e) => {
+                self.lost(e).await;
+                None
+            }
+        }
+    }
+
+    /// Send a command; false if the link is down or just failed
+    pub async fn send_command(&mut self, cmd: &MotorCommand) -> bool {
+        let Some(transport) = self.transport.as_mut().filter(|_| self.state == LinkState::Connected)
+        else {
+            return false;
+        };
+        match with_timeout(self.config.io_timeout, transport.send_command(cmd)).await {
+            Ok(()) => true,
+            Err(e) => {
+                self.lost(e).await;
+                false
+            }
+        }
+    }
+
+    /// Stop the robot and close the link for good
+    pub async fn close(&mut self) -> Result<()> {
+        match self.transport.take() {
+            Some(mut transport) => with_timeout(self.config.io_timeout, transport.close()).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the robot has answered recently; updates the silence clock
+    fn heard_from(&mut self, health: &LinkHealth) -> bool {
+        let now = Instant::now();
+        match &health.freshness {
+            // Polling links: only a newly read field counts
+            Some(freshness) => {
+                let newest = freshness.newest_us();
+                if newest.is_some() && newest != self.newest_update_us {
+                    self.newest_update_us = newest;
+                    self.last_heard = now;
+                }
+            }
+            // Anything else answered just by returning
+            None => self.last_heard = now,
+        }
+        now.duration_since(self.last_heard) <= self.config.silence_timeout
+    }
+
+    /// Give up on the current link: stop the robot if possible and start
+    /// reconnecting
+    async fn lost(&mut self, error: anyhow::Error) {
+        warn!("🔌 Link lost: {:#}", error);
+        self.last_error = Some(format!("{:#}", error));
+        if let Some(mut transport) = self.transport.take() {
+            // Best effort: the link may be too far gone to carry either
+            let stop = with_timeout(self.config.io_timeout, transport.send_command(&STOP)).await;
+            if let Err(e) = stop {
+                warn!("Could not stop the robot: {:#}", e);
+            }
+            let _ = with_timeout(self.config.io_timeout, transport.close()).await;
+        }
+        self.state = LinkState::Reconnecting {
+            attempts: 0,
+            retry_at: Instant::now() + self.config.backoff_min,
+        };
+    }
+
+    /// Reconnect if an attempt is due; true once connected again
+    async fn try_reconnect(&mut self) -> bool {
+        let LinkState::Reconnecting { attempts, retry_at } = self.state else {
+            return true;
+        };
+        if Instant::now() < retry_at {
+            return false;
+        }
+
+        match with_timeout(self.config.connect_timeout, (self.reconnect)()).await {
+            Ok(link) => {
+                info!("🔗 Link restored after {} failed attempts", attempts);
+                self.transport = Some(MBotTransport::from_link(link));
+                self.state = LinkState::Connected;
+                self.reconnects += 1;
+                self.last_heard = Instant::now();
+                self.newest_update_us = None;
+                true
+            }
+            Err(e) => {
+                let attempts = attempts + 1;
+                let backoff = self
+                    .config
+                    .backoff_min
+                    .saturating_mul(1 << attempts.min(16))
+                    .min(self.config.backoff_max);
+                warn!("Reconnect attempt {} failed: {:#}", attempts, e);
+                self.last_error = Some(format!("{:#}", e));
+                self.state = LinkState::Reconnecting {
+                    attempts,
+                    retry_at: Instant::now() + backoff,
+                };
+                false
+            }
+        }
+    }
+}
+
+/// Run `io`, failing if it takes longer than `limit`
+async fn with_timeout<T>(limit: Duration, io: impl Future<Output = Result<T>>) -> Result<T> {
+    timeout(limit, io)
+        .await
+        .map_err(|_| anyhow!("Link timed out after {:?}", limit))?
+}
+
+/// Put the brain back as it was when the link dropped.
+///
+/// The robot may have restarted, so its clock and encoder counts are taken
+/// afresh from the next reading instead of being compared with the old ones.
+pub fn resume(brain: &mut MBotBrain, snapshot: &BrainSnapshot) {
+    let snapshot = BrainSnapshot {
+        last_timestamp_us: None,
+        ..snapshot.clone()
+    };
+    brain.restore(&snapshot);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::RobotLink;
+    use async_trait::async_trait;
+    use mbot_core::NOMINAL_TICK_US;
+    use std::sync::{Arc, Mutex};
+
+    /// What the fake robot saw and what it should do next
+    #[derive(Default)]
+    struct Script {
+        /// Reads and sends that succeed before the link breaks
+        ops_until_failure: Option<u32>,
+        /// Reads never return, though commands still get through
+        hang: bool,
+        /// Connection attempts to refuse
+        refuse_connects: u32,
+        /// How long each connection attempt takes
+        connect_delay: Duration,
+        connects: u32,
+        /// (left, right, pen) of each command received
+        commands: Vec<(i8, i8, u8)>,
+        closes: u32,
+        /// Encoder count the robot reports; a restarted robot starts again at 0
+        ticks: i32,
+    }
+
+    type Shared = Arc<Mutex<Script>>;
+
+    /// A link that breaks when the script says so
+    struct FlakyLink {
+        script: Shared,
+        broken: bool,
+    }
+
+    impl FlakyLink {
+        fn op(&mut self) -> Result<()> {
+            let mut script = self.script.lock().unwrap();
+            if self.broken {
+                return Err(anyhow!("link is broken"));
+            }
+            match &mut script.ops_until_failure {
+                Some(0) => {
+                    self.broken = true;
+                    Err(anyhow!("cable pulled"))
+                }
+                Some(n) => {
+                    *n -= 1;
+                    Ok(())
+                }
+                None => Ok(()),
+            }
+        }
+
+        fn hangs(&self) -> bool {
+            self.script.lock().unwrap().hang
+        }
+    }
+
+    #[async_trait]
+    impl RobotLink for FlakyLink {
+        async fn read_sensors(&mut self) -> Result<MBotSensors> {
+            if self.hangs() {
+                std::future::pending::<()>().await;
+            }
+            self.op()?;
+            let mut script = self.script.lock().unwrap();
+            script.ticks += 10;
+            Ok(MBotSensors {
+                timestamp_us: script.ticks as u64 * 5_000,
+                encoder_left: script.ticks,
+                encoder_right: script.ticks,
+                ultrasonic_cm: 100.0,
+                ..Default::default()
+            })
+        }
+
+        async fn send_command(&mut self, cmd: &MotorCommand) -> Result<()> {
+            self.op()?;
+            self.script.lock().unwrap().commands.push((cmd.left, cmd.right, cmd.pen_angle));
+            Ok(())
+        }
+
+        fn health(&self) -> LinkHealth {
+            LinkHealth {
+                connected: !self.broken,
+                freshness: None,
+            }
+        }
+
+        async fn close(&mut self) -> Result<()> {
+            self.script.lock().unwrap().closes += 1;
+            Ok(())
+        }
+    }
+
+    fn flaky(script: &Shared) -> Box<dyn RobotLink> {
+        script.lock().unwrap().connects += 1;
+        Box::new(FlakyLink {
+            script: script.clone(),
+            broken: false,
+        })
+    }
+
+    fn supervisor(script: &Shared) -> LinkSupervisor {
+        let first = MBotTransport::from_link(flaky(script));
+        let shared = script.clone();
+        let reconnect: Reconnect = Box::new(move || {
+            let script = shared.clone();
+            Box::pin(async move {
+                let delay = script.lock().unwrap().connect_delay;
+                tokio::time::sleep(delay).await;
+                {
+                    let mut s = script.lock().unwrap();
+                    if s.refuse_connects > 0 {
+                        s.refuse_connects -= 1;
+                        return Err(anyhow!("robot not found"));
+                    }
+                    // Back from a restart, healthy and counting from zero
+                    s.ops_until_failure = None;
+                    s.hang = false;
+                    s.ticks = 0;
+                }
+                Ok(flaky(&script))
+            })
+        });
+        LinkSupervisor::new(first, reconnect, SupervisorConfig::default())
+    }
+
+    const FORWARD: MotorCommand = MotorCommand {
+        left: 60,
+        right: 60,
+        pen_angle: PEN_UP,
+        led_color: [0, 0, 0],
+        buzzer_hz: 0,
+    };
+
+    #[tokio::test(start_paused = true)]
+    async fn test_disconnect_mid_tick_stops_and_reconnects() {
+        let script: Shared = Arc::default();
+        let mut sup = supervisor(&script);
+
+        // Read fine, then the cable goes between read and command
+        script.lock().unwrap().ops_until_failure = Some(1);
+        assert!(sup.read_sensors().await.is_some());
+        assert!(!sup.send_command(&FORWARD).await);
+        assert!(!sup.is_connected());
+        {
+            let s = script.lock().unwrap();
+            assert_eq!(s.closes, 1);
+            // The stop couldn't get through a broken link, and nothing else did
+            assert!(s.commands.is_empty());
+        }
+        assert!(sup.status().to_string().contains("cable pulled"));
+
+        // Nothing happens until the backoff has passed
+        assert!(sup.read_sensors().await.is_none());
+        assert_eq!(script.lock().unwrap().connects, 1);
+
+        tokio::time::advance(SupervisorConfig::default().backoff_min).await;
+        assert!(sup.read_sensors().await.is_some());
+        assert!(sup.send_command(&FORWARD).await);
+        assert_eq!(script.lock().unwrap().connects, 2);
+        assert_eq!(sup.status().reconnects, 1);
+        assert!(sup.status().to_string().contains("1 reconnects"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_slow_reconnect_is_waited_for() {
+        let script: Shared = Arc::default();
+        let mut sup = supervisor(&script);
+        let config = SupervisorConfig::default();
+        script.lock().unwrap().ops_until_failure = Some(0);
+        assert!(sup.read_sensors().await.is_none());
+
+        // Like a BLE scan: far slower than any read, but the robot is there
+        script.lock().unwrap().connect_delay = config.io_timeout * 10;
+        assert!(config.connect_timeout > config.io_timeout * 10);
+        tokio::time::advance(config.backoff_min).await;
+        assert!(sup.read_sensors().await.is_some());
+        assert!(sup.is_connected());
+        assert_eq!(sup.status().reconnects, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stop_sent_when_link_still_writable() {
+        let script: Shared = Arc::default();
+        let mut sup = supervisor(&script);
+
+        // Reads hang but the link is otherwise fine
+        script.lock().unwrap().hang = true;
+        let read = sup.read_sensors();
+        assert!(read.await.is_none());
+        assert!(sup.status().last_error.unwrap().contains("timed out"));
+
+        // The robot was told to stop with its pen up on the way out
+        let s = script.lock().unwrap();
+        assert_eq!(s.commands.last(), Some(&(0, 0, PEN_UP)));
+        assert_eq!(s.closes, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_backoff_doubles_up_to_limit() {
+        let script: Shared = Arc::default();
+        let mut sup = supervisor(&script);
+        script.lock().unwrap().ops_until_failure = Some(0);
+        script.lock().unwrap().refuse_connects = 100;
+        assert!(sup.read_sensors().await.is_none());
+
+        let start = Instant::now();
+        let mut attempts_at = Vec::new();
+        while attempts_at.len() < 7 {
+            let before = script.lock().unwrap().refuse_connects;
+            sup.read_sensors().await;
+            if script.lock().unwrap().refuse_connects < before {
+                attempts_at.push(start.elapsed().as_millis());
+            }
+            tokio::time::advance(Duration::from_millis(50)).await;
+        }
+        let gaps: Vec<u128> = attempts_at.windows(2).map(|w| w[1] - w[0]).collect();
+        assert_eq!(gaps, vec![500, 1000, 2000, 4000, 5000, 5000]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_silent_link_counts_as_lost() {
+        use crate::poller::Freshness;
+
+        /// Answers every read with the same old data
+        struct Stuck;
+
+        #[async_trait]
+        impl RobotLink for Stuck {
+            async fn read_sensors(&mut self) -> Result<MBotSensors> {
+                Ok(MBotSensors::default())
+            }
+            async fn send_command(&mut self, _cmd: &MotorCommand) -> Result<()> {
+                Ok(())
+            }
+            fn health(&self) -> LinkHealth {
+                let mut freshness = Freshness::default();
+                freshness.mark_all(0);
+                LinkHealth {
+                    connected: true,
+                    freshness: Some(freshness),
+                }
+            }
+            async fn close(&mut self) -> Result<()> {
+                Ok(())
+            }
+        }
+
+        let reconnect: Reconnect = Box::new(|| Box::pin(async { Err(anyhow!("gone")) }));
+        let mut sup = LinkSupervisor::new(
+            MBotTransport::from_link(Box::new(Stuck)),
+            reconnect,
+            SupervisorConfig::default(),
+        );
+        assert!(sup.read_sensors().await.is_some());
+        tokio::time::advance(Duration::from_millis(1100)).await;
+        assert!(sup.read_sensors().await.is_none());
+        assert!(sup.status().last_error.unwrap().contains("No answer"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_brain_resumes_without_odometry_jump() {
+        let script: Shared = Arc::default();
+        let mut sup = supervisor(&script);
+        let mut brain = MBotBrain::new();
+
+        // Drive straight for a while
+        for _ in 0..20 {
+            let sensors = sup.read_sensors().await.unwrap();
+            brain.tick(&sensors);
+        }
+        let before = brain.position();
+        assert!(before.0 > 1.0, "{:?}", before);
+
+        // The link drops; the brain is held while it's down
+        script.lock().unwrap().ops_until_failure = Some(0);
+        assert!(sup.read_sensors().await.is_none());
+        let held = brain.snapshot();
+        tokio::time::advance(Duration::from_secs(1)).await;
+
+        // The robot comes back with its encoders reset to zero
+        let sensors = sup.read_sensors().await.unwrap();
+        resume(&mut brain, &held);
+        brain.tick(&sensors);
+        assert_eq!(brain.position(), before);
+        assert_eq!(brain.snapshot().mood.lifetime_us, held.mood.lifetime_us + NOMINAL_TICK_US);
+
+        let sensors = sup.read_sensors().await.unwrap();
+        brain.tick(&sensors);
+        assert!(brain.position().0 > before.0);
+        assert!(brain.position().0 - before.0 < 2.0, "{:?} {:?}", before, brain.position());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_waits_for_first_encoder_count() {
+        use crate::poller::{Freshness, SensorField};
+
+        /// Everything but the encoders answers for the first `reads`
+        struct SlowEncoders {
+            reads: u64,
+            freshness: Freshness,
+        }
+
+        #[async_trait]
+        impl RobotLink for SlowEncoders {
+            async fn read_sensors(&mut self) -> Result<MBotSensors> {
+                self.reads += 1;
+                let now_us = self.reads * 50_000;
+                for field in SensorField::ALL {
+                    let encoder = matches!(field, SensorField::EncoderLeft | SensorField::EncoderRight);
+                    if !encoder || self.reads > 3 {
+                        self.freshness.mark(field, now_us);
+                    }
+                }
+                let ticks = if self.reads > 3 { 5_000 } else { 0 };
+                Ok(MBotSensors {
+                    timestamp_us: now_us,
+                    encoder_left: ticks,
+                    encoder_right: ticks,
+                    ..MBotSensors::UNREAD
+                })
+            }
+            async fn send_command(&mut self, _cmd: &MotorCommand) -> Result<()> {
+                Ok(())
+            }
+            fn health(&self) -> LinkHealth {
+                LinkHealth {
+                    connected: true,
+                    freshness: Some(self.freshness.clone()),
+                }
+            }
+            async fn close(&mut self) -> Result<()> {
+                Ok(())
+            }
+        }
+
+        let link = SlowEncoders { reads: 0, freshness: Freshness::default() };
+        let reconnect: Reconnect = Box::new(|| Box::pin(async { Err(anyhow!("gone")) }));
+        let mut sup = LinkSupervisor::new(MBotTransport::from_link(Box::new(link)), reconnect, SupervisorConfig::default());
+        let mut brain = MBotBrain::new();
+        let held = brain.snapshot();
+
+        // Waiting on the encoders isn't a lost link
+        for _ in 0..3 {
+            assert!(sup.read_sensors().await.is_none());
+            assert!(sup.is_connected());
+        }
+
+        // The first count is where odometry starts, not a 5000-tick lurch
+        let sensors = sup.read_sensors().await.unwrap();
+        resume(&mut brain, &held);
+        brain.tick(&sensors);
+        brain.tick(&sup.read_sensors().await.unwrap());
+        assert_eq!(brain.position(), (0.0, 0.0));
+    }
+}
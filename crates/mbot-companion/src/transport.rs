@@ -1,80 +1,512 @@
 //! Transport layer for mBot2 communication
-
-use anyhow::{anyhow, Context, Result};
-use mbot_core::{MBotSensors, MotorCommand};
+//!
+//! Every way of talking to a robot is a `RobotLink`, chosen at runtime by a
+//! URI:
+//!
+//! ```text
+//! sim://                  simulated robot, no hardware
+//! sim://scenes/maze.json  simulated robot in a scene file
+//! serial:///dev/ttyUSB0   USB serial (serial://COM3 on Windows)
+//! ble://                  first mBot2 found over Bluetooth
+//! ble://CyberPi-1234      a Bluetooth robot by name
+//! ble://AA:BB:CC:DD:EE:FF  a Bluetooth robot by address
+//! tcp://localhost:7000    robot bridge or mbot-emulator over the network
+//! replay://session.log    a session recorded with --record, played back
+//! ```
+//!
+//! `LinkRegistry` maps each scheme to a connector, so adding a link means
+//! registering it rather than editing `MBotTransport`.
+
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use mbot_core::{MBotSensors, MotorCommand, PEN_UP};
+use std::future::Future;
+use std::pin::Pin;
+use tokio::time::{timeout, Instant};
+use tracing::debug;
+
+use anyhow::Context;
+use std::path::Path;
 use std::time::Duration;
-use tracing::{debug, info};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tracing::info;
 
+#[cfg(feature = "bluetooth")]
+use crate::ble::{
+    find_uart, BleLink, BlePort, BleTarget, CharProps, GattCharacteristic, WriteMode,
+    DEFAULT_ATT_MTU,
+};
+use crate::poller::{self, Freshness, SensorField, SensorPoller};
 use crate::protocol;
+use crate::record::SessionLog;
+use crate::replay::ReplayLink;
+use crate::sim::{Scene, SensorNoise, SimWorld};
+
+/// One way of talking to a robot
+#[async_trait]
+pub trait RobotLink: Send {
+    /// Latest reading of every sensor
+    async fn read_sensors(&mut self) -> Result<MBotSensors>;
+
+    /// Drive the motors, LED and pen
+    async fn send_command(&mut self, cmd: &MotorCommand) -> Result<()>;
 
-pub enum TransportType {
-    #[cfg(feature = "bluetooth")]
-    Bluetooth,
-    #[cfg(feature = "serial")]
-    Serial(String),
-    Simulated,
+    /// How the link is doing right now
+    fn health(&self) -> LinkHealth;
+
+    /// Stop the robot and let go of the connection; later calls fail
+    async fn close(&mut self) -> Result<()>;
 }
 
-pub struct MBotTransport {
-    inner: TransportInner,
-    // Simulation state
-    sim_distance: f32,
-    sim_encoder_left: i32,
-    sim_encoder_right: i32,
-    sim_tick: u64,
+/// Link status for display and supervision
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LinkHealth {
+    pub connected: bool,
+    /// When each sensor field was last read, for links that poll fields separately
+    pub freshness: Option<Freshness>,
 }
 
-enum TransportInner {
-    #[cfg(feature = "bluetooth")]
-    Bluetooth(BluetoothTransport),
-    #[cfg(feature = "serial")]
-    Serial(SerialTransport),
-    Simulated,
+impl LinkHealth {
+    /// Whether both encoders have given a count yet; odometry measures from
+    /// the first one. Links that get whole frames always have.
+    pub fn encoders_read(&self) -> bool {
+        self.freshness.as_ref().is_none_or(|f| {
+            f.has_read(SensorField::EncoderLeft) && f.has_read(SensorField::EncoderRight)
+        })
+    }
 }
 
-#[cfg(feature = "bluetooth")]
-struct BluetoothTransport {
-    // btleplug peripheral would go here
-    #[allow(dead_code)]
-    connected: bool,
+/// What a connector returns
+pub type ConnectFuture = Pin<Box<dyn Future<Output = Result<Box<dyn RobotLink>>> + Send>>;
+
+/// Opens a link given the part of its URI after `scheme://`
+pub type Connector = fn(target: String) -> ConnectFuture;
+
+/// Split a link URI into its lower-cased scheme and target
+pub fn parse_uri(uri: &str) -> Result<(String, &str)> {
+    let (scheme, target) = uri.split_once("://").ok_or_else(|| {
+        anyhow!("Link '{}' is not a URI like sim:// or serial:///dev/ttyUSB0", uri)
+    })?;
+    let valid = |c: char| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.');
+    if scheme.is_empty() || !scheme.chars().all(valid) {
+        bail!("Link '{}' has no valid scheme", uri);
+    }
+    Ok((scheme.to_ascii_lowercase(), target))
 }
 
-#[cfg(feature = "serial")]
-struct SerialTransport {
-    port: Box<dyn serialport::SerialPort>,
+/// Link kinds by URI scheme
+pub struct LinkRegistry {
+    connectors: Vec<(&'static str, Connector)>,
+}
+
+impl LinkRegistry {
+    /// A registry that knows no links
+    pub fn empty() -> Self {
+        Self {
+            connectors: Vec::new(),
+        }
+    }
+
+    /// Every link built into this binary
+    pub fn new() -> Self {
+        let mut registry = Self::empty();
+        registry.register("sim", connect_sim);
+        registry.register("serial", connect_serial);
+        registry.register("ble", connect_bluetooth);
+        registry.register("tcp", connect_tcp);
+        registry.register("replay", connect_replay);
+        registry
+    }
+
+    /// Add a link kind, replacing any already registered for `scheme`
+    pub fn register(&mut self, scheme: &'static str, connector: Connector) {
+        match self.connectors.iter_mut().find(|(s, _)| *s == scheme) {
+            Some(entry) => entry.1 = connector,
+            None => self.connectors.push((scheme, connector)),
+        }
+    }
+
+    /// Registered schemes, in registration order
+    pub fn schemes(&self) -> Vec<&'static str> {
+        self.connectors.iter().map(|(s, _)| *s).collect()
+    }
+
+    /// Open the link `uri` names
+    pub async fn connect(&self, uri: &str) -> Result<Box<dyn RobotLink>> {
+        let (scheme, target) = parse_uri(uri)?;
+        let Some(&(_, connector)) = self.connectors.iter().find(|(s, _)| *s == scheme) else {
+            let known: Vec<String> = self.schemes().iter().map(|s| format!("{}://", s)).collect();
+            bail!("Unknown link '{}'. Available: {}", uri, known.join(", "));
+        };
+        connector(target.to_string()).await
+    }
+}
+
+impl Default for LinkRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The link the app is talking to
+pub struct MBotTransport {
+    link: Box<dyn RobotLink>,
 }
 
 impl MBotTransport {
-    pub async fn connect(transport_type: TransportType) -> Result<Self> {
-        let inner = match transport_type {
-            #[cfg(feature = "bluetooth")]
-            TransportType::Bluetooth => {
-                let bt = Self::connect_bluetooth().await?;
-                TransportInner::Bluetooth(bt)
-            }
-            #[cfg(feature = "serial")]
-            TransportType::Serial(port_name) => {
-                let serial = Self::connect_serial(&port_name)?;
-                TransportInner::Serial(serial)
-            }
-            TransportType::Simulated => TransportInner::Simulated,
+    /// Open `uri` with the built-in links
+    pub async fn connect(uri: &str) -> Result<Self> {
+        Self::connect_with(&LinkRegistry::new(), uri).await
+    }
+
+    /// Open `uri` with the links in `registry`
+    pub async fn connect_with(registry: &LinkRegistry, uri: &str) -> Result<Self> {
+        Ok(Self::from_link(registry.connect(uri).await?))
+    }
+
+    pub fn from_link(link: Box<dyn RobotLink>) -> Self {
+        Self { link }
+    }
+
+    pub async fn read_sensors(&mut self) -> Result<MBotSensors> {
+        self.link.read_sensors().await
+    }
+
+    /// Latest sensors once the brain can use them: None until both encoders
+    /// have given a count, since odometry measures from the first one and a
+    /// placeholder 0 would turn into a jump
+    pub async fn read_for_brain(&mut self) -> Result<Option<MBotSensors>> {
+        let sensors = self.link.read_sensors().await?;
+        Ok(self.link.health().encoders_read().then_some(sensors))
+    }
+
+    pub async fn send_command(&mut self, cmd: &MotorCommand) -> Result<()> {
+        self.link.send_command(cmd).await
+    }
+
+    pub fn health(&self) -> LinkHealth {
+        self.link.health()
+    }
+
+    pub async fn close(&mut self) -> Result<()> {
+        self.link.close().await
+    }
+}
+
+pub(crate) fn closed() -> anyhow::Error {
+    anyhow!("Link is closed")
+}
+
+// ============================================
+// Simulation
+// ============================================
+
+/// Open a simulated robot, in the scene file `target` names if any
+fn connect_sim(target: String) -> ConnectFuture {
+    Box::pin(async move {
+        let link = if target.is_empty() {
+            SimLink::new()
+        } else {
+            let scene = Scene::load(Path::new(&target))?;
+            info!("🗺️  Simulating {}", if scene.name.is_empty() { &target } else { &scene.name });
+            SimLink::with_scene(scene)
         };
+        Ok(Box::new(link) as Box<dyn RobotLink>)
+    })
+}
 
-        Ok(Self {
-            inner,
-            sim_distance: 100.0,
-            sim_encoder_left: 0,
-            sim_encoder_right: 0,
-            sim_tick: 0,
+/// A robot with no hardware, see `SimWorld`; readings pass through the
+/// scene's `SensorNoise`
+pub struct SimLink {
+    // Driven by wall-clock time so the loop rate doesn't change the
+    // simulated world
+    start: Instant,
+    world: SimWorld,
+    noise: SensorNoise,
+    /// Newest frame to have arrived, repeated while frames are lost
+    latest: MBotSensors,
+    freshness: Freshness,
+    open: bool,
+}
+
+impl SimLink {
+    pub fn new() -> Self {
+        Self::with_scene(Scene::default())
+    }
+
+    pub fn with_scene(scene: Scene) -> Self {
+        Self {
+            start: Instant::now(),
+            noise: SensorNoise::new(scene.noise.clone()),
+            world: SimWorld::with_scene(scene),
+            latest: MBotSensors::UNREAD,
+            freshness: Freshness::default(),
+            open: true,
+        }
+    }
+}
+
+impl Default for SimLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl RobotLink for SimLink {
+    async fn read_sensors(&mut self) -> Result<MBotSensors> {
+        if !self.open {
+            return Err(closed());
+        }
+        let clean = self.world.sensors(self.start.elapsed());
+        if let Some(frame) = self.noise.apply(&clean) {
+            self.latest = frame;
+            self.freshness.mark_all(clean.timestamp_us);
+        }
+        Ok(MBotSensors {
+            timestamp_us: clean.timestamp_us,
+            ..self.latest.clone()
         })
     }
 
-    #[cfg(feature = "bluetooth")]
-    async fn connect_bluetooth() -> Result<BluetoothTransport> {
+    async fn send_command(&mut self, cmd: &MotorCommand) -> Result<()> {
+        if !self.open {
+            return Err(closed());
+        }
+        self.world.set_motors(cmd.left, cmd.right, self.start.elapsed());
+        debug!(
+            "SIM Command: L={} R={} Mode={:?}",
+            cmd.left,
+            cmd.right,
+            if cmd.left < 0 && cmd.right < 0 {
+                "REVERSE"
+            } else if cmd.left > cmd.right {
+                "TURN_RIGHT"
+            } else if cmd.right > cmd.left {
+                "TURN_LEFT"
+            } else {
+                "FORWARD"
+            }
+        );
+        Ok(())
+    }
+
+    fn health(&self) -> LinkHealth {
+        LinkHealth {
+            connected: self.open,
+            freshness: Some(self.freshness.clone()),
+        }
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        self.world.set_motors(0, 0, self.start.elapsed());
+        self.open = false;
+        Ok(())
+    }
+}
+
+// ============================================
+// Replay
+// ============================================
+
+/// Play back the session log `target` names, see `ReplayLink`
+fn connect_replay(target: String) -> ConnectFuture {
+    Box::pin(async move {
+        let log = SessionLog::read(Path::new(&target))?;
+        info!("📼 Replaying {} ({} records)", target, log.records.len());
+        Ok(Box::new(ReplayLink::new(log)) as Box<dyn RobotLink>)
+    })
+}
+
+// ============================================
+// Byte streams: serial and TCP
+// ============================================
+
+/// Time allowed for one round of sensor reads - inside the 50ms control period
+const POLL_BUDGET: Duration = Duration::from_millis(40);
+/// A command that can't be written in this long means the link is stuck
+const WRITE_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// A link speaking Makeblock frames over any async byte stream, polling
+/// every sensor each read.
+///
+/// All I/O goes through tokio, so a stalled robot never blocks the runtime
+/// and every read and write can be abandoned by a timeout.
+pub struct StreamLink<S> {
+    stream: Option<S>,
+    poller: SensorPoller,
+    freshness: Freshness,
+}
+
+/// USB serial link
+#[cfg(feature = "serial")]
+pub type SerialLink = StreamLink<tokio_serial::SerialStream>;
+
+/// Network link to a robot bridge or `mbot-emulator`
+pub type TcpLink = StreamLink<TcpStream>;
+
+impl<S> StreamLink<S> {
+    /// Talk to a robot over an already open stream
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream: Some(stream),
+            poller: SensorPoller::new(),
+            freshness: Freshness::default(),
+        }
+    }
+}
+
+#[cfg(feature = "serial")]
+impl StreamLink<tokio_serial::SerialStream> {
+    /// Open `port_name`; needs a running tokio runtime
+    pub fn open_serial(port_name: &str) -> Result<Self> {
+        use tokio_serial::SerialPortBuilderExt;
+
+        if port_name.is_empty() {
+            bail!("A serial link needs a port, like serial:///dev/ttyUSB0");
+        }
+        info!("📡 Opening serial port: {}", port_name);
+
+        let port = tokio_serial::new(port_name, 115200)
+            .open_native_async()
+            .context(format!("Failed to open serial port: {}", port_name))?;
+
+        info!("✅ Serial port opened!");
+
+        Ok(Self::new(port))
+    }
+}
+
+impl StreamLink<TcpStream> {
+    /// Connect to `host:port`, giving up after `TCP_CONNECT_TIMEOUT`
+    pub async fn connect_tcp(addr: &str) -> Result<Self> {
+        info!("📡 Connecting to {}", addr);
+
+        let stream = timeout(TCP_CONNECT_TIMEOUT, TcpStream::connect(addr))
+            .await
+            .map_err(|_| anyhow!("Timed out connecting to {} after {:?}", addr, TCP_CONNECT_TIMEOUT))?
+            .with_context(|| {
+                format!("Failed to connect to {} (a TCP link needs host:port, like tcp://localhost:{})", addr, DEFAULT_TCP_PORT)
+            })?;
+        // Frames are tiny; don't hold them back waiting for more
+        stream.set_nodelay(true)?;
+
+        info!("✅ Connected to {}", addr);
+
+        Ok(Self::new(stream))
+    }
+}
+
+/// Port `mbot-emulator` listens on by default
+pub const DEFAULT_TCP_PORT: u16 = 7000;
+const TCP_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Frames carrying one motor command
+pub(crate) fn command_bytes(cmd: &MotorCommand) -> Vec<u8> {
+    // Motors and LED every time
+    let mut bytes = protocol::motor_cmd(cmd.left, cmd.right);
+    bytes.extend(protocol::led_cmd(cmd.led_color));
+
+    // Pen servo if drawing
+    if cmd.pen_angle != PEN_UP {
+        bytes.extend(protocol::servo_cmd(1, cmd.pen_angle));
+    }
+    bytes
+}
+
+/// Write all of `bytes`, or fail if the stream won't take them in time
+async fn write_within<S: AsyncWrite + Unpin>(stream: &mut S, bytes: &[u8]) -> Result<()> {
+    timeout(WRITE_TIMEOUT, async {
+        stream.write_all(bytes).await?;
+        stream.flush().await
+    })
+    .await
+    .map_err(|_| anyhow!("Timed out writing to the robot after {:?}", WRITE_TIMEOUT))??;
+    Ok(())
+}
+
+#[async_trait]
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> RobotLink for StreamLink<S> {
+    async fn read_sensors(&mut self) -> Result<MBotSensors> {
+        let stream = self.stream.as_mut().ok_or_else(closed)?;
+        // Every sensor is requested at once; whatever hasn't answered
+        // within the budget keeps its last value
+        let (sensors, freshness) = poller::poll(stream, &mut self.poller, POLL_BUDGET).await?;
+        self.freshness = freshness;
+        Ok(sensors)
+    }
+
+    async fn send_command(&mut self, cmd: &MotorCommand) -> Result<()> {
+        let stream = self.stream.as_mut().ok_or_else(closed)?;
+        write_within(stream, &command_bytes(cmd)).await
+    }
+
+    fn health(&self) -> LinkHealth {
+        LinkHealth {
+            connected: self.stream.is_some(),
+            freshness: Some(self.freshness.clone()),
+        }
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        if let Some(mut stream) = self.stream.take() {
+            write_within(&mut stream, &protocol::motor_cmd(0, 0)).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serial")]
+fn connect_serial(target: String) -> ConnectFuture {
+    Box::pin(async move { Ok(Box::new(SerialLink::open_serial(&target)?) as Box<dyn RobotLink>) })
+}
+
+#[cfg(not(feature = "serial"))]
+fn connect_serial(_target: String) -> ConnectFuture {
+    Box::pin(async {
+        bail!("Serial support not compiled. Rebuild with: cargo build --features serial")
+    })
+}
+
+fn connect_tcp(target: String) -> ConnectFuture {
+    Box::pin(async move { Ok(Box::new(TcpLink::connect_tcp(&target).await?) as Box<dyn RobotLink>) })
+}
+
+// ============================================
+// Bluetooth
+// ============================================
+
+/// Bluetooth LE link to the CyberPi
+#[cfg(feature = "bluetooth")]
+pub type BluetoothLink = BleLink<BtleplugPort>;
+
+/// How long to scan for the robot before giving up
+#[cfg(feature = "bluetooth")]
+const BLE_SCAN_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often to look through what the scan has found
+#[cfg(feature = "bluetooth")]
+const BLE_SCAN_POLL: Duration = Duration::from_millis(250);
+
+/// The UART characteristics of a connected peripheral, via btleplug
+#[cfg(feature = "bluetooth")]
+pub struct BtleplugPort {
+    peripheral: btleplug::platform::Peripheral,
+    write: btleplug::api::Characteristic,
+    write_props: CharProps,
+    notifications: tokio::sync::mpsc::Receiver<Vec<u8>>,
+    /// ATT MTU negotiated for the write characteristic
+    mtu: usize,
+}
+
+#[cfg(feature = "bluetooth")]
+impl BtleplugPort {
+    /// Scan until `target` shows up, then connect and subscribe
+    pub async fn connect(target: &BleTarget) -> Result<Self> {
         use btleplug::api::{Central, Manager as _, Peripheral as _, ScanFilter};
         use btleplug::platform::Manager;
 
-        info!("🔍 Scanning for mBot2...");
+        info!("🔍 Scanning for mBot2 ({:?})...", target);
 
         let manager = Manager::new()
             .await
@@ -96,167 +528,400 @@ impl MBotTransport {
             .await
             .context("Failed to start Bluetooth scan")?;
 
-        // Wait for devices
-        tokio::time::sleep(Duration::from_secs(5)).await;
+        // Take the robot as soon as it is seen rather than after a fixed wait
+        let deadline = Instant::now() + BLE_SCAN_TIMEOUT;
+        let peripheral = loop {
+            let mut found = None;
+            for peripheral in adapter
+                .peripherals()
+                .await
+                .context("Failed to get peripherals")?
+            {
+                if let Ok(Some(props)) = peripheral.properties().await {
+                    let address = props.address.to_string();
+                    debug!("Found device: {:?} {}", props.local_name, address);
+                    if target.matches(props.local_name.as_deref(), &address) {
+                        info!("✅ Found mBot2: {:?} {}", props.local_name, address);
+                        found = Some(peripheral);
+                        break;
+                    }
+                }
+            }
+            if let Some(peripheral) = found {
+                break peripheral;
+            }
+            if Instant::now() >= deadline {
+                let _ = adapter.stop_scan().await;
+                bail!("mBot2 not found. Make sure it's powered on and Bluetooth is enabled.");
+            }
+            tokio::time::sleep(BLE_SCAN_POLL).await;
+        };
+        let _ = adapter.stop_scan().await;
 
-        let peripherals = adapter
-            .peripherals()
+        peripheral
+            .connect()
             .await
-            .context("Failed to get peripherals")?;
-
-        // Find mBot2 (CyberPi)
-        for peripheral in peripherals {
-            if let Ok(Some(props)) = peripheral.properties().await {
-                let name = props.local_name.unwrap_or_default();
-                debug!("Found device: {}", name);
-
-                if name.contains("Makeblock") || name.contains("CyberPi") || name.contains("mBot") {
-                    info!("✅ Found mBot2: {}", name);
+            .context("Failed to connect to mBot2")?;
 
-                    peripheral
-                        .connect()
-                        .await
-                        .context("Failed to connect to mBot2")?;
-
-                    peripheral
-                        .discover_services()
-                        .await
-                        .context("Failed to discover services")?;
+        peripheral
+            .discover_services()
+            .await
+            .context("Failed to discover services")?;
+
+        let characteristics = peripheral.characteristics();
+        let found: Vec<GattCharacteristic> = characteristics
+            .iter()
+            .map(|c| GattCharacteristic {
+                service: c.service_uuid.as_u128(),
+                uuid: c.uuid.as_u128(),
+                props: char_props(c.properties),
+            })
+            .collect();
+        let uart = find_uart(&found).ok_or_else(|| anyhow!("mBot2 has no UART service"))?;
+        let pick = |wanted: &GattCharacteristic| {
+            characteristics
+                .iter()
+                .find(|c| c.service_uuid.as_u128() == wanted.service && c.uuid.as_u128() == wanted.uuid)
+                .cloned()
+                .expect("characteristic was just listed")
+        };
+        let write = pick(&uart.write);
+        let notify = pick(&uart.notify);
 
-                    info!("✅ Connected to mBot2!");
+        peripheral
+            .subscribe(&notify)
+            .await
+            .context("Failed to subscribe to notifications")?;
 
-                    return Ok(BluetoothTransport { connected: true });
+        // Forward notifications into a channel so reads can time out
+        let mut stream = peripheral
+            .notifications()
+            .await
+            .context("Failed to open notification stream")?;
+        let (tx, rx) = tokio::sync::mpsc::channel(64);
+        let notify_uuid = notify.uuid;
+        tokio::spawn(async move {
+            use futures::StreamExt;
+            while let Some(n) = stream.next().await {
+                if n.uuid == notify_uuid && tx.send(n.value).await.is_err() {
+                    break;
                 }
             }
-        }
+        });
+
+        let mtu = match negotiated_mtu(&peripheral, &write).await {
+            Ok(Some(mtu)) => mtu,
+            Ok(None) => DEFAULT_ATT_MTU,
+            Err(e) => {
+                debug!("Couldn't read the negotiated MTU: {:#}", e);
+                DEFAULT_ATT_MTU
+            }
+        };
+        info!("✅ Connected to mBot2 via {} service (MTU {})!", uart.service, mtu);
 
-        Err(anyhow!(
-            "mBot2 not found. Make sure it's powered on and Bluetooth is enabled."
-        ))
+        Ok(Self {
+            peripheral,
+            write,
+            write_props: uart.write.props,
+            notifications: rx,
+            mtu,
+        })
     }
+}
 
-    #[cfg(feature = "serial")]
-    fn connect_serial(port_name: &str) -> Result<SerialTransport> {
-        info!("📡 Opening serial port: {}", port_name);
+/// The ATT MTU BlueZ negotiated for `characteristic`.
+///
+/// btleplug doesn't expose it, so this asks BlueZ directly over D-Bus,
+/// finding the device by the id btleplug gave it.
+#[cfg(all(feature = "bluetooth", target_os = "linux"))]
+async fn negotiated_mtu(
+    peripheral: &btleplug::platform::Peripheral,
+    characteristic: &btleplug::api::Characteristic,
+) -> Result<Option<usize>> {
+    use btleplug::api::Peripheral as _;
+
+    let (_, session) = bluez_async::BluetoothSession::new().await?;
+    let id = peripheral.id().to_string();
+    let device = session
+        .get_devices()
+        .await?
+        .into_iter()
+        .find(|d| d.id.to_string() == id)
+        .ok_or_else(|| anyhow!("BlueZ doesn't know device {}", id))?;
+    let info = session
+        .get_service_characteristic_by_uuid(&device.id, characteristic.service_uuid, characteristic.uuid)
+        .await?;
+    Ok(info.mtu.map(usize::from))
+}
 
-        let port = serialport::new(port_name, 115200)
-            .timeout(Duration::from_millis(100))
-            .open()
-            .context(format!("Failed to open serial port: {}", port_name))?;
+/// Other platforms don't report the MTU; assume the minimum
+#[cfg(all(feature = "bluetooth", not(target_os = "linux")))]
+async fn negotiated_mtu(
+    _peripheral: &btleplug::platform::Peripheral,
+    _characteristic: &btleplug::api::Characteristic,
+) -> Result<Option<usize>> {
+    Ok(None)
+}
 
-        info!("✅ Serial port opened!");
+#[cfg(feature = "bluetooth")]
+fn char_props(flags: btleplug::api::CharPropFlags) -> CharProps {
+    use btleplug::api::CharPropFlags;
+    CharProps {
+        write: flags.contains(CharPropFlags::WRITE),
+        write_without_response: flags.contains(CharPropFlags::WRITE_WITHOUT_RESPONSE),
+        notify: flags.contains(CharPropFlags::NOTIFY),
+    }
+}
 
-        Ok(SerialTransport { port })
+#[cfg(feature = "bluetooth")]
+#[async_trait]
+impl BlePort for BtleplugPort {
+    async fn write(&mut self, packet: &[u8], mode: WriteMode) -> Result<()> {
+        use btleplug::api::{Peripheral as _, WriteType};
+        let write_type = match mode {
+            WriteMode::WithResponse => WriteType::WithResponse,
+            WriteMode::WithoutResponse => WriteType::WithoutResponse,
+        };
+        self.peripheral
+            .write(&self.write, packet, write_type)
+            .await
+            .context("Bluetooth write failed")
     }
 
-    pub async fn read_sensors(&mut self) -> Result<MBotSensors> {
-        match &mut self.inner {
-            #[cfg(feature = "bluetooth")]
-            TransportInner::Bluetooth(_bt) => {
-                // TODO: Read from Bluetooth notification characteristic
-                // For now, return simulated values
-                self.read_simulated()
-            }
-            #[cfg(feature = "serial")]
-            TransportInner::Serial(serial) => {
-                // Send read command
-                let cmd = protocol::read_ultrasonic_cmd();
-                serial.port.write_all(&cmd)?;
-
-                // Read response
-                let mut buf = [0u8; 64];
-                match serial.port.read(&mut buf) {
-                    Ok(n) if n > 0 => {
-                        let distance = protocol::parse_ultrasonic_response(&buf[..n])
-                            .unwrap_or(100.0);
-
-                        Ok(MBotSensors {
-                            ultrasonic_cm: distance,
-                            ..Default::default()
-                        })
-                    }
-                    _ => self.read_simulated(),
-                }
-            }
-            TransportInner::Simulated => self.read_simulated(),
+    async fn notification(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>> {
+        match tokio::time::timeout(timeout, self.notifications.recv()).await {
+            Ok(Some(bytes)) => Ok(Some(bytes)),
+            Ok(None) => bail!("mBot2 disconnected"),
+            Err(_) => Ok(None),
         }
     }
 
-    fn read_simulated(&mut self) -> Result<MBotSensors> {
-        self.sim_tick += 1;
+    fn mtu(&self) -> usize {
+        self.mtu
+    }
 
-        // Simulate varying distance (something approaching and retreating)
-        let wave = ((self.sim_tick as f32) * 0.02).sin();
-        self.sim_distance = 50.0 + wave * 40.0;
+    fn write_props(&self) -> CharProps {
+        self.write_props
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        use btleplug::api::Peripheral as _;
+        self.peripheral
+            .disconnect()
+            .await
+            .context("Failed to disconnect")
+    }
+}
+
+#[cfg(feature = "bluetooth")]
+fn connect_bluetooth(target: String) -> ConnectFuture {
+    Box::pin(async move {
+        let port = BtleplugPort::connect(&BleTarget::parse(&target)).await?;
+        Ok(Box::new(BluetoothLink::new(port)) as Box<dyn RobotLink>)
+    })
+}
 
-        // Add occasional "close approach"
-        if self.sim_tick % 200 > 180 {
-            self.sim_distance = 10.0 + (self.sim_tick % 20) as f32;
+#[cfg(not(feature = "bluetooth"))]
+fn connect_bluetooth(_target: String) -> ConnectFuture {
+    Box::pin(async {
+        bail!("Bluetooth support not compiled. Rebuild with: cargo build --features bluetooth")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A link that counts what it's asked to do
+    struct CountingLink {
+        commands: u32,
+    }
+
+    #[async_trait]
+    impl RobotLink for CountingLink {
+        async fn read_sensors(&mut self) -> Result<MBotSensors> {
+            Ok(MBotSensors {
+                encoder_left: self.commands as i32,
+                ..Default::default()
+            })
         }
 
-        // Simulate encoder movement
-        self.sim_encoder_left += 5;
-        self.sim_encoder_right += 5;
+        async fn send_command(&mut self, _cmd: &MotorCommand) -> Result<()> {
+            self.commands += 1;
+            Ok(())
+        }
 
-        Ok(MBotSensors {
-            timestamp_us: self.sim_tick * 50_000, // 50ms per tick
-            ultrasonic_cm: self.sim_distance,
-            encoder_left: self.sim_encoder_left,
-            encoder_right: self.sim_encoder_right,
-            gyro_z: wave * 10.0,
-            accel: [wave * 0.5, 0.0, 9.8],
-            sound_level: 0.1 + (wave * 0.1).abs(),
-            light_level: 0.5,
-            quad_rgb: [[200, 200, 200]; 4], // White surface
-        })
+        fn health(&self) -> LinkHealth {
+            LinkHealth {
+                connected: true,
+                freshness: None,
+            }
+        }
+
+        async fn close(&mut self) -> Result<()> {
+            Ok(())
+        }
     }
 
-    pub async fn send_command(&mut self, cmd: &MotorCommand) -> Result<()> {
-        match &mut self.inner {
-            #[cfg(feature = "bluetooth")]
-            TransportInner::Bluetooth(_bt) => {
-                // TODO: Write to Bluetooth characteristic
-                debug!(
-                    "BT Command: L={} R={} LED={:?}",
-                    cmd.left, cmd.right, cmd.led_color
-                );
+    #[tokio::test]
+    async fn test_brain_reads_wait_for_both_encoders() {
+        /// Polls one encoder per read, as a field-by-field link does
+        struct WarmingUp {
+            freshness: Freshness,
+            reads: u64,
+        }
+
+        #[async_trait]
+        impl RobotLink for WarmingUp {
+            async fn read_sensors(&mut self) -> Result<MBotSensors> {
+                self.reads += 1;
+                let field = match self.reads {
+                    1 => SensorField::Ultrasonic,
+                    2 => SensorField::EncoderLeft,
+                    _ => SensorField::EncoderRight,
+                };
+                self.freshness.mark(field, self.reads);
+                Ok(MBotSensors::UNREAD)
+            }
+            async fn send_command(&mut self, _cmd: &MotorCommand) -> Result<()> {
                 Ok(())
             }
-            #[cfg(feature = "serial")]
-            TransportInner::Serial(serial) => {
-                // Send motor command
-                let motor_cmd = protocol::motor_cmd(cmd.left, cmd.right);
-                serial.port.write_all(&motor_cmd)?;
-
-                // Send LED command
-                let led_cmd = protocol::led_cmd(cmd.led_color);
-                serial.port.write_all(&led_cmd)?;
-
-                // Send pen servo if drawing
-                if cmd.pen_angle != 45 {
-                    let servo_cmd = protocol::servo_cmd(1, cmd.pen_angle);
-                    serial.port.write_all(&servo_cmd)?;
+            fn health(&self) -> LinkHealth {
+                LinkHealth {
+                    connected: true,
+                    freshness: Some(self.freshness.clone()),
                 }
-
-                Ok(())
             }
-            TransportInner::Simulated => {
-                debug!(
-                    "SIM Command: L={} R={} Mode={:?}",
-                    cmd.left,
-                    cmd.right,
-                    if cmd.left < 0 && cmd.right < 0 {
-                        "REVERSE"
-                    } else if cmd.left > cmd.right {
-                        "TURN_RIGHT"
-                    } else if cmd.right > cmd.left {
-                        "TURN_LEFT"
-                    } else {
-                        "FORWARD"
-                    }
-                );
+            async fn close(&mut self) -> Result<()> {
                 Ok(())
             }
         }
+
+        let link = WarmingUp { freshness: Freshness::default(), reads: 0 };
+        let mut transport = MBotTransport::from_link(Box::new(link));
+        assert!(transport.read_for_brain().await.unwrap().is_none());
+        assert!(transport.read_for_brain().await.unwrap().is_none());
+        assert!(transport.read_for_brain().await.unwrap().is_some());
+
+        // Whole-frame links are ready from the start
+        let mut counting = MBotTransport::from_link(Box::new(CountingLink { commands: 0 }));
+        assert!(counting.read_for_brain().await.unwrap().is_some());
+    }
+
+    #[test]
+    fn test_parse_uri() {
+        assert_eq!(parse_uri("sim://").unwrap(), ("sim".to_string(), ""));
+        assert_eq!(
+            parse_uri("serial:///dev/ttyUSB0").unwrap(),
+            ("serial".to_string(), "/dev/ttyUSB0")
+        );
+        assert_eq!(parse_uri("BLE://CyberPi").unwrap(), ("ble".to_string(), "CyberPi"));
+        assert_eq!(
+            parse_uri("tcp://localhost:7000").unwrap(),
+            ("tcp".to_string(), "localhost:7000")
+        );
+        assert!(parse_uri("/dev/ttyUSB0").is_err());
+        assert!(parse_uri("://x").is_err());
+        assert!(parse_uri("se rial://x").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_scheme_lists_known_ones() {
+        let err = LinkRegistry::new().connect("carrier-pigeon://coop").await.err().unwrap();
+        let message = err.to_string();
+        assert!(message.contains("sim://"), "{}", message);
+        assert!(message.contains("serial://"), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn test_registered_link_is_used() {
+        let mut registry = LinkRegistry::empty();
+        registry.register("count", |_| {
+            Box::pin(async { Ok(Box::new(CountingLink { commands: 0 }) as Box<dyn RobotLink>) })
+        });
+        assert_eq!(registry.schemes(), vec!["count"]);
+
+        let mut transport = MBotTransport::connect_with(&registry, "count://").await.unwrap();
+        transport.send_command(&MotorCommand::default()).await.unwrap();
+        transport.send_command(&MotorCommand::default()).await.unwrap();
+        assert_eq!(transport.read_sensors().await.unwrap().encoder_left, 2);
+        assert!(MBotTransport::connect_with(&registry, "sim://").await.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_sim_link_follows_commands_until_closed() {
+        let mut transport = MBotTransport::connect("sim://").await.unwrap();
+        assert!(transport.health().connected);
+
+        let forward = MotorCommand {
+            left: 50,
+            right: 50,
+            ..Default::default()
+        };
+        transport.send_command(&forward).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        let sensors = transport.read_sensors().await.unwrap();
+        assert_eq!((sensors.encoder_left, sensors.encoder_right), (150, 150));
+        assert_eq!(sensors.gyro_z, 0.0);
+
+        transport.close().await.unwrap();
+        assert!(!transport.health().connected);
+        assert!(transport.read_sensors().await.is_err());
+        assert!(transport.send_command(&forward).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sim_link_loads_scene() {
+        let course = concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/obstacle_course.json");
+        let mut transport = MBotTransport::connect(&format!("sim://{}", course)).await.unwrap();
+        // The first barrier is 63cm ahead of the sensor, the default room's wall 193cm
+        let distance = transport.read_sensors().await.unwrap().ultrasonic_cm;
+        assert!((distance - 63.0).abs() < 1.0, "{}", distance);
+
+        let err = MBotTransport::connect("sim://no/such/scene.json").await.err().unwrap();
+        assert!(format!("{:#}", err).contains("no/such/scene.json"), "{:#}", err);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_sim_link_loses_frames() {
+        let mut scene = Scene::default();
+        scene.noise.frame_loss = 1.0;
+        let mut link = SimLink::with_scene(scene);
+
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        let sensors = link.read_sensors().await.unwrap();
+        // Nothing ever arrived, as with a poller no reply has reached
+        assert_eq!(sensors.timestamp_us, 200_000);
+        assert_eq!((sensors.distance_cm(), sensors.gyro_dps(), sensors.sound()), (None, None, None));
+        assert_eq!(link.health().freshness.unwrap().newest_us(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stream_link_never_blocks_on_a_stuck_robot() {
+        // Connected, but the robot neither reads nor answers
+        let (ours, _theirs) = tokio::io::duplex(64);
+        let mut link = StreamLink::new(ours);
+
+        let start = Instant::now();
+        let err = link.read_sensors().await.unwrap_err();
+        assert!(start.elapsed() <= POLL_BUDGET, "{:?}", start.elapsed());
+        assert!(format!("{:#}", err).contains("Timed out"), "{:#}", err);
+
+        let stuck = link.send_command(&MotorCommand { left: 50, ..Default::default() }).await;
+        assert!(format!("{:#}", stuck.unwrap_err()).contains("Timed out writing"));
+        assert!(link.close().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tcp_connect_errors() {
+        let err = MBotTransport::connect("tcp://localhost").await.err().unwrap();
+        assert!(format!("{:#}", err).contains("host:port"), "{:#}", err);
+
+        // Nothing listening
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let err = MBotTransport::connect(&format!("tcp://{}", addr)).await.err().unwrap();
+        assert!(format!("{:#}", err).contains("Failed to connect"), "{:#}", err);
     }
 }
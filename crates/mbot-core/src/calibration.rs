@@ -0,0 +1,181 @@
+//! Odometry calibration - turning encoder ticks into centimetres
+//!
+//! Wheel wear, tyre pressure and the motor batch all change how far one
+//! encoder tick really is, and the effective wheel base depends on where
+//! the tyres grip. Both are measured by driving a straight line and an
+//! in-place spin, then comparing the encoder counts with what a ruler and
+//! protractor say actually happened.
+
+use core::fmt;
+
+/// Straight runs shorter than this can't be measured accurately with a ruler
+const MIN_STRAIGHT_CM: f32 = 5.0;
+/// Spins smaller than this can't be measured accurately by eye
+const MIN_SPIN_DEG: f32 = 45.0;
+/// Fewer encoder ticks than this means the wheels barely moved
+const MIN_TICKS: f32 = 20.0;
+
+mod bounds {
+    pub const TICKS_PER_CM: (f32, f32) = (1.0, 100.0);
+    pub const WHEEL_BASE_CM: (f32, f32) = (5.0, 30.0);
+}
+
+/// Physical constants for dead reckoning
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Calibration {
+    /// Encoder ticks per cm of wheel travel (1-100)
+    pub ticks_per_cm: f32,
+    /// Effective distance between the wheels' contact points in cm (5-30)
+    pub wheel_base_cm: f32,
+}
+
+/// Why a calibration was rejected
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CalibrationError {
+    /// The run was too small to measure reliably
+    TooShort,
+    /// A constant came out (or was set) outside its plausible range; usually
+    /// a swapped motor cable or a mis-typed measurement
+    OutOfRange {
+        field: &'static str,
+        value: f32,
+        min: f32,
+        max: f32,
+    },
+}
+
+impl fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalibrationError::TooShort => write!(f, "run too short to measure"),
+            CalibrationError::OutOfRange { field, value, min, max } => {
+                write!(f, "{} = {} is outside {}..={}", field, value, min, max)
+            }
+        }
+    }
+}
+
+impl core::error::Error for CalibrationError {}
+
+fn check(field: &'static str, value: f32, (min, max): (f32, f32)) -> Result<(), CalibrationError> {
+    if value.is_finite() && (min..=max).contains(&value) {
+        Ok(())
+    } else {
+        Err(CalibrationError::OutOfRange { field, value, min, max })
+    }
+}
+
+impl Calibration {
+    /// The constants odometry has always assumed
+    pub const DEFAULT: Self = Self {
+        ticks_per_cm: 10.0,
+        wheel_base_cm: 10.0,
+    };
+
+    /// Check every constant is within its plausible range
+    pub fn validate(&self) -> Result<(), CalibrationError> {
+        check("ticks_per_cm", self.ticks_per_cm, bounds::TICKS_PER_CM)?;
+        check("wheel_base_cm", self.wheel_base_cm, bounds::WHEEL_BASE_CM)
+    }
+
+    /// Derive `ticks_per_cm` from a straight run of `measured_cm`
+    pub fn with_straight_run(
+        self,
+        left_ticks: i32,
+        right_ticks: i32,
+        measured_cm: f32,
+    ) -> Result<Self, CalibrationError> {
+        let ticks = (left_ticks as f32 + right_ticks as f32) / 2.0;
+        if ticks.abs() < MIN_TICKS || measured_cm.is_nan() || measured_cm.abs() < MIN_STRAIGHT_CM {
+            return Err(CalibrationError::TooShort);
+        }
+
+        let out = Self {
+            ticks_per_cm: ticks / measured_cm,
+            ..self
+        };
+        check("ticks_per_cm", out.ticks_per_cm, bounds::TICKS_PER_CM)?;
+        Ok(out)
+    }
+
+    /// Derive `wheel_base_cm` from an in-place spin of `measured_deg`
+    /// (counter-clockwise positive), using the current `ticks_per_cm`
+    pub fn with_spin(
+        self,
+        left_ticks: i32,
+        right_ticks: i32,
+        measured_deg: f32,
+    ) -> Result<Self, CalibrationError> {
+        let differential = right_ticks as f32 - left_ticks as f32;
+        if differential.abs() < MIN_TICKS || measured_deg.is_nan() || measured_deg.abs() < MIN_SPIN_DEG {
+            return Err(CalibrationError::TooShort);
+        }
+
+        let out = Self {
+            wheel_base_cm: differential / self.ticks_per_cm / measured_deg.to_radians(),
+            ..self
+        };
+        check("wheel_base_cm", out.wheel_base_cm, bounds::WHEEL_BASE_CM)?;
+        Ok(out)
+    }
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_is_valid() {
+        assert_eq!(Calibration::DEFAULT.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_straight_run() {
+        // 36cm measured, encoders averaged 648 ticks
+        let c = Calibration::DEFAULT.with_straight_run(640, 656, 36.0).unwrap();
+        assert!((c.ticks_per_cm - 18.0).abs() < 1e-4);
+        assert_eq!(c.wheel_base_cm, Calibration::DEFAULT.wheel_base_cm);
+    }
+
+    #[test]
+    fn test_spin() {
+        // A 12cm wheel base turning 180 degrees moves each wheel 6*pi cm
+        let arc_ticks = (6.0 * core::f32::consts::PI * 18.0) as i32;
+        let c = Calibration { ticks_per_cm: 18.0, ..Calibration::DEFAULT }
+            .with_spin(-arc_ticks, arc_ticks, 180.0)
+            .unwrap();
+        assert!((c.wheel_base_cm - 12.0).abs() < 0.05);
+
+        // Spinning clockwise measures the same base
+        let cw = Calibration { ticks_per_cm: 18.0, ..Calibration::DEFAULT }
+            .with_spin(arc_ticks, -arc_ticks, -180.0)
+            .unwrap();
+        assert!((cw.wheel_base_cm - c.wheel_base_cm).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_rejects_tiny_runs() {
+        let c = Calibration::DEFAULT;
+        assert_eq!(c.with_straight_run(5, 5, 30.0), Err(CalibrationError::TooShort));
+        assert_eq!(c.with_straight_run(300, 300, 1.0), Err(CalibrationError::TooShort));
+        assert_eq!(c.with_straight_run(300, 300, f32::NAN), Err(CalibrationError::TooShort));
+        assert_eq!(c.with_spin(-200, 200, 10.0), Err(CalibrationError::TooShort));
+    }
+
+    #[test]
+    fn test_rejects_swapped_motors() {
+        // Encoders say it turned clockwise, the user saw counter-clockwise
+        let result = Calibration::DEFAULT.with_spin(200, -200, 90.0);
+        assert!(matches!(
+            result,
+            Err(CalibrationError::OutOfRange { field: "wheel_base_cm", .. })
+        ));
+    }
+}
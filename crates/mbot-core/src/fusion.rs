@@ -0,0 +1,471 @@
+//! Pose estimation - fusing the gyro with wheel odometry
+//!
+//! Encoders measure distance well but heading poorly: a wheel that slips on
+//! paper or carpet reports travel that never happened, and the error turns
+//! every later stroke. The gyro measures rotation directly but drifts with
+//! its bias. Each tick the two heading increments are combined with a
+//! one-dimensional Kalman update, and an encoder increment that disagrees
+//! with the gyro by more than `slip_gate` standard deviations is treated as
+//! wheel slip and dropped. Heading has no absolute reference, so its
+//! variance only grows; `Pose::confidence` summarises it.
+
+use crate::{sqrtf, Real};
+#[cfg(test)]
+use crate::{cosf, sinf};
+
+/// Confidence is 0.5 once the heading standard deviation reaches this (~6 degrees)
+const CONFIDENCE_HEADING_RAD: f32 = 0.1;
+/// Smoothing factor for the gyro bias while the wheels are still
+const BIAS_ALPHA: f32 = 0.02;
+/// Keeps encoder variance positive when the wheels barely move
+const MIN_ENCODER_VARIANCE: f32 = 1e-8;
+
+/// How much to trust each sensor
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FusionConfig {
+    /// Gyro rate noise, degrees per second (1 sigma)
+    pub gyro_noise_dps: f32,
+    /// Encoder heading noise per cm of wheel travel, radians (1 sigma)
+    pub encoder_noise_per_cm: f32,
+    /// Encoder distance noise per cm travelled, cm (1 sigma)
+    pub distance_noise_per_cm: f32,
+    /// Disagreement, in standard deviations, beyond which the wheels are
+    /// assumed to be slipping
+    pub slip_gate: f32,
+    /// Fuse `gyro_z` at all (off for links that never report it)
+    pub use_gyro: bool,
+}
+
+impl FusionConfig {
+    /// Tuned for the CyberPi gyro and mBot2 encoder motors
+    pub const DEFAULT: Self = Self {
+        gyro_noise_dps: 1.0,
+        encoder_noise_per_cm: 0.01,
+        distance_noise_per_cm: 0.02,
+        slip_gate: 3.0,
+        use_gyro: true,
+    };
+
+    /// Plain wheel odometry (the original behavior)
+    pub const ENCODERS_ONLY: Self = Self {
+        use_gyro: false,
+        ..Self::DEFAULT
+    };
+}
+
+impl Default for FusionConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Estimated pose and its uncertainty
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pose {
+    /// Position in cm from where tracking started
+    pub x: f32,
+    pub y: f32,
+    /// Heading in radians, counter-clockwise positive (not wrapped)
+    pub heading: f32,
+    /// Heading variance, radians squared
+    pub heading_variance: f32,
+    /// Position variance along each axis, cm squared
+    pub position_variance: f32,
+    /// Estimated gyro reading at rest, degrees per second
+    pub gyro_bias_dps: f32,
+}
+
+impl Pose {
+    /// At the origin, facing +x, with no uncertainty
+    pub const ORIGIN: Self = Self {
+        x: 0.0,
+        y: 0.0,
+        heading: 0.0,
+        heading_variance: 0.0,
+        position_variance: 0.0,
+        gyro_bias_dps: 0.0,
+    };
+
+    /// How much to trust the heading: 1.0 when exact, falling towards 0.0
+    pub fn confidence(&self) -> f32 {
+        1.0 / (1.0 + sqrtf(self.heading_variance.max(0.0)) / CONFIDENCE_HEADING_RAD)
+    }
+
+    /// This pose made safe to estimate from. A field that isn't a number
+    /// can't be recovered, so tracking starts again from the origin (keeping
+    /// a sound gyro bias); negative variances become zero.
+    pub fn clamped(&self) -> Self {
+        let fields = [
+            self.x,
+            self.y,
+            self.heading,
+            self.heading_variance,
+            self.position_variance,
+            self.gyro_bias_dps,
+        ];
+        if fields.iter().all(|v| v.is_finite()) {
+            return Self {
+                heading_variance: self.heading_variance.max(0.0),
+                position_variance: self.position_variance.max(0.0),
+                ..*self
+            };
+        }
+        Self {
+            gyro_bias_dps: if self.gyro_bias_dps.is_finite() { self.gyro_bias_dps } else { 0.0 },
+            ..Self::ORIGIN
+        }
+    }
+}
+
+impl Default for Pose {
+    fn default() -> Self {
+        Self::ORIGIN
+    }
+}
+
+/// Dead-reckoning pose tracker
+#[derive(Clone, Debug)]
+pub struct PoseEstimator {
+    config: FusionConfig,
+    pose: Pose,
+}
+
+impl PoseEstimator {
+    pub fn new(config: FusionConfig) -> Self {
+        Self {
+            config,
+            pose: Pose::ORIGIN,
+        }
+    }
+
+    pub fn config(&self) -> &FusionConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: FusionConfig) {
+        self.config = config;
+    }
+
+    pub fn pose(&self) -> &Pose {
+        &self.pose
+    }
+
+    /// Resume from a previously estimated pose
+    pub fn set_pose(&mut self, pose: Pose) {
+        self.pose = pose;
+    }
+
+    /// Return to the origin, keeping the learned gyro bias
+    pub fn reset(&mut self) {
+        self.pose = Pose {
+            gyro_bias_dps: self.pose.gyro_bias_dps,
+            ..Pose::ORIGIN
+        };
+    }
+
+    /// Advance by one tick of wheel travel and gyro rate (None if the gyro
+    /// wasn't read); returns true if the encoders were rejected as slipping
+    pub fn update(
+        &mut self,
+        left_cm: f32,
+        right_cm: f32,
+        wheel_base_cm: f32,
+        gyro_z_dps: Option<f32>,
+        dt_us: u64,
+    ) -> bool {
+        self.update_with(left_cm, right_cm, wheel_base_cm, gyro_z_dps, dt_us)
+    }
+
+    /// `update` in a particular numeric backend.
+    ///
+    /// Angular uncertainties are carried in units of `N::SIGMA_SCALE` radians
+    /// so they stay representable in fixed point. In Q16 the heading
+    /// variance saturates at about 0.03 rad² (10° of doubt); `reset` after a
+    /// long run.
+    pub fn update_with<N: Real>(
+        &mut self,
+        left_cm: N,
+        right_cm: N,
+        wheel_base_cm: N,
+        gyro_z_dps: Option<N>,
+        dt_us: u64,
+    ) -> bool {
+        let c = &self.config;
+        let num = N::from_f32;
+        let scale = num(N::SIGMA_SCALE);
+        let dt = N::from_ratio(dt_us, 1_000_000);
+
+        let mut pose = ScaledPose::<N>::from_pose(&self.pose);
+
+        let encoder_turn = (right_cm - left_cm) / wheel_base_cm;
+        let travel = left_cm.abs() + right_cm.abs();
+        let encoder_noise = num(c.encoder_noise_per_cm * N::SIGMA_SCALE);
+        let min_variance = num(MIN_ENCODER_VARIANCE * N::SIGMA_SCALE * N::SIGMA_SCALE);
+        let encoder_var = (encoder_noise * encoder_noise * travel).max(min_variance);
+
+        let gyro = gyro_z_dps.filter(|_| c.use_gyro);
+        let (turn, turn_var, slipping) = if let Some(gyro_z_dps) = gyro {
+            // Still wheels: whatever the gyro reads is its bias
+            if left_cm == N::ZERO && right_cm == N::ZERO {
+                pose.gyro_bias_dps = pose.gyro_bias_dps + num(BIAS_ALPHA) * (gyro_z_dps - pose.gyro_bias_dps);
+            }
+
+            let gyro_turn = (gyro_z_dps - pose.gyro_bias_dps).to_radians() * dt;
+            let gyro_sigma = num(c.gyro_noise_dps.to_radians() * N::SIGMA_SCALE) * dt;
+            let gyro_var = gyro_sigma * gyro_sigma;
+
+            let innovation = encoder_turn - gyro_turn;
+            let scaled_innovation = innovation * scale;
+            let total_var = gyro_var + encoder_var;
+            let gate = num(c.slip_gate);
+            if scaled_innovation * scaled_innovation > gate * gate * total_var {
+                (gyro_turn, gyro_var, true)
+            } else {
+                let gain = gyro_var / total_var;
+                (gyro_turn + gain * innovation, gyro_var * encoder_var / total_var, false)
+            }
+        } else {
+            (encoder_turn, encoder_var, false)
+        };
+
+        // A slipping wheel over-reports travel: trust whichever wheel agrees
+        // with the fused turn and moved least
+        let forward = if slipping {
+            let from_left = left_cm + turn * wheel_base_cm / num(2.0);
+            let from_right = right_cm - turn * wheel_base_cm / num(2.0);
+            if from_left.abs() < from_right.abs() { from_left } else { from_right }
+        } else {
+            (left_cm + right_cm) / num(2.0)
+        };
+
+        pose.heading = pose.heading + turn;
+        pose.heading_variance = pose.heading_variance + turn_var;
+        pose.x = pose.x + forward * pose.heading.cos();
+        pose.y = pose.y + forward * pose.heading.sin();
+
+        let distance_sigma = num(c.distance_noise_per_cm) * forward.abs();
+        pose.position_variance = pose.position_variance
+            + (distance_sigma * distance_sigma + forward * forward / scale * (pose.heading_variance / scale));
+
+        pose.store(&mut self.pose);
+        slipping
+    }
+}
+
+/// A `Pose` in some numeric backend, with heading variance in
+/// `N::SIGMA_SCALE`-scaled units
+struct ScaledPose<N> {
+    x: N,
+    y: N,
+    heading: N,
+    heading_variance: N,
+    position_variance: N,
+    gyro_bias_dps: N,
+}
+
+impl<N: Real> ScaledPose<N> {
+    fn from_pose(pose: &Pose) -> Self {
+        let variance_scale = N::SIGMA_SCALE * N::SIGMA_SCALE;
+        Self {
+            x: N::from_f32(pose.x),
+            y: N::from_f32(pose.y),
+            heading: N::from_f32(pose.heading),
+            heading_variance: N::from_f32(pose.heading_variance * variance_scale),
+            position_variance: N::from_f32(pose.position_variance),
+            gyro_bias_dps: N::from_f32(pose.gyro_bias_dps),
+        }
+    }
+
+    fn store(&self, pose: &mut Pose) {
+        let variance_scale = N::SIGMA_SCALE * N::SIGMA_SCALE;
+        *pose = Pose {
+            x: self.x.to_f32(),
+            y: self.y.to_f32(),
+            heading: self.heading.to_f32(),
+            heading_variance: self.heading_variance.to_f32() / variance_scale,
+            position_variance: self.position_variance.to_f32(),
+            gyro_bias_dps: self.gyro_bias_dps.to_f32(),
+        };
+    }
+}
+
+impl Default for PoseEstimator {
+    fn default() -> Self {
+        Self::new(FusionConfig::DEFAULT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Rng;
+
+    const DT: u64 = 50_000;
+    const BASE: f32 = 10.0;
+
+    /// Ground truth for a robot tracing a square, plus what its sensors saw
+    struct Drive {
+        truth: Pose,
+        fused: PoseEstimator,
+        encoders: PoseEstimator,
+        rng: Rng,
+    }
+
+    impl Drive {
+        fn new() -> Self {
+            Self {
+                truth: Pose::ORIGIN,
+                fused: PoseEstimator::default(),
+                encoders: PoseEstimator::new(FusionConfig::ENCODERS_ONLY),
+                rng: Rng::new(9),
+            }
+        }
+
+        /// One tick of true wheel travel; `slip_cm` is phantom left-wheel travel
+        fn step(&mut self, left_cm: f32, right_cm: f32, slip_cm: f32) {
+            let dt = DT as f32 / 1_000_000.0;
+            let turn = (right_cm - left_cm) / BASE;
+            let forward = (left_cm + right_cm) / 2.0;
+            self.truth.heading += turn;
+            self.truth.x += forward * cosf(self.truth.heading);
+            self.truth.y += forward * sinf(self.truth.heading);
+
+            // Gyro: true rate plus bias plus noise
+            let gyro = (turn / dt).to_degrees() + 0.3 + self.rng.range_f32(-0.5, 0.5);
+            let measured_left = left_cm + slip_cm;
+            self.fused.update(measured_left, right_cm, BASE, Some(gyro), DT);
+            self.encoders.update(measured_left, right_cm, BASE, Some(gyro), DT);
+        }
+
+        fn square(&mut self, slip_cm: f32) {
+            // Sit still long enough to learn the gyro bias
+            for _ in 0..200 {
+                self.step(0.0, 0.0, 0.0);
+            }
+            for side in 0..4 {
+                for tick in 0..40 {
+                    let slip = if side == 1 && (10..20).contains(&tick) { slip_cm } else { 0.0 };
+                    self.step(1.0, 1.0, slip);
+                }
+                // Quarter turn in place over 20 ticks
+                let arc = core::f32::consts::FRAC_PI_2 * BASE / 2.0 / 20.0;
+                for _ in 0..20 {
+                    self.step(-arc, arc, 0.0);
+                }
+            }
+        }
+    }
+
+    fn position_error(a: &Pose, b: &Pose) -> f32 {
+        sqrtf((a.x - b.x) * (a.x - b.x) + (a.y - b.y) * (a.y - b.y))
+    }
+
+    #[test]
+    fn test_tracks_truth_without_slip() {
+        let mut drive = Drive::new();
+        drive.square(0.0);
+        let fused = drive.fused.pose();
+        assert!((fused.heading - drive.truth.heading).abs() < 0.05);
+        assert!(position_error(fused, &drive.truth) < 3.0);
+    }
+
+    #[test]
+    fn test_rejects_wheel_slip() {
+        let mut drive = Drive::new();
+        drive.square(0.8);
+
+        let fused_error = (drive.fused.pose().heading - drive.truth.heading).abs();
+        let encoder_error = (drive.encoders.pose().heading - drive.truth.heading).abs();
+        assert!(encoder_error > 0.5, "slip should fool the encoders: {}", encoder_error);
+        assert!(fused_error < 0.05, "fused heading off by {}", fused_error);
+
+        let fused_pos = position_error(drive.fused.pose(), &drive.truth);
+        let encoder_pos = position_error(drive.encoders.pose(), &drive.truth);
+        assert!(fused_pos < 3.0, "fused position off by {}", fused_pos);
+        assert!(fused_pos < encoder_pos);
+    }
+
+    #[test]
+    fn test_slip_is_reported() {
+        let mut estimator = PoseEstimator::default();
+        assert!(!estimator.update(1.0, 1.0, BASE, Some(0.0), DT));
+        assert!(estimator.update(3.0, 1.0, BASE, Some(0.0), DT));
+    }
+
+    #[test]
+    fn test_learns_gyro_bias_at_rest() {
+        let mut estimator = PoseEstimator::default();
+        for _ in 0..500 {
+            estimator.update(0.0, 0.0, BASE, Some(0.7), DT);
+        }
+        assert!((estimator.pose().gyro_bias_dps - 0.7).abs() < 0.01);
+        assert!(estimator.pose().heading.abs() < 0.01);
+    }
+
+    #[test]
+    fn test_confidence_falls_with_travel() {
+        let mut estimator = PoseEstimator::default();
+        assert_eq!(estimator.pose().confidence(), 1.0);
+
+        let mut last = 1.0;
+        for _ in 0..10 {
+            for _ in 0..100 {
+                estimator.update(1.0, 1.0, BASE, Some(0.0), DT);
+            }
+            let confidence = estimator.pose().confidence();
+            assert!(confidence < last);
+            last = confidence;
+        }
+        assert!(estimator.pose().position_variance > 0.0);
+    }
+
+    #[test]
+    fn test_clamped_pose_restarts_from_origin() {
+        let sound = Pose {
+            x: 3.0,
+            y: -2.0,
+            heading_variance: -1.0,
+            gyro_bias_dps: 0.4,
+            ..Pose::ORIGIN
+        };
+        assert_eq!(sound.clamped(), Pose { heading_variance: 0.0, ..sound });
+
+        let broken = Pose { y: f32::INFINITY, ..sound };
+        assert_eq!(broken.clamped(), Pose { gyro_bias_dps: 0.4, ..Pose::ORIGIN });
+        let no_bias = Pose { gyro_bias_dps: f32::NAN, ..sound };
+        assert_eq!(no_bias.clamped(), Pose::ORIGIN);
+    }
+
+    #[test]
+    fn test_encoders_only_matches_plain_odometry() {
+        let mut estimator = PoseEstimator::new(FusionConfig::ENCODERS_ONLY);
+        estimator.update(1.0, 2.0, BASE, Some(45.0), DT);
+        assert!((estimator.pose().heading - 0.1).abs() < 1e-6);
+        assert!((estimator.pose().x - 1.5 * cosf(0.1)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_missing_gyro_falls_back_to_encoders() {
+        let mut estimator = PoseEstimator::default();
+        assert!(!estimator.update(1.0, 2.0, BASE, None, DT));
+        assert!((estimator.pose().heading - 0.1).abs() < 1e-6);
+
+        // The bias isn't learned from a reading that never came
+        estimator.update(0.0, 0.0, BASE, None, DT);
+        assert_eq!(estimator.pose().gyro_bias_dps, 0.0);
+    }
+
+    #[test]
+    fn test_reset_keeps_bias() {
+        let mut estimator = PoseEstimator::default();
+        for _ in 0..200 {
+            estimator.update(0.0, 0.0, BASE, Some(1.0), DT);
+        }
+        estimator.update(5.0, 5.0, BASE, Some(1.0), DT);
+        estimator.reset();
+        assert_eq!(estimator.pose().x, 0.0);
+        assert!(estimator.pose().gyro_bias_dps > 0.9);
+    }
+}
@@ -8,31 +8,55 @@
 
 #![cfg_attr(feature = "no_std", no_std)]
 
-#[cfg(feature = "no_std")]
+// With `no_alloc` nothing here touches the heap, so the crate links into
+// firmware that has no global allocator
+#[cfg(all(feature = "no_std", not(feature = "no_alloc")))]
 extern crate alloc;
 
-#[cfg(feature = "no_std")]
+#[cfg(all(feature = "no_std", not(feature = "no_alloc")))]
 use alloc::vec::Vec;
 
 #[cfg(not(feature = "no_std"))]
 use std::vec::Vec;
 
-// Math functions - use libm for no_std, std for normal builds
-#[cfg(feature = "no_std")]
+// Math functions come from libm in every build, not just no_std: std's
+// depend on the platform's C library, and a recorded session must replay
+// bit-for-bit wherever it runs
 use libm::{sinf, cosf, sqrtf, atan2f, fabsf, powf};
 
-#[cfg(not(feature = "no_std"))]
-mod math {
-    #[inline] pub fn sinf(x: f32) -> f32 { x.sin() }
-    #[inline] pub fn cosf(x: f32) -> f32 { x.cos() }
-    #[inline] pub fn sqrtf(x: f32) -> f32 { x.sqrt() }
-    #[inline] pub fn atan2f(y: f32, x: f32) -> f32 { y.atan2(x) }
-    #[inline] pub fn fabsf(x: f32) -> f32 { x.abs() }
-    #[inline] pub fn powf(x: f32, y: f32) -> f32 { x.powf(y) }
-}
-
-#[cfg(not(feature = "no_std"))]
-use math::*;
+pub mod calibration;
+pub mod fusion;
+pub mod mood;
+pub mod numeric;
+pub mod persist;
+pub mod personality;
+pub mod reflex;
+pub mod rng;
+pub mod snapshot;
+
+pub use calibration::{Calibration, CalibrationError};
+pub use fusion::{FusionConfig, Pose, PoseEstimator};
+pub use mood::MoodMemory;
+pub use numeric::{Real, Scalar, Q16};
+pub use persist::{PersistError, RobotProfile};
+pub use personality::{PersonalityConfig, PersonalityError, PersonalityPreset, PRESETS};
+pub use reflex::{ReflexConfig, ReflexStateMachine, ReflexTransition};
+pub use rng::Rng;
+pub use snapshot::BrainSnapshot;
+
+use reflex::TransitionQueue;
+
+/// Loop period that per-tick personality rates are defined at (20Hz)
+pub const NOMINAL_TICK_US: u64 = 50_000;
+
+/// Longest gap integrated in one tick - bigger gaps (dropped frames, a
+/// stalled link) are clamped so one late frame can't drain all the energy
+pub const MAX_TICK_US: u64 = 500_000;
+
+/// Largest step of the wander noise random walk per nominal tick
+const WANDER_NOISE_STEP: f32 = 0.5;
+/// Wander noise never pushes the motors further than this
+const WANDER_NOISE_MAX: f32 = 5.0;
 
 /// Sensor frame from mBot2 hardware
 #[derive(Clone, Debug, Default)]
@@ -47,7 +71,7 @@ pub struct MBotSensors {
     pub encoder_right: i32,
     /// Quad RGB sensor readings [front_left, front_right, back_left, back_right]
     pub quad_rgb: [[u8; 3]; 4],
-    /// Gyroscope Z-axis (rotation rate)
+    /// Gyroscope Z-axis rotation rate, degrees/second counter-clockwise
     pub gyro_z: f32,
     /// Accelerometer readings [x, y, z]
     pub accel: [f32; 3],
@@ -57,6 +81,48 @@ pub struct MBotSensors {
     pub light_level: f32,
 }
 
+impl MBotSensors {
+    /// A frame before any sensor has answered: every reading is NaN so it
+    /// can't pass for a real zero. Encoders and colours have no such value
+    /// and read 0; links hold the last good value of each field after this.
+    pub const UNREAD: Self = Self {
+        timestamp_us: 0,
+        ultrasonic_cm: f32::NAN,
+        encoder_left: 0,
+        encoder_right: 0,
+        quad_rgb: [[0; 3]; 4],
+        gyro_z: f32::NAN,
+        accel: [f32::NAN; 3],
+        sound_level: f32::NAN,
+        light_level: f32::NAN,
+    };
+
+    /// Ultrasonic distance, or None if there is no reading.
+    ///
+    /// A distance that isn't positive and finite means the sensor hasn't
+    /// answered: `UNREAD` frames and echoes that never came back report NaN
+    /// or 0.
+    pub fn distance_cm(&self) -> Option<f32> {
+        (self.ultrasonic_cm.is_finite() && self.ultrasonic_cm > 0.0).then_some(self.ultrasonic_cm)
+    }
+
+    /// Gyro rate, or None if the gyro hasn't been read (a non-finite
+    /// `gyro_z`). Odometry then runs on the encoders alone.
+    pub fn gyro_dps(&self) -> Option<f32> {
+        self.gyro_z.is_finite().then_some(self.gyro_z)
+    }
+
+    /// Sound level, or None if the microphone hasn't been read
+    pub fn sound(&self) -> Option<f32> {
+        self.sound_level.is_finite().then_some(self.sound_level)
+    }
+
+    /// Accelerometer reading, or None unless every axis has been read
+    pub fn acceleration(&self) -> Option<[f32; 3]> {
+        self.accel.iter().all(|a| a.is_finite()).then_some(self.accel)
+    }
+}
+
 /// Motor command output
 #[derive(Clone, Debug, Default)]
 pub struct MotorCommand {
@@ -64,7 +130,7 @@ pub struct MotorCommand {
     pub left: i8,
     /// Right motor power (-100 to 100)
     pub right: i8,
-    /// Pen servo position (0 = up, 90 = down)
+    /// Pen servo angle, `PEN_UP` or `PEN_DOWN`
     pub pen_angle: u8,
     /// LED color [R, G, B]
     pub led_color: [u8; 3],
@@ -72,8 +138,14 @@ pub struct MotorCommand {
     pub buzzer_hz: u16,
 }
 
+/// Pen servo angle with the pen lifted off the paper
+pub const PEN_UP: u8 = 45;
+/// Pen servo angle with the pen on the paper
+pub const PEN_DOWN: u8 = 90;
+
 /// Reflex modes based on DAG tension levels
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ReflexMode {
     /// Tension < 0.20: Relaxed wandering, learning allowed
     Calm,
@@ -87,11 +159,16 @@ pub enum ReflexMode {
 
 impl ReflexMode {
     pub fn from_tension(tension: f32) -> Self {
-        if tension > 0.85 {
+        Self::from_tension_with(tension, &PersonalityConfig::DEFAULT)
+    }
+
+    /// Classify tension using a personality's reflex thresholds
+    pub fn from_tension_with(tension: f32, personality: &PersonalityConfig) -> Self {
+        if tension > personality.protect_threshold {
             ReflexMode::Protect
-        } else if tension > 0.55 {
+        } else if tension > personality.spike_threshold {
             ReflexMode::Spike
-        } else if tension > 0.20 {
+        } else if tension > personality.active_threshold {
             ReflexMode::Active
         } else {
             ReflexMode::Calm
@@ -135,12 +212,61 @@ impl Default for HomeostasisState {
     }
 }
 
+/// An in-progress blend from one personality to another
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PersonalityTransition {
+    from: PersonalityConfig,
+    to: PersonalityConfig,
+    duration_us: u64,
+    elapsed_us: u64,
+}
+
+impl PersonalityTransition {
+    /// A blend `elapsed_us` into `duration_us`, with both personalities
+    /// clamped into their safe bounds (ARCH-004)
+    pub fn new(
+        from: PersonalityConfig,
+        to: PersonalityConfig,
+        duration_us: u64,
+        elapsed_us: u64,
+    ) -> Self {
+        Self {
+            from: from.clamped(),
+            to: to.clamped(),
+            duration_us,
+            elapsed_us: elapsed_us.min(duration_us),
+        }
+    }
+
+    pub fn from(&self) -> &PersonalityConfig {
+        &self.from
+    }
+
+    pub fn to(&self) -> &PersonalityConfig {
+        &self.to
+    }
+
+    /// Brain time the whole blend takes
+    pub fn duration_us(&self) -> u64 {
+        self.duration_us
+    }
+
+    /// Brain time blended so far
+    pub fn elapsed_us(&self) -> u64 {
+        self.elapsed_us
+    }
+}
+
 /// The core nervous system for mBot2
 pub struct MBotBrain {
+    // Personality traits (always within safe bounds)
+    personality: PersonalityConfig,
+    transition: Option<PersonalityTransition>,
+
     // EMA smoothing
     tension_ema: f32,
     coherence_ema: f32,
-    alpha: f32,
 
     // State tracking
     last_distance: f32,
@@ -155,22 +281,55 @@ pub struct MBotBrain {
 
     // Drawing state
     pen_down: bool,
-    position: (f32, f32),  // Estimated X, Y position
-    heading: f32,          // Heading in radians
+    pose: PoseEstimator,   // Fused gyro + encoder position and heading
+    calibration: Calibration,
 
     // Energy management
     energy: f32,
 
+    // Seeded source of variety (ARCH-002)
+    rng: Rng,
+    wander_noise: f32,
+    investigate_side: f32,  // +1 turns left toward new things, -1 right
+
+    // Brain clock, driven by sensor timestamps
+    last_timestamp_us: Option<u64>,
+    elapsed_us: u64,
+
+    // Reflex mode with hysteresis, plus unread mode changes
+    reflex: ReflexStateMachine,
+    transitions: TransitionQueue,
+
+    // Long-term mood
+    mood: MoodMemory,
+
     // Tick counter
     tick_count: u64,
 }
 
 impl MBotBrain {
     pub fn new() -> Self {
+        Self::with_personality(PersonalityConfig::DEFAULT)
+    }
+
+    /// Create a brain with a custom personality.
+    ///
+    /// Out-of-range traits are clamped into their safe bounds (ARCH-004).
+    pub fn with_personality(personality: PersonalityConfig) -> Self {
+        Self::seeded(personality, rng::DEFAULT_SEED)
+    }
+
+    /// Create a brain whose random choices come from `seed`.
+    ///
+    /// The same seed and the same sensor frames always produce the same
+    /// outputs.
+    pub fn seeded(personality: PersonalityConfig, seed: u64) -> Self {
         Self {
+            personality: personality.clamped(),
+            transition: None,
+
             tension_ema: 0.0,
             coherence_ema: 1.0,
-            alpha: 0.15,
 
             last_distance: 100.0,
             last_encoder_left: 0,
@@ -182,11 +341,23 @@ impl MBotBrain {
             danger_distance: 15.0,
 
             pen_down: false,
-            position: (0.0, 0.0),
-            heading: 0.0,
+            pose: PoseEstimator::default(),
+            calibration: Calibration::DEFAULT,
 
             energy: 1.0,
 
+            rng: Rng::new(seed),
+            wander_noise: 0.0,
+            investigate_side: 1.0,
+
+            last_timestamp_us: None,
+            elapsed_us: 0,
+
+            reflex: ReflexStateMachine::new(ReflexConfig::DEFAULT),
+            transitions: TransitionQueue::new(),
+
+            mood: MoodMemory::FRESH,
+
             tick_count: 0,
         }
     }
@@ -198,135 +369,288 @@ impl MBotBrain {
         self.danger_distance = danger_dist;
     }
 
-    /// Main processing tick - takes sensors, returns motor commands
+    /// Current personality traits (mid-blend while transitioning)
+    pub fn personality(&self) -> &PersonalityConfig {
+        &self.personality
+    }
+
+    /// Switch personality gradually over `duration_us` of brain time.
+    ///
+    /// Every trait is interpolated from the current (possibly already
+    /// blended) personality, so the robot never "snaps" to a new character.
+    /// Internal state - tension, energy, odometry - is kept. A duration of
+    /// zero switches immediately.
+    pub fn transition_to(&mut self, personality: PersonalityConfig, duration_us: u64) {
+        let target = personality.clamped();
+        if duration_us == 0 {
+            self.personality = target;
+            self.transition = None;
+            return;
+        }
+
+        self.transition = Some(PersonalityTransition::new(self.personality, target, duration_us, 0));
+    }
+
+    /// The brain's random source, for callers' own quirks.
+    ///
+    /// Drawing from it changes the brain's later choices, which stays
+    /// deterministic as long as the caller's draws are too.
+    pub fn rng_mut(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+
+    /// Tune reflex hysteresis and dwell times
+    pub fn set_reflex_config(&mut self, config: ReflexConfig) {
+        self.reflex.set_config(config);
+    }
+
+    /// Current reflex hysteresis and dwell times
+    pub fn reflex_config(&self) -> &ReflexConfig {
+        self.reflex.config()
+    }
+
+    /// Current reflex mode
+    pub fn reflex_mode(&self) -> ReflexMode {
+        self.reflex.mode()
+    }
+
+    /// Oldest unread reflex mode change, if any.
+    ///
+    /// Up to `reflex::TRANSITION_QUEUE_LEN` changes are buffered; if they are
+    /// never read, the oldest are dropped.
+    pub fn next_transition(&mut self) -> Option<ReflexTransition> {
+        self.transitions.pop()
+    }
+
+    /// Create a brain from a saved profile, picking up its remembered mood
+    pub fn with_profile(profile: &RobotProfile) -> Self {
+        Self::with_profile_seeded(profile, rng::DEFAULT_SEED)
+    }
+
+    /// [`with_profile`](Self::with_profile), with random choices from `seed`
+    pub fn with_profile_seeded(profile: &RobotProfile, seed: u64) -> Self {
+        let mut brain = Self::seeded(profile.personality, seed);
+        brain.restore_mood(&profile.mood);
+        // Loaded profiles are validated; a hand-built invalid one keeps the default
+        let _ = brain.set_calibration(profile.calibration);
+        brain
+    }
+
+    /// Snapshot of who this robot is, ready to persist
+    pub fn profile(&self) -> RobotProfile {
+        RobotProfile {
+            personality: self.transition.map_or(self.personality, |t| t.to),
+            mood: self.mood,
+            calibration: self.calibration,
+        }
+    }
+
+    /// Use measured odometry constants; implausible ones are rejected
+    pub fn set_calibration(&mut self, calibration: Calibration) -> Result<(), CalibrationError> {
+        calibration.validate()?;
+        self.calibration = calibration;
+        Ok(())
+    }
+
+    pub fn calibration(&self) -> &Calibration {
+        &self.calibration
+    }
+
+    /// Accumulated long-term mood
+    pub fn mood(&self) -> &MoodMemory {
+        &self.mood
+    }
+
+    /// Resume from a remembered mood: energy carries over and tension
+    /// starts from the long-term average instead of zero
+    pub fn restore_mood(&mut self, mood: &MoodMemory) {
+        self.mood = mood.clamped();
+        self.energy = self.mood.energy;
+        self.tension_ema = self.mood.long_tension;
+        self.coherence_ema = self.mood.long_coherence;
+    }
+
+    /// Whether a personality transition is still in progress
+    pub fn is_transitioning(&self) -> bool {
+        self.transition.is_some()
+    }
+
+    /// Main processing tick - takes sensors, returns motor commands.
+    ///
+    /// Everything time-dependent uses the time elapsed since the previous
+    /// frame's `timestamp_us`, so behavior is the same at any loop rate.
     pub fn tick(&mut self, sensors: &MBotSensors) -> (HomeostasisState, MotorCommand) {
         self.tick_count += 1;
+        let first_frame = self.last_timestamp_us.is_none();
+        let dt_us = self.advance_clock(sensors.timestamp_us);
 
-        // Update position estimate from encoders
-        self.update_odometry(sensors);
+        // Advance any personality blend before it shapes this tick
+        self.advance_transition(dt_us);
+
+        // Update position estimate from encoders and gyro. The first frame
+        // only latches the encoders: there is no interval to integrate yet.
+        if !first_frame {
+            self.update_odometry(sensors, dt_us);
+        }
+
+        // A missing ultrasonic reading keeps the last one rather than
+        // reading as an obstacle touching the robot
+        let distance = sensors.distance_cm().unwrap_or(self.last_distance);
 
         // Compute homeostasis
-        let state = self.compute_homeostasis(sensors);
+        let state = self.compute_homeostasis(sensors, dt_us);
+
+        // Random variety for this tick's movement
+        self.update_exploration(distance, dt_us);
 
         // Generate motor command based on state
-        let cmd = self.generate_command(sensors, &state);
+        let cmd = self.generate_command(distance, &state);
 
         // Update last values
-        self.last_distance = sensors.ultrasonic_cm;
+        self.last_distance = distance;
         self.last_encoder_left = sensors.encoder_left;
         self.last_encoder_right = sensors.encoder_right;
 
         (state, cmd)
     }
 
-    fn compute_homeostasis(&mut self, sensors: &MBotSensors) -> HomeostasisState {
-        // === TENSION CALCULATION ===
-
-        // Proximity tension (closer = more tense)
-        let proximity = if sensors.ultrasonic_cm < 100.0 {
-            1.0 - (sensors.ultrasonic_cm / 100.0)
-        } else {
-            0.0
+    /// Advance the brain clock to a new frame and return the step in µs.
+    ///
+    /// - The first frame counts as one nominal tick.
+    /// - A 32-bit sensor clock wrapping past zero is stitched back together.
+    /// - Duplicate or out-of-order timestamps take no time and never move
+    ///   the clock backwards: a repeated frame is not 50ms of new experience.
+    /// - Gaps longer than `MAX_TICK_US` are clamped.
+    fn advance_clock(&mut self, timestamp_us: u64) -> u64 {
+        let dt_us = match self.last_timestamp_us {
+            None => NOMINAL_TICK_US,
+            Some(last) if timestamp_us > last => timestamp_us - last,
+            Some(last) => match wrapped_u32_delta(last, timestamp_us) {
+                Some(dt_us) => dt_us,
+                None => return 0,
+            },
         };
 
-        // Sudden change tension
-        let distance_delta = fabsf(sensors.ultrasonic_cm - self.last_distance);
-        let change_tension = (distance_delta / 50.0).min(1.0);
-
-        // Sound tension
-        let sound_tension = sensors.sound_level * 0.5;
-
-        // Movement tension (from accelerometer)
-        let accel_magnitude = sqrtf(powf(sensors.accel[0], 2.0) +
-                                    powf(sensors.accel[1], 2.0) +
-                                    powf(sensors.accel[2], 2.0));
-        let movement_tension = (accel_magnitude / 20.0).min(1.0);
-
-        // Combined raw tension
-        let raw_tension = (proximity * 0.5 +
-                          change_tension * 0.2 +
-                          sound_tension * 0.15 +
-                          movement_tension * 0.15).min(1.0);
+        self.last_timestamp_us = Some(timestamp_us);
+        let dt_us = dt_us.min(MAX_TICK_US);
+        self.elapsed_us += dt_us;
+        dt_us
+    }
 
-        // EMA smoothing
-        self.tension_ema = self.alpha * raw_tension + (1.0 - self.alpha) * self.tension_ema;
+    /// Time since the first frame, as seen by the brain (µs)
+    pub fn elapsed_us(&self) -> u64 {
+        self.elapsed_us
+    }
 
-        // === COHERENCE CALCULATION ===
+    fn advance_transition(&mut self, dt_us: u64) {
+        if let Some(mut transition) = self.transition {
+            transition.elapsed_us += dt_us;
+            if transition.elapsed_us >= transition.duration_us {
+                self.personality = transition.to;
+                self.transition = None;
+            } else {
+                let t = transition.elapsed_us as f32 / transition.duration_us as f32;
+                self.personality = transition.from.lerp(&transition.to, t);
+                self.transition = Some(transition);
+            }
+        }
+    }
 
-        // Coherence drops with high/unstable tension
-        let tension_instability = fabsf(raw_tension - self.tension_ema);
-        let raw_coherence = 1.0 - (self.tension_ema * 0.4 + tension_instability * 0.6);
-        self.coherence_ema = self.alpha * raw_coherence + (1.0 - self.alpha) * self.coherence_ema;
+    fn compute_homeostasis(&mut self, sensors: &MBotSensors, dt_us: u64) -> HomeostasisState {
+        let p = self.personality;
+        // Tension, coherence, energy and curiosity, in the selected backend
+        let mut affect = Affect::<Scalar>::from_f32(self.tension_ema, self.coherence_ema, self.energy);
+        let curiosity = affect
+            .feel(&p, sensors, self.last_distance, self.danger_distance, dt_us)
+            .to_f32();
+        (self.tension_ema, self.coherence_ema, self.energy) = affect.to_f32();
 
-        // === ENERGY ===
+        // === REFLEX ===
 
-        // Energy depletes with high tension, recovers when calm
-        if self.tension_ema > 0.5 {
-            self.energy = (self.energy - 0.001).max(0.1);
-        } else {
-            self.energy = (self.energy + 0.0005).min(1.0);
+        let transition = self.reflex.update(self.tension_ema, &p, self.tick_count, dt_us);
+        if let Some(t) = transition {
+            self.transitions.push(t);
         }
 
-        // === CURIOSITY ===
-
-        // Curiosity increases when things are novel but not threatening
-        let curiosity = if self.tension_ema > 0.2 && self.tension_ema < 0.6 {
-            (self.coherence_ema * 0.7 + change_tension * 0.3).min(1.0)
-        } else {
-            0.2
-        };
-
-        HomeostasisState {
+        let state = HomeostasisState {
             tension: self.tension_ema.clamp(0.0, 1.0),
             coherence: self.coherence_ema.clamp(0.0, 1.0),
-            reflex: ReflexMode::from_tension(self.tension_ema),
+            reflex: self.reflex.mode(),
             energy: self.energy,
             curiosity,
+        };
+
+        // === MOOD MEMORY ===
+        let startled = transition.is_some_and(|t| mood::is_startle(t.from, t.to));
+        self.mood.record(state.tension, state.coherence, state.energy, startled, dt_us);
+
+        state
+    }
+
+    fn update_exploration(&mut self, distance: f32, dt_us: u64) {
+        let dt_ratio = dt_us as f32 / NOMINAL_TICK_US as f32;
+
+        // Wander noise: a bounded random walk so wandering isn't a pure sine
+        let step = self.rng.range_f32(-1.0, 1.0) * WANDER_NOISE_STEP * dt_ratio;
+        self.wander_noise = (self.wander_noise + step).clamp(-WANDER_NOISE_MAX, WANDER_NOISE_MAX);
+
+        // Exploratory choice: when something new appears ahead, pick a side
+        // to investigate it from
+        let ahead = distance < self.approach_distance;
+        let was_ahead = self.last_distance < self.approach_distance;
+        if ahead && !was_ahead {
+            self.investigate_side = if self.rng.chance(0.5) { 1.0 } else { -1.0 };
         }
     }
 
-    fn generate_command(&self, sensors: &MBotSensors, state: &HomeostasisState) -> MotorCommand {
+    fn generate_command(&self, distance: f32, state: &HomeostasisState) -> MotorCommand {
+        // Expressive personalities move bigger and faster (1.0 at default)
+        let gain = 0.5 + self.personality.movement_expressiveness;
+
+        // Wander phases follow brain time, not tick count
+        let t = self.elapsed_us as f32 / 1_000_000.0;
+
         let (left, right) = match state.reflex {
             ReflexMode::Calm => {
                 // Gentle wandering with occasional turns
-                let wander = sinf((self.tick_count as f32) * 0.05) * 10.0;
-                let speed = self.base_speed * 0.6 * self.energy;
+                let wander = (sinf(t * 1.0) * 10.0 + self.wander_noise) * gain;
+                let speed = self.base_speed * 0.6 * self.energy * gain;
                 ((speed + wander) as i8, (speed - wander) as i8)
             }
 
             ReflexMode::Active => {
                 // Active exploration - follow interesting stimuli
-                let turn = if sensors.ultrasonic_cm < self.approach_distance {
+                let turn = if distance < self.approach_distance {
                     // Something ahead - turn slightly to investigate
-                    (state.curiosity * self.turn_gain) as i8
+                    (state.curiosity * self.turn_gain * self.investigate_side) as i8
                 } else {
                     // Wander more actively
-                    let wander = sinf((self.tick_count as f32) * 0.1) * 20.0;
+                    let wander = (sinf(t * 2.0) * 20.0 + self.wander_noise) * gain;
                     wander as i8
                 };
 
-                let speed = (self.base_speed * 0.8 * self.energy) as i8;
-                (speed - turn, speed + turn)
+                let speed = (self.base_speed * 0.8 * self.energy * gain) as i8;
+                (speed.saturating_sub(turn), speed.saturating_add(turn))
             }
 
             ReflexMode::Spike => {
                 // Excited! Move faster, more erratic
                 let excitement = state.tension * 30.0;
-                let speed = (self.base_speed * self.energy + excitement) as i8;
+                let speed = (self.base_speed * self.energy * gain + excitement) as i8;
 
                 // Sharp turns based on stimuli
-                let turn = if sensors.ultrasonic_cm < 30.0 {
+                let turn = if distance < 30.0 {
                     40_i8  // Sharp turn toward interesting thing
                 } else {
-                    (sinf(self.tick_count as f32 * 0.2) * 25.0) as i8
+                    (sinf(t * 4.0) * 25.0) as i8
                 };
 
-                ((speed - turn).clamp(-100, 100), (speed + turn).clamp(-100, 100))
+                (speed.saturating_sub(turn), speed.saturating_add(turn))
             }
 
             ReflexMode::Protect => {
                 // DANGER! Back away
-                if sensors.ultrasonic_cm < self.danger_distance {
+                if distance < self.danger_distance {
                     (-60, -60)  // Back up
                 } else {
                     // Turn away from threat
@@ -336,36 +660,32 @@ impl MBotBrain {
         };
 
         MotorCommand {
-            left,
-            right,
-            pen_angle: if self.pen_down { 90 } else { 45 },
+            left: left.clamp(-100, 100),
+            right: right.clamp(-100, 100),
+            pen_angle: if self.pen_down { PEN_DOWN } else { PEN_UP },
             led_color: state.reflex.led_color(),
             buzzer_hz: if state.reflex == ReflexMode::Protect { 440 } else { 0 },
         }
     }
 
-    fn update_odometry(&mut self, sensors: &MBotSensors) {
+    fn update_odometry(&mut self, sensors: &MBotSensors, dt_us: u64) {
         // Calculate wheel movement
         let left_delta = sensors.encoder_left - self.last_encoder_left;
         let right_delta = sensors.encoder_right - self.last_encoder_right;
 
-        // Convert to distance (approximate)
-        const TICKS_PER_CM: f32 = 10.0;  // Calibrate this!
-        const WHEEL_BASE: f32 = 10.0;    // Distance between wheels in cm
-
-        let left_dist = left_delta as f32 / TICKS_PER_CM;
-        let right_dist = right_delta as f32 / TICKS_PER_CM;
-
-        // Calculate movement
-        let forward = (left_dist + right_dist) / 2.0;
-        let rotation = (right_dist - left_dist) / WHEEL_BASE;
-
-        // Update heading
-        self.heading += rotation;
-
-        // Update position
-        self.position.0 += forward * cosf(self.heading);
-        self.position.1 += forward * sinf(self.heading);
+        // Convert to distance
+        let c = &self.calibration;
+        let ticks_per_cm = Scalar::from_f32(c.ticks_per_cm);
+        let left_dist = Scalar::from_int(left_delta) / ticks_per_cm;
+        let right_dist = Scalar::from_int(right_delta) / ticks_per_cm;
+
+        self.pose.update_with(
+            left_dist,
+            right_dist,
+            Scalar::from_f32(c.wheel_base_cm),
+            sensors.gyro_dps().map(Scalar::from_f32),
+            dt_us,
+        );
     }
 
     // === DRAWING METHODS ===
@@ -377,18 +697,32 @@ impl MBotBrain {
 
     /// Get current estimated position
     pub fn position(&self) -> (f32, f32) {
-        self.position
+        let pose = self.pose.pose();
+        (pose.x, pose.y)
     }
 
     /// Get current heading in radians
     pub fn heading(&self) -> f32 {
-        self.heading
+        self.pose.pose().heading
+    }
+
+    /// Full pose estimate, including its uncertainty
+    pub fn pose(&self) -> &Pose {
+        self.pose.pose()
+    }
+
+    /// Choose how much to trust the gyro versus the wheels
+    pub fn set_fusion_config(&mut self, config: FusionConfig) {
+        self.pose.set_config(config);
+    }
+
+    pub fn fusion_config(&self) -> &FusionConfig {
+        self.pose.config()
     }
 
     /// Reset position tracking
     pub fn reset_position(&mut self) {
-        self.position = (0.0, 0.0);
-        self.heading = 0.0;
+        self.pose.reset();
     }
 
     /// Get tick count
@@ -403,6 +737,131 @@ impl Default for MBotBrain {
     }
 }
 
+/// Rescale a per-nominal-tick EMA factor to a tick `dt_ratio` nominal ticks long
+fn scaled_alpha<N: Real>(alpha: N, dt_ratio: N) -> N {
+    if dt_ratio == N::ONE {
+        alpha
+    } else {
+        N::ONE - (N::ONE - alpha).powf(dt_ratio)
+    }
+}
+
+/// The arithmetic of homeostasis, generic over the numeric backend so the
+/// fixed-point build computes exactly what the float one does
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Affect<N> {
+    pub tension_ema: N,
+    pub coherence_ema: N,
+    pub energy: N,
+}
+
+impl<N: Real> Affect<N> {
+    pub fn from_f32(tension_ema: f32, coherence_ema: f32, energy: f32) -> Self {
+        Self {
+            tension_ema: N::from_f32(tension_ema),
+            coherence_ema: N::from_f32(coherence_ema),
+            energy: N::from_f32(energy),
+        }
+    }
+
+    pub fn to_f32(self) -> (f32, f32, f32) {
+        (self.tension_ema.to_f32(), self.coherence_ema.to_f32(), self.energy.to_f32())
+    }
+
+    /// Take in one frame; returns curiosity
+    pub fn feel(
+        &mut self,
+        p: &PersonalityConfig,
+        sensors: &MBotSensors,
+        last_distance: f32,
+        danger_distance: f32,
+        dt_us: u64,
+    ) -> N {
+        let num = N::from_f32;
+
+        // Personality rates are per nominal tick; scale them to this frame
+        let dt_ratio = N::from_ratio(dt_us, NOMINAL_TICK_US);
+        let alpha = scaled_alpha(num(p.smoothing), dt_ratio);
+        // Without a reading the last distance holds: no sudden change and
+        // no phantom obstacle
+        let distance = num(sensors.distance_cm().unwrap_or(last_distance));
+
+        // === TENSION CALCULATION ===
+
+        // Proximity tension (closer = more tense)
+        let proximity = if distance < num(100.0) {
+            N::ONE - (distance / num(100.0))
+        } else {
+            N::ZERO
+        };
+
+        // Sudden change tension (distance change per nominal tick)
+        let distance_delta = (distance - num(last_distance)).abs();
+        let change_tension = if dt_ratio > N::ZERO {
+            (distance_delta / dt_ratio / num(50.0)).min(N::ONE)
+        } else {
+            N::ZERO
+        };
+
+        // Sound tension; silence until the microphone has been read
+        let sound_tension = num(sensors.sound().unwrap_or(0.0)) * num(0.5);
+
+        // Movement tension (from accelerometer), none until it's been read
+        let [ax, ay, az] = sensors.acceleration().unwrap_or_default().map(num);
+        let accel_magnitude = (ax * ax + ay * ay + az * az).sqrt();
+        let movement_tension = (accel_magnitude / num(20.0)).min(N::ONE);
+
+        // Combined raw tension
+        let mut raw_tension = (proximity * num(p.proximity_weight) +
+                              change_tension * num(p.change_weight) +
+                              sound_tension * num(p.sound_weight) +
+                              movement_tension * num(p.movement_weight)).min(N::ONE);
+
+        // Danger reflex: something inside the danger zone is always alarming,
+        // no matter how relaxed the personality is
+        if distance < num(danger_distance) {
+            raw_tension = N::ONE;
+        }
+
+        // EMA smoothing
+        self.tension_ema = alpha * raw_tension + (N::ONE - alpha) * self.tension_ema;
+
+        // === COHERENCE CALCULATION ===
+
+        // Coherence drops with high/unstable tension
+        let tension_instability = (raw_tension - self.tension_ema).abs();
+        let raw_coherence = N::ONE - (self.tension_ema * num(0.4) + tension_instability * num(0.6));
+        self.coherence_ema = alpha * raw_coherence + (N::ONE - alpha) * self.coherence_ema;
+
+        // === ENERGY ===
+
+        // Energy depletes with high tension, recovers when calm
+        if self.tension_ema > num(p.fatigue_threshold) {
+            self.energy = (self.energy - num(p.energy_drain) * dt_ratio).max(num(0.1));
+        } else {
+            self.energy = (self.energy + num(p.energy_recovery) * dt_ratio).min(N::ONE);
+        }
+
+        // === CURIOSITY ===
+
+        // Curiosity increases when things are novel but not threatening
+        if self.tension_ema > num(p.curiosity_low) && self.tension_ema < num(p.curiosity_high) {
+            (self.coherence_ema * num(0.7) + change_tension * num(0.3)).min(N::ONE)
+        } else {
+            num(0.2)
+        }
+    }
+}
+
+/// Forward step of a 32-bit µs clock that wrapped past zero, if plausible
+fn wrapped_u32_delta(last: u64, now: u64) -> Option<u64> {
+    if last > u32::MAX as u64 || now > u32::MAX as u64 {
+        return None;
+    }
+    let delta = (now as u32).wrapping_sub(last as u32) as u64;
+    (delta > 0 && delta <= MAX_TICK_US).then_some(delta)
+}
+
 // === DRAWING HELPERS ===
 
 /// Calculate motor powers to drive to a target position
@@ -412,62 +871,80 @@ pub fn drive_to_point(
     target: (f32, f32),
     base_speed: f32,
 ) -> (i8, i8) {
+    let num = Scalar::from_f32;
+    drive_to_point_with::<Scalar>(
+        (num(current.0), num(current.1)),
+        num(heading),
+        (num(target.0), num(target.1)),
+        num(base_speed),
+    )
+}
+
+/// `drive_to_point` in a particular numeric backend
+pub fn drive_to_point_with<N: Real>(current: (N, N), heading: N, target: (N, N), base_speed: N) -> (i8, i8) {
     let dx = target.0 - current.0;
     let dy = target.1 - current.1;
-    let distance = sqrtf(dx * dx + dy * dy);
+    let distance = (dx * dx + dy * dy).sqrt();
 
-    if distance < 1.0 {
+    if distance < N::ONE {
         return (0, 0);  // Close enough
     }
 
-    let target_angle = atan2f(dy, dx);
-    let angle_diff = normalize_angle(target_angle - heading);
+    let target_angle = dy.atan2(dx);
+    let angle_diff = normalize_angle_with(target_angle - heading);
 
     // Proportional control
-    let turn = (angle_diff * 50.0).clamp(-base_speed, base_speed);
-    let speed = base_speed * (1.0 - (fabsf(angle_diff) / core::f32::consts::PI));
+    let turn = (angle_diff * N::from_f32(50.0)).clamp(-base_speed, base_speed);
+    let speed = base_speed * (N::ONE - (angle_diff.abs() / N::PI));
 
     (
-        (speed - turn) as i8,
-        (speed + turn) as i8,
+        (speed - turn).to_i8(),
+        (speed + turn).to_i8(),
     )
 }
 
 /// Normalize angle to [-PI, PI]
 pub fn normalize_angle(angle: f32) -> f32 {
+    normalize_angle_with(angle)
+}
+
+fn normalize_angle_with<N: Real>(angle: N) -> N {
+    let turn = N::from_f32(2.0) * N::PI;
     let mut a = angle;
-    while a > core::f32::consts::PI {
-        a -= 2.0 * core::f32::consts::PI;
+    while a > N::PI {
+        a = a - turn;
     }
-    while a < -core::f32::consts::PI {
-        a += 2.0 * core::f32::consts::PI;
+    while a < -N::PI {
+        a = a + turn;
     }
     a
 }
 
 /// Generate points for a circle
-/// Generate points for a circle
+#[cfg(not(feature = "no_alloc"))]
 pub fn circle_points_vec(center: (f32, f32), radius: f32, segments: usize) -> Vec<(f32, f32)> {
-    let mut points = Vec::with_capacity(segments + 1);
-    for i in 0..=segments {
-        let angle = (i as f32 / segments as f32) * 2.0 * core::f32::consts::PI;
-        points.push((
-            center.0 + radius * cosf(angle),
-            center.1 + radius * sinf(angle),
-        ));
-    }
-    points
+    circle_points(center, radius, segments).collect()
 }
 
-#[cfg(feature = "std")]
+/// Points around a circle, `segments + 1` of them so the last closes it
 pub fn circle_points(center: (f32, f32), radius: f32, segments: usize) -> impl Iterator<Item = (f32, f32)> {
-    (0..=segments).map(move |i| {
-        let angle = (i as f32 / segments as f32) * 2.0 * core::f32::consts::PI;
-        (
-            center.0 + radius * cosf(angle),
-            center.1 + radius * sinf(angle),
-        )
-    })
+    (0..=segments).map(move |i| circle_point(center, radius, i as f32 / segments as f32))
+}
+
+/// `N` points around a circle, the last closing it; no heap needed
+pub fn circle_points_array<const N: usize>(center: (f32, f32), radius: f32) -> [(f32, f32); N] {
+    let segments = N.saturating_sub(1).max(1) as f32;
+    core::array::from_fn(|i| circle_point(center, radius, i as f32 / segments))
+}
+
+/// The point `fraction` of the way around a circle, counter-clockwise from
+/// the +x axis
+fn circle_point(center: (f32, f32), radius: f32, fraction: f32) -> (f32, f32) {
+    let angle = fraction * 2.0 * core::f32::consts::PI;
+    (
+        center.0 + radius * cosf(angle),
+        center.1 + radius * sinf(angle),
+    )
 }
 
 /// Generate points for an X
@@ -486,6 +963,10 @@ pub fn x_points(center: (f32, f32), size: f32) -> [(f32, f32); 5] {
 mod tests {
     use super::*;
 
+    // The test harness always has a heap, whatever the library is built with
+    extern crate std;
+    use std::vec::Vec;
+
     #[test]
     fn test_reflex_modes() {
         assert_eq!(ReflexMode::from_tension(0.1), ReflexMode::Calm);
@@ -514,9 +995,11 @@ mod tests {
     fn test_protect_mode_backs_up() {
         let mut brain = MBotBrain::new();
 
-        // Simulate something very close
-        for _ in 0..20 {
+        // Simulate something very close. Proximity alone tops out below the
+        // Protect threshold; inside danger_distance the danger reflex takes over
+        for tick in 0..20 {
             let sensors = MBotSensors {
+                timestamp_us: tick * 50_000,
                 ultrasonic_cm: 5.0,  // Very close!
                 ..Default::default()
             };
@@ -531,6 +1014,452 @@ mod tests {
         panic!("Should have entered Protect mode");
     }
 
+    #[test]
+    fn test_missing_distance_is_not_danger() {
+        let mut brain = MBotBrain::new();
+        for tick in 0..100 {
+            // No ultrasonic reading yet, then one that came back garbled
+            let ultrasonic_cm = if tick < 50 { 0.0 } else { f32::NAN };
+            let (state, cmd) = brain.tick(&MBotSensors {
+                timestamp_us: tick * 50_000,
+                ultrasonic_cm,
+                ..Default::default()
+            });
+            assert_eq!(state.reflex, ReflexMode::Calm, "tick {}", tick);
+            assert!(cmd.left > 0 && cmd.right > 0, "backed up at tick {}", tick);
+        }
+        assert_eq!(brain.next_transition(), None);
+    }
+
+    #[test]
+    fn test_unread_frame_is_calm() {
+        let mut brain = MBotBrain::new();
+        let mut calm = MBotBrain::new();
+        for tick in 0..50 {
+            let timestamp_us = tick * 50_000;
+            let (state, cmd) = brain.tick(&MBotSensors { timestamp_us, ..MBotSensors::UNREAD });
+            let (expected, expected_cmd) = calm.tick(&MBotSensors { timestamp_us, ..Default::default() });
+            assert!(state.tension.is_finite() && state.coherence.is_finite() && state.energy.is_finite());
+            assert_eq!(state.tension, expected.tension, "tick {}", tick);
+            assert_eq!((cmd.left, cmd.right), (expected_cmd.left, expected_cmd.right));
+        }
+        assert_eq!(brain.position(), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_turns_on_encoders_without_gyro() {
+        let mut brain = MBotBrain::new();
+        assert!(brain.fusion_config().use_gyro);
+        for tick in 0..10 {
+            // Spinning in place, 1cm per wheel per tick, and no gyro reading
+            brain.tick(&MBotSensors {
+                timestamp_us: tick * 50_000,
+                encoder_left: -10 * tick as i32,
+                encoder_right: 10 * tick as i32,
+                gyro_z: f32::NAN,
+                ..Default::default()
+            });
+        }
+        // Nine intervals of 0.2 rad each over the 10cm wheel base
+        assert!((brain.heading() - 1.8).abs() < 1e-4, "heading {}", brain.heading());
+        assert!(brain.position().0.abs() < 1e-4 && brain.position().1.abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_default_personality_matches_new() {
+        let mut a = MBotBrain::new();
+        let mut b = MBotBrain::with_personality(PersonalityConfig::default());
+
+        for i in 0..50 {
+            let sensors = MBotSensors {
+                ultrasonic_cm: 20.0 + (i % 7) as f32 * 10.0,
+                sound_level: 0.3,
+                ..Default::default()
+            };
+            let (sa, ca) = a.tick(&sensors);
+            let (sb, cb) = b.tick(&sensors);
+            assert_eq!(sa.tension, sb.tension);
+            assert_eq!(sa.reflex, sb.reflex);
+            assert_eq!((ca.left, ca.right), (cb.left, cb.right));
+        }
+    }
+
+    #[test]
+    fn test_with_personality_clamps_traits() {
+        let brain = MBotBrain::with_personality(PersonalityConfig {
+            smoothing: 7.0,
+            protect_threshold: 0.01,
+            ..PersonalityConfig::DEFAULT
+        });
+
+        assert_eq!(brain.personality().validate(), Ok(()));
+        assert_eq!(brain.personality().smoothing, 1.0);
+    }
+
+    #[test]
+    fn test_personality_shapes_reflex() {
+        // A jumpy personality with low thresholds reaches Spike on the same
+        // stimulus that keeps the default robot Active
+        let jumpy = PersonalityConfig {
+            smoothing: 0.5,
+            active_threshold: 0.1,
+            spike_threshold: 0.3,
+            protect_threshold: 0.9,
+            ..PersonalityConfig::DEFAULT
+        };
+        let mut calm = MBotBrain::new();
+        let mut nervous = MBotBrain::with_personality(jumpy);
+
+        let sensors = |tick: u64| MBotSensors {
+            timestamp_us: tick * 50_000,
+            ultrasonic_cm: 30.0,
+            ..Default::default()
+        };
+        let mut calm_state = HomeostasisState::default();
+        let mut nervous_state = HomeostasisState::default();
+        for tick in 0..40 {
+            calm_state = calm.tick(&sensors(tick)).0;
+            nervous_state = nervous.tick(&sensors(tick)).0;
+        }
+
+        assert_eq!(calm_state.reflex, ReflexMode::Active);
+        assert_eq!(nervous_state.reflex, ReflexMode::Spike);
+    }
+
+    /// Calm, steady surroundings: any change in motor output comes from the
+    /// personality switch itself
+    fn quiet_room(tick: u64) -> MBotSensors {
+        MBotSensors {
+            timestamp_us: tick * 50_000,
+            ultrasonic_cm: 150.0,
+            accel: [0.0, 0.0, 9.8],
+            sound_level: 0.05,
+            ..Default::default()
+        }
+    }
+
+    fn max_motor_step(brain: &mut MBotBrain, start: u64, ticks: u64, last: &mut MotorCommand) -> i32 {
+        let mut max_step = 0;
+        for tick in start..start + ticks {
+            let (_, cmd) = brain.tick(&quiet_room(tick));
+            let step = (cmd.left as i32 - last.left as i32)
+                .abs()
+                .max((cmd.right as i32 - last.right as i32).abs());
+            max_step = max_step.max(step);
+            *last = cmd;
+        }
+        max_step
+    }
+
+    #[test]
+    fn test_transition_has_no_motor_discontinuity() {
+        let mut snapping = MBotBrain::with_personality(PersonalityConfig::CHILL_CHARLIE);
+        let mut blending = MBotBrain::with_personality(PersonalityConfig::CHILL_CHARLIE);
+
+        let mut last_snap = MotorCommand::default();
+        let mut last_blend = MotorCommand::default();
+        max_motor_step(&mut snapping, 0, 100, &mut last_snap);
+        max_motor_step(&mut blending, 0, 100, &mut last_blend);
+
+        snapping.transition_to(PersonalityConfig::BOUNCY_BETTY, 0);
+        blending.transition_to(PersonalityConfig::BOUNCY_BETTY, 3_000_000);
+
+        let snap_step = max_motor_step(&mut snapping, 100, 100, &mut last_snap);
+        let blend_step = max_motor_step(&mut blending, 100, 100, &mut last_blend);
+
+        assert!(snap_step >= 10, "instant switch should jump, got {}", snap_step);
+        assert!(blend_step <= 2, "blended switch stepped by {}", blend_step);
+        assert!(!blending.is_transitioning());
+        assert_eq!(*blending.personality(), PersonalityConfig::BOUNCY_BETTY);
+    }
+
+    #[test]
+    fn test_transition_preserves_state() {
+        let mut brain = MBotBrain::new();
+        for tick in 0..50 {
+            let mut sensors = quiet_room(tick);
+            sensors.encoder_left = tick as i32 * 10;
+            sensors.encoder_right = tick as i32 * 12;
+            brain.tick(&sensors);
+        }
+        let position = brain.position();
+        let heading = brain.heading();
+
+        brain.transition_to(PersonalityConfig::NERVOUS_NELLIE, 1_500_000);
+
+        assert_eq!(brain.tick_count(), 50);
+        assert_eq!(brain.position(), position);
+        assert_eq!(brain.heading(), heading);
+        assert!(brain.is_transitioning());
+        assert_eq!(*brain.personality(), PersonalityConfig::DEFAULT);
+    }
+
+    #[test]
+    fn test_transition_is_deterministic() {
+        let run = || {
+            let mut brain = MBotBrain::with_personality(PersonalityConfig::CURIOUS_GEORGE);
+            let mut outputs = Vec::new();
+            for tick in 0..120 {
+                if tick == 20 {
+                    brain.transition_to(PersonalityConfig::NERVOUS_NELLIE, 2_000_000);
+                }
+                if tick == 40 {
+                    // Switch again mid-blend
+                    brain.transition_to(PersonalityConfig::CHILL_CHARLIE, 2_000_000);
+                }
+                let mut sensors = quiet_room(tick);
+                sensors.ultrasonic_cm = 30.0 + (tick % 40) as f32;
+                let (state, cmd) = brain.tick(&sensors);
+                outputs.push((state.tension, cmd.left, cmd.right));
+            }
+            outputs
+        };
+
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn test_transition_lasts_the_same_time_at_any_rate() {
+        for hz in [20, 50] {
+            let period_us = 1_000_000 / hz;
+            let mut brain = MBotBrain::new();
+            brain.transition_to(PersonalityConfig::BOUNCY_BETTY, 3_000_000);
+            let mut tick = 0;
+            while brain.is_transitioning() {
+                brain.tick(&MBotSensors { timestamp_us: tick * period_us, ..quiet_room(0) });
+                tick += 1;
+            }
+            let took = brain.elapsed_us();
+            assert!((3_000_000..3_000_000 + period_us).contains(&took), "{}Hz blend took {}µs", hz, took);
+        }
+    }
+
+    #[test]
+    fn test_mood_memory_survives_profile_round_trip() {
+        let mut brain = MBotBrain::with_personality(PersonalityConfig::NERVOUS_NELLIE);
+        for tick in 0..200 {
+            let mut sensors = quiet_room(tick);
+            // Something keeps lunging at the robot
+            if tick % 50 < 15 {
+                sensors.ultrasonic_cm = 5.0;
+            }
+            brain.tick(&sensors);
+        }
+
+        let mood = *brain.mood();
+        assert!(mood.startle_count > 0);
+        assert_eq!(mood.lifetime_us, 200 * NOMINAL_TICK_US);
+        assert!(mood.long_tension > 0.0);
+
+        let bytes = brain.profile().encode();
+        let restored = MBotBrain::with_profile(&RobotProfile::decode(&bytes).unwrap());
+        assert_eq!(*restored.mood(), mood);
+        assert_eq!(*restored.personality(), PersonalityConfig::NERVOUS_NELLIE);
+    }
+
+    #[test]
+    fn test_reflex_does_not_flicker_on_noisy_sensors() {
+        // Ultrasonic noise around the distance where tension sits on the
+        // Active/Spike boundary for a twitchy personality
+        let twitchy = PersonalityConfig {
+            proximity_weight: 0.8,
+            smoothing: 0.6,
+            ..PersonalityConfig::DEFAULT
+        };
+        let noise = [0.0, 6.0, -5.0, 3.0, -7.0, 5.0, -2.0, 4.0, -6.0, 1.0];
+        let sensors = |tick: u64| MBotSensors {
+            timestamp_us: tick * 50_000,
+            ultrasonic_cm: 35.0 + noise[(tick % 10) as usize],
+            ..Default::default()
+        };
+
+        let mut raw = MBotBrain::with_personality(twitchy);
+        raw.set_reflex_config(ReflexConfig::NONE);
+        let mut steady = MBotBrain::with_personality(twitchy);
+
+        let count = |brain: &mut MBotBrain| {
+            let mut changes = 0;
+            for tick in 0..300 {
+                brain.tick(&sensors(tick));
+                while brain.next_transition().is_some() {
+                    changes += 1;
+                }
+            }
+            changes
+        };
+
+        let raw_changes = count(&mut raw);
+        let steady_changes = count(&mut steady);
+        assert!(raw_changes > 20, "input should flicker, got {}", raw_changes);
+        assert!(steady_changes <= 3, "flickered {} times", steady_changes);
+    }
+
+    #[test]
+    fn test_transitions_are_reported_in_order() {
+        let mut brain = MBotBrain::new();
+        let mut transitions = Vec::new();
+        for tick in 0..120 {
+            let distance = if tick < 60 { 5.0 } else { 150.0 };
+            let (state, _) = brain.tick(&MBotSensors {
+                timestamp_us: tick * 50_000,
+                ultrasonic_cm: distance,
+                ..Default::default()
+            });
+            while let Some(t) = brain.next_transition() {
+                assert_eq!(t.to, state.reflex);
+                assert_eq!(t.tick, brain.tick_count());
+                transitions.push(t);
+            }
+        }
+
+        assert!(transitions.iter().any(|t| t.to == ReflexMode::Protect));
+        for pair in transitions.windows(2) {
+            assert_eq!(pair[0].to, pair[1].from);
+            assert!(pair[0].tick < pair[1].tick);
+        }
+        assert_eq!(transitions.last().unwrap().to, brain.reflex_mode());
+    }
+
+    /// The same twelve-second approach and retreat, sampled at `hz`
+    fn approach_session(hz: u64) -> impl Iterator<Item = MBotSensors> {
+        let period_us = 1_000_000 / hz;
+        (0..=12 * hz).map(move |i| {
+            let t = (i * period_us) as f32 / 1_000_000.0;
+            // Hand approaches from 120cm to 25cm over 4s, waits, then leaves
+            let distance = if t < 4.0 {
+                120.0 - t * 23.75
+            } else if t < 8.0 {
+                25.0
+            } else {
+                120.0
+            };
+            MBotSensors {
+                timestamp_us: i * period_us,
+                ultrasonic_cm: distance,
+                encoder_left: (t * 100.0) as i32,
+                encoder_right: (t * 120.0) as i32,
+                // 2cm/s wheel difference over a 10cm base
+                gyro_z: 0.2f32.to_degrees(),
+                accel: [0.0, 0.0, 9.8],
+                sound_level: 0.1,
+                ..Default::default()
+            }
+        })
+    }
+
+    /// (tension, energy, heading) at each whole second
+    fn sample_each_second(hz: u64) -> Vec<(f32, f32, f32)> {
+        let mut brain = MBotBrain::new();
+        let mut samples = Vec::new();
+        for (i, sensors) in approach_session(hz).enumerate() {
+            let (state, _) = brain.tick(&sensors);
+            if i > 0 && (i as u64).is_multiple_of(hz) {
+                samples.push((state.tension, state.energy, brain.heading()));
+            }
+        }
+        samples
+    }
+
+    #[test]
+    fn test_behavior_is_loop_rate_independent() {
+        let at_20 = sample_each_second(20);
+        for hz in [10, 50] {
+            let other = sample_each_second(hz);
+            assert_eq!(other.len(), at_20.len());
+            for (second, (a, b)) in at_20.iter().zip(other.iter()).enumerate() {
+                assert!((a.0 - b.0).abs() < 0.03, "{}Hz tension at {}s: {} vs {}", hz, second, b.0, a.0);
+                assert!((a.1 - b.1).abs() < 0.002, "{}Hz energy at {}s: {} vs {}", hz, second, b.1, a.1);
+                assert!((a.2 - b.2).abs() < 0.01, "{}Hz heading at {}s: {} vs {}", hz, second, b.2, a.2);
+            }
+        }
+    }
+
+    #[test]
+    fn test_dropped_frames_are_clamped() {
+        let mut brain = MBotBrain::new();
+        brain.tick(&MBotSensors { timestamp_us: 1_000_000, ultrasonic_cm: 150.0, ..Default::default() });
+        // The link stalls for 30 seconds
+        brain.tick(&MBotSensors { timestamp_us: 31_000_000, ultrasonic_cm: 150.0, ..Default::default() });
+        assert_eq!(brain.elapsed_us(), NOMINAL_TICK_US + MAX_TICK_US);
+    }
+
+    #[test]
+    fn test_out_of_order_and_duplicate_timestamps() {
+        let mut brain = MBotBrain::new();
+        let frame = |ts: u64| MBotSensors { timestamp_us: ts, ultrasonic_cm: 150.0, ..Default::default() };
+
+        brain.tick(&frame(2_000_000));
+        brain.tick(&frame(2_050_000));
+        // A stale frame arrives late, then a duplicate: neither takes time
+        brain.tick(&frame(1_900_000));
+        brain.tick(&frame(2_050_000));
+        assert_eq!(brain.elapsed_us(), 2 * NOMINAL_TICK_US);
+        assert_eq!(brain.mood().lifetime_us, 2 * NOMINAL_TICK_US);
+
+        // The clock was not rewound by the stale frame
+        brain.tick(&frame(2_100_000));
+        assert_eq!(brain.elapsed_us(), 3 * NOMINAL_TICK_US);
+    }
+
+    #[test]
+    fn test_u32_timestamp_wraparound() {
+        let mut brain = MBotBrain::new();
+        let frame = |ts: u64| MBotSensors { timestamp_us: ts, ultrasonic_cm: 150.0, ..Default::default() };
+
+        brain.tick(&frame(u32::MAX as u64 - 19_999));
+        brain.tick(&frame(30_000));
+        assert_eq!(brain.elapsed_us(), NOMINAL_TICK_US + 50_000);
+    }
+
+    /// A wandering session with objects coming and going
+    fn busy_room(tick: u64) -> MBotSensors {
+        MBotSensors {
+            timestamp_us: tick * 50_000,
+            ultrasonic_cm: 35.0 + ((tick * 37) % 90) as f32,
+            encoder_left: tick as i32 * 4,
+            encoder_right: tick as i32 * 5,
+            accel: [0.0, 0.0, 9.8],
+            sound_level: 0.2,
+            ..Default::default()
+        }
+    }
+
+    fn motor_trace(seed: u64) -> Vec<(i8, i8)> {
+        let mut brain = MBotBrain::seeded(PersonalityConfig::CURIOUS_GEORGE, seed);
+        (0..400)
+            .map(|tick| {
+                let (_, cmd) = brain.tick(&busy_room(tick));
+                (cmd.left, cmd.right)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_same_seed_reproduces_run() {
+        assert_eq!(motor_trace(1234), motor_trace(1234));
+    }
+
+    #[test]
+    fn test_seed_changes_choices() {
+        assert_ne!(motor_trace(1), motor_trace(2));
+    }
+
+    #[test]
+    fn test_profile_brain_takes_seed() {
+        let profile = RobotProfile {
+            personality: PersonalityConfig::CURIOUS_GEORGE,
+            ..Default::default()
+        };
+        let mut brain = MBotBrain::with_profile_seeded(&profile, 1234);
+        let trace: Vec<_> = (0..400)
+            .map(|tick| {
+                let (_, cmd) = brain.tick(&busy_room(tick));
+                (cmd.left, cmd.right)
+            })
+            .collect();
+        assert_eq!(trace, motor_trace(1234));
+    }
+
     #[test]
     fn test_normalize_angle() {
         use core::f32::consts::PI;
@@ -540,4 +1469,136 @@ mod tests {
         assert!(fabsf(normalize_angle(3.0 * PI) - PI) < 0.001);
         assert!(fabsf(normalize_angle(-3.0 * PI) - (-PI)) < 0.001);
     }
+
+    #[test]
+    fn test_circle_array_matches_iterator() {
+        let ring: [(f32, f32); 25] = circle_points_array((10.0, -5.0), 8.0);
+        let points: Vec<_> = circle_points((10.0, -5.0), 8.0, 24).collect();
+        assert_eq!(ring.as_slice(), points.as_slice());
+
+        // Closed: ends where it started
+        assert!(fabsf(ring[0].0 - ring[24].0) < 0.001 && fabsf(ring[0].1 - ring[24].1) < 0.001);
+        assert_eq!(ring[0], (18.0, -5.0));
+        assert_eq!(circle_points_array::<0>((0.0, 0.0), 1.0), []);
+    }
+
+    /// A sensor stream recorded from the simulator with the brain driving
+    /// (columns as in the file header; quad RGB isn't recorded)
+    fn recorded_stream(csv: &str) -> Vec<MBotSensors> {
+        csv.lines()
+            .skip(1)
+            .map(|line| {
+                let f: Vec<&str> = line.split(',').collect();
+                let num = |i: usize| f[i].parse::<f32>().unwrap();
+                MBotSensors {
+                    timestamp_us: f[0].parse().unwrap(),
+                    ultrasonic_cm: num(1),
+                    encoder_left: f[2].parse().unwrap(),
+                    encoder_right: f[3].parse().unwrap(),
+                    gyro_z: num(4),
+                    accel: [num(5), num(6), num(7)],
+                    sound_level: num(8),
+                    light_level: num(9),
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+
+    fn recorded_streams() -> [Vec<MBotSensors>; 2] {
+        [
+            recorded_stream(include_str!("../tests/data/sim_wander.csv")),
+            recorded_stream(include_str!("../tests/data/noisy_bumps.csv")),
+        ]
+    }
+
+    /// Tick lengths as the brain would measure them
+    fn tick_lengths(stream: &[MBotSensors]) -> impl Iterator<Item = (&MBotSensors, u64)> {
+        stream.iter().scan(None, |last: &mut Option<u64>, s| {
+            let dt_us = last.map_or(NOMINAL_TICK_US, |last| (s.timestamp_us - last).min(MAX_TICK_US));
+            *last = Some(s.timestamp_us);
+            Some((s, dt_us))
+        })
+    }
+
+    #[test]
+    fn test_fixed_point_homeostasis_tracks_float() {
+        let p = PersonalityConfig::default();
+        for stream in recorded_streams() {
+            let mut float = Affect::<f32>::from_f32(0.0, 1.0, 1.0);
+            let mut fixed = Affect::<Q16>::from_f32(0.0, 1.0, 1.0);
+            let mut last_distance = 100.0;
+            let mut worst = 0.0f32;
+
+            for (s, dt_us) in tick_lengths(&stream) {
+                let curiosity = float.feel(&p, s, last_distance, 10.0, dt_us);
+                let fixed_curiosity = fixed.feel(&p, s, last_distance, 10.0, dt_us).to_f32();
+                last_distance = s.ultrasonic_cm;
+
+                let (t, c, e) = float.to_f32();
+                let (ft, fc, fe) = fixed.to_f32();
+                for diff in [t - ft, c - fc, e - fe, curiosity - fixed_curiosity] {
+                    worst = worst.max(fabsf(diff));
+                }
+            }
+            assert!(worst < 0.002, "homeostasis diverged by {}", worst);
+        }
+    }
+
+    #[test]
+    fn test_fixed_point_odometry_tracks_float() {
+        let c = Calibration::DEFAULT;
+        let mut slips = 0;
+        for stream in recorded_streams() {
+            let mut float = PoseEstimator::default();
+            let mut fixed = PoseEstimator::default();
+            let mut last = (0, 0);
+
+            for (s, dt_us) in tick_lengths(&stream) {
+                let left = (s.encoder_left - last.0) as f32 / c.ticks_per_cm;
+                let right = (s.encoder_right - last.1) as f32 / c.ticks_per_cm;
+                last = (s.encoder_left, s.encoder_right);
+
+                let slipped = float.update(left, right, c.wheel_base_cm, Some(s.gyro_z), dt_us);
+                let num = Q16::from_f32;
+                let fixed_slipped = fixed.update_with(num(left), num(right), num(c.wheel_base_cm), Some(num(s.gyro_z)), dt_us);
+                slips += slipped as u32;
+
+                let (a, b) = (float.pose(), fixed.pose());
+                assert_eq!(slipped, fixed_slipped, "slip gate disagreed at {}µs", s.timestamp_us);
+                assert!(fabsf(a.x - b.x) < 0.5 && fabsf(a.y - b.y) < 0.5, "{:?} vs {:?}", a, b);
+                assert!(fabsf(a.heading - b.heading) < 0.01, "{:?} vs {:?}", a, b);
+                assert!(fabsf(a.heading_variance - b.heading_variance) < 1e-5 + a.heading_variance * 0.02);
+                assert!(fabsf(a.position_variance - b.position_variance) < 0.01 + a.position_variance * 0.02);
+            }
+        }
+        // The slip gate was exercised, not just agreed on
+        assert!(slips > 0);
+    }
+
+    #[test]
+    fn test_fixed_point_steering_tracks_float() {
+        let targets = [(0.0, 0.0), (30.0, 0.0), (-20.0, 45.0), (5.0, -60.0)];
+        for stream in recorded_streams() {
+            let mut pose = PoseEstimator::default();
+            let mut last = (0, 0);
+            for (s, dt_us) in tick_lengths(&stream) {
+                let c = Calibration::DEFAULT;
+                let left = (s.encoder_left - last.0) as f32 / c.ticks_per_cm;
+                let right = (s.encoder_right - last.1) as f32 / c.ticks_per_cm;
+                last = (s.encoder_left, s.encoder_right);
+                pose.update(left, right, c.wheel_base_cm, Some(s.gyro_z), dt_us);
+
+                let p = pose.pose();
+                for target in targets {
+                    let (l, r) = drive_to_point_with::<f32>((p.x, p.y), p.heading, target, 50.0);
+                    let num = Q16::from_f32;
+                    let (fl, fr) =
+                        drive_to_point_with::<Q16>((num(p.x), num(p.y)), num(p.heading), (num(target.0), num(target.1)), num(50.0));
+                    assert!((l as i16 - fl as i16).abs() <= 1 && (r as i16 - fr as i16).abs() <= 1,
+                        "steering to {:?} from {:?}: ({}, {}) vs ({}, {})", target, p, l, r, fl, fr);
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,82 @@
+//! Mood memory - what the robot carries over from its past
+//!
+//! Homeostasis reacts tick by tick; mood memory accumulates slowly across a
+//! whole session (and across sessions when persisted), so a robot that was
+//! startled a lot wakes up a little more tense.
+
+use crate::{ReflexMode, NOMINAL_TICK_US};
+
+/// Smoothing factor per nominal tick for the long-term mood averages
+/// (~1000 ticks, under a minute at 20Hz)
+const LONG_ALPHA: f32 = 0.001;
+
+/// Slowly accumulated mood that survives restarts
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MoodMemory {
+    /// Energy level when the memory was taken (0.1-1.0)
+    pub energy: f32,
+    /// Long-term average tension (0.0-1.0)
+    pub long_tension: f32,
+    /// Long-term average coherence (0.0-1.0)
+    pub long_coherence: f32,
+    /// Number of times the robot was startled into Protect
+    pub startle_count: u32,
+    /// Total time lived across all sessions (µs of brain time)
+    pub lifetime_us: u64,
+}
+
+impl MoodMemory {
+    /// A fresh robot: full of energy, relaxed, never startled
+    pub const FRESH: Self = Self {
+        energy: 1.0,
+        long_tension: 0.0,
+        long_coherence: 1.0,
+        startle_count: 0,
+        lifetime_us: 0,
+    };
+
+    /// Fold one tick of experience, `dt_us` long, into the memory
+    pub(crate) fn record(
+        &mut self,
+        tension: f32,
+        coherence: f32,
+        energy: f32,
+        startled: bool,
+        dt_us: u64,
+    ) {
+        // Linear approximation of the EMA is plenty for such a slow average
+        let dt_ratio = dt_us as f32 / NOMINAL_TICK_US as f32;
+        let alpha = (LONG_ALPHA * dt_ratio).min(1.0);
+        self.energy = energy;
+        self.long_tension += alpha * (tension - self.long_tension);
+        self.long_coherence += alpha * (coherence - self.long_coherence);
+        if startled {
+            self.startle_count = self.startle_count.saturating_add(1);
+        }
+        self.lifetime_us = self.lifetime_us.saturating_add(dt_us);
+    }
+
+    /// Force every value into its valid range
+    pub fn clamped(&self) -> Self {
+        let unit = |v: f32, fallback: f32| if v.is_finite() { v.clamp(0.0, 1.0) } else { fallback };
+        Self {
+            energy: unit(self.energy, Self::FRESH.energy).max(0.1),
+            long_tension: unit(self.long_tension, Self::FRESH.long_tension),
+            long_coherence: unit(self.long_coherence, Self::FRESH.long_coherence),
+            startle_count: self.startle_count,
+            lifetime_us: self.lifetime_us,
+        }
+    }
+}
+
+impl Default for MoodMemory {
+    fn default() -> Self {
+        Self::FRESH
+    }
+}
+
+/// A startle is the moment the robot first flips into Protect
+pub(crate) fn is_startle(previous: ReflexMode, current: ReflexMode) -> bool {
+    current == ReflexMode::Protect && previous != ReflexMode::Protect
+}
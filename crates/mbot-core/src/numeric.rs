@@ -0,0 +1,477 @@
+//! Numeric backends - the same nervous system in float or fixed point
+//!
+//! Homeostasis, odometry and `drive_to_point` are written once against the
+//! `Real` trait. `f32` is the reference backend. `Q16` is Q16.16 fixed point:
+//! integer-only arithmetic, for microcontrollers without an FPU and for runs
+//! that must be bit-identical on every platform. The `fixed_point` feature
+//! makes `Scalar` (the backend the brain uses) `Q16`; both are always built
+//! so they can be compared.
+//!
+//! Brain state stays `f32` between ticks and is converted at the edges of
+//! each kernel. Conversions are exact or correctly rounded, so fixed-point
+//! runs remain deterministic.
+
+use core::ops::{Add, Div, Mul, Neg, Sub};
+
+use crate::{atan2f, cosf, fabsf, powf, sinf, sqrtf};
+
+/// The backend the brain computes with
+#[cfg(feature = "fixed_point")]
+pub type Scalar = Q16;
+
+/// The backend the brain computes with
+#[cfg(not(feature = "fixed_point"))]
+pub type Scalar = f32;
+
+/// Arithmetic the nervous system needs from a number type
+pub trait Real:
+    Copy
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+{
+    const ZERO: Self;
+    const ONE: Self;
+    const PI: Self;
+
+    /// Factor applied to angular standard deviations inside the pose filter
+    /// (radians become milliradians in Q16), so that per-tick variances of
+    /// around 1e-7 rad² don't vanish below the type's resolution
+    const SIGMA_SCALE: f32;
+
+    fn from_f32(x: f32) -> Self;
+    fn to_f32(self) -> f32;
+    fn from_int(n: i32) -> Self;
+    /// `num / den`, e.g. a tick length in nominal ticks
+    fn from_ratio(num: u64, den: u64) -> Self;
+    /// Truncated toward zero and saturated, like `f32 as i8`
+    fn to_i8(self) -> i8;
+
+    fn abs(self) -> Self;
+    fn sqrt(self) -> Self;
+    fn sin(self) -> Self;
+    fn cos(self) -> Self;
+    /// Angle of the point (x, y) from the +x axis, like `f32::atan2`
+    fn atan2(self, x: Self) -> Self;
+    fn powf(self, exponent: Self) -> Self;
+
+    fn min(self, other: Self) -> Self {
+        if other < self { other } else { self }
+    }
+
+    fn max(self, other: Self) -> Self {
+        if other > self { other } else { self }
+    }
+
+    fn clamp(self, lo: Self, hi: Self) -> Self {
+        self.max(lo).min(hi)
+    }
+
+    fn to_radians(self) -> Self {
+        self * Self::from_f32(core::f32::consts::PI / 180.0)
+    }
+}
+
+impl Real for f32 {
+    const ZERO: Self = 0.0;
+    const ONE: Self = 1.0;
+    const PI: Self = core::f32::consts::PI;
+    const SIGMA_SCALE: f32 = 1.0;
+
+    fn from_f32(x: f32) -> Self {
+        x
+    }
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn from_int(n: i32) -> Self {
+        n as f32
+    }
+
+    fn from_ratio(num: u64, den: u64) -> Self {
+        num as f32 / den as f32
+    }
+
+    fn to_i8(self) -> i8 {
+        self as i8
+    }
+
+    fn abs(self) -> Self {
+        fabsf(self)
+    }
+
+    fn sqrt(self) -> Self {
+        sqrtf(self)
+    }
+
+    fn sin(self) -> Self {
+        sinf(self)
+    }
+
+    fn cos(self) -> Self {
+        cosf(self)
+    }
+
+    fn atan2(self, x: Self) -> Self {
+        atan2f(self, x)
+    }
+
+    fn powf(self, exponent: Self) -> Self {
+        powf(self, exponent)
+    }
+
+    fn min(self, other: Self) -> Self {
+        f32::min(self, other)
+    }
+
+    fn max(self, other: Self) -> Self {
+        f32::max(self, other)
+    }
+
+    fn clamp(self, lo: Self, hi: Self) -> Self {
+        f32::clamp(self, lo, hi)
+    }
+
+    fn to_radians(self) -> Self {
+        f32::to_radians(self)
+    }
+}
+
+/// Q16.16 fixed point: 16 integer bits (with sign) and 16 fraction bits,
+/// covering ±32768 in steps of 1/65536. All arithmetic saturates.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Q16(i32);
+
+const FRAC_BITS: u32 = 16;
+const ONE_BITS: i64 = 1 << FRAC_BITS;
+
+/// π/2 with 32 fraction bits, for accurate range reduction
+const FRAC_PI_2_Q32: i64 = 6_746_518_852;
+
+impl Q16 {
+    pub const MAX: Self = Self(i32::MAX);
+    pub const MIN: Self = Self(i32::MIN);
+    /// Smallest positive value
+    pub const EPSILON: Self = Self(1);
+
+    const FRAC_PI_2: Self = Self(102_944);
+
+    pub const fn from_bits(bits: i32) -> Self {
+        Self(bits)
+    }
+
+    pub const fn to_bits(self) -> i32 {
+        self.0
+    }
+
+    /// Saturate a wide intermediate back into range
+    const fn saturate(wide: i64) -> Self {
+        if wide > i32::MAX as i64 {
+            Self::MAX
+        } else if wide < i32::MIN as i64 {
+            Self::MIN
+        } else {
+            Self(wide as i32)
+        }
+    }
+
+    /// Evaluate a polynomial in `self` by Horner's rule, highest power first
+    fn poly(self, coefficients: &[Q16]) -> Q16 {
+        coefficients.iter().fold(Q16(0), |acc, &c| acc * self + c)
+    }
+
+    /// Split an angle into quarter turns and a remainder in [-π/4, π/4]
+    fn quarter_turns(self) -> (i64, Q16) {
+        let x = (self.0 as i64) << FRAC_BITS;
+        let half = FRAC_PI_2_Q32 / 2;
+        let quarters = (x + if x >= 0 { half } else { -half }) / FRAC_PI_2_Q32;
+        let rest = x - quarters * FRAC_PI_2_Q32;
+        (quarters, Q16(((rest + (1 << (FRAC_BITS - 1))) >> FRAC_BITS) as i32))
+    }
+
+    /// sin(quarters·π/2 + r) for |r| <= π/4, by Taylor series (error below
+    /// 1e-6 before rounding)
+    fn sin_quarters(quarters: i64, r: Q16) -> Q16 {
+        const SIN: [Q16; 4] = [q16(-1.0 / 5040.0), q16(1.0 / 120.0), q16(-1.0 / 6.0), q16(1.0)];
+        const COS: [Q16; 5] = [
+            q16(1.0 / 40_320.0),
+            q16(-1.0 / 720.0),
+            q16(1.0 / 24.0),
+            q16(-0.5),
+            q16(1.0),
+        ];
+        let r2 = r * r;
+        match quarters.rem_euclid(4) {
+            0 => r * r2.poly(&SIN),
+            1 => r2.poly(&COS),
+            2 => -(r * r2.poly(&SIN)),
+            _ => -r2.poly(&COS),
+        }
+    }
+
+    /// Arctangent for |self| <= 1 (Abramowitz & Stegun 4.4.49, error 1e-5)
+    fn atan_unit(self) -> Q16 {
+        const C: [Q16; 5] = [
+            q16(0.020_835_1),
+            q16(-0.085_133),
+            q16(0.180_141),
+            q16(-0.330_299_5),
+            q16(0.999_866),
+        ];
+        // Odd polynomial: x * p(x²)
+        self * (self * self).poly(&C)
+    }
+
+    /// log₂ of a positive value
+    fn log2(self) -> Q16 {
+        if self.0 <= 0 {
+            return Self::MIN;
+        }
+        // Integer part from the position of the top bit
+        let top = 31 - self.0.leading_zeros() as i32;
+        let int = top - FRAC_BITS as i32;
+        // Mantissa in [1, 2), with 30 fraction bits so squaring stays exact
+        // enough for all 16 result bits
+        const M_BITS: i32 = 30;
+        let shift = M_BITS - top;
+        let mut m = if shift >= 0 { (self.0 as i64) << shift } else { (self.0 as i64) >> -shift };
+        // Fraction bits by repeated squaring
+        let mut frac = 0i64;
+        for bit in (0..FRAC_BITS).rev() {
+            m = (m * m) >> M_BITS;
+            if m >= 2 << M_BITS {
+                m >>= 1;
+                frac |= 1 << bit;
+            }
+        }
+        Self::saturate(((int as i64) << FRAC_BITS) + frac)
+    }
+
+    /// 2 to the power of `self`
+    fn exp2(self) -> Q16 {
+        const C: [Q16; 6] = [
+            q16(0.001_333_36),
+            q16(0.009_618_13),
+            q16(0.055_504_11),
+            q16(0.240_226_5),
+            q16(core::f32::consts::LN_2),
+            q16(1.0),
+        ];
+        let int = self.0 >> FRAC_BITS;
+        let frac = Q16(self.0 & (ONE_BITS as i32 - 1));
+        let mantissa = frac.poly(&C).0 as i64;
+        match int {
+            ..=-32 => Q16(0),
+            -31..=-1 => Q16((mantissa >> -int) as i32),
+            0..=15 => Self::saturate(mantissa << int),
+            _ => Self::MAX,
+        }
+    }
+}
+
+/// `x` in Q16.16, rounded to nearest; usable in constants
+pub const fn q16(x: f32) -> Q16 {
+    let scaled = x * ONE_BITS as f32;
+    // `as` saturates out-of-range values and maps NaN to zero
+    Q16((if scaled >= 0.0 { scaled + 0.5 } else { scaled - 0.5 }) as i32)
+}
+
+impl Add for Q16 {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0.saturating_add(rhs.0))
+    }
+}
+
+impl Sub for Q16 {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Self(self.0.saturating_sub(rhs.0))
+    }
+}
+
+impl Mul for Q16 {
+    type Output = Self;
+    /// Rounded to nearest
+    fn mul(self, rhs: Self) -> Self {
+        let wide = self.0 as i64 * rhs.0 as i64;
+        Self::saturate((wide + (1 << (FRAC_BITS - 1))) >> FRAC_BITS)
+    }
+}
+
+impl Div for Q16 {
+    type Output = Self;
+    /// Truncated toward zero; division by zero saturates toward the
+    /// dividend's sign
+    fn div(self, rhs: Self) -> Self {
+        if rhs.0 == 0 {
+            return match self.0 {
+                0 => Self(0),
+                n if n > 0 => Self::MAX,
+                _ => Self::MIN,
+            };
+        }
+        Self::saturate(((self.0 as i64) << FRAC_BITS) / rhs.0 as i64)
+    }
+}
+
+impl Neg for Q16 {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self(self.0.saturating_neg())
+    }
+}
+
+impl Real for Q16 {
+    const ZERO: Self = Self(0);
+    const ONE: Self = Self(ONE_BITS as i32);
+    const PI: Self = Self(205_887);
+    const SIGMA_SCALE: f32 = 1000.0;
+
+    fn from_f32(x: f32) -> Self {
+        q16(x)
+    }
+
+    fn to_f32(self) -> f32 {
+        self.0 as f32 / ONE_BITS as f32
+    }
+
+    fn from_int(n: i32) -> Self {
+        Self::saturate((n as i64) << FRAC_BITS)
+    }
+
+    fn from_ratio(num: u64, den: u64) -> Self {
+        if den == 0 {
+            return if num == 0 { Self(0) } else { Self::MAX };
+        }
+        let wide = ((num as u128) << FRAC_BITS) / den as u128;
+        Self::saturate(wide.min(i64::MAX as u128) as i64)
+    }
+
+    fn to_i8(self) -> i8 {
+        (self.0 / ONE_BITS as i32).clamp(i8::MIN as i32, i8::MAX as i32) as i8
+    }
+
+    fn abs(self) -> Self {
+        Self(self.0.saturating_abs())
+    }
+
+    fn sqrt(self) -> Self {
+        if self.0 <= 0 {
+            return Self(0);
+        }
+        Self((((self.0 as u64) << FRAC_BITS).isqrt()) as i32)
+    }
+
+    fn sin(self) -> Self {
+        let (quarters, r) = self.quarter_turns();
+        Self::sin_quarters(quarters, r)
+    }
+
+    fn cos(self) -> Self {
+        let (quarters, r) = self.quarter_turns();
+        Self::sin_quarters(quarters + 1, r)
+    }
+
+    fn atan2(self, x: Self) -> Self {
+        let y = self;
+        if x.0 == 0 && y.0 == 0 {
+            return Self(0);
+        }
+        if y.abs() <= x.abs() {
+            let angle = (y / x).atan_unit();
+            match (x.0 < 0, y.0 < 0) {
+                (false, _) => angle,
+                (true, false) => angle + Self::PI,
+                (true, true) => angle - Self::PI,
+            }
+        } else {
+            let angle = (x / y).atan_unit();
+            if y.0 > 0 {
+                Self::FRAC_PI_2 - angle
+            } else {
+                -Self::FRAC_PI_2 - angle
+            }
+        }
+    }
+
+    fn powf(self, exponent: Self) -> Self {
+        if exponent.0 == 0 {
+            return Self::ONE;
+        }
+        if self.0 <= 0 {
+            return Self(0);
+        }
+        (exponent * self.log2()).exp2()
+    }
+
+    /// Multiplies before dividing; converting the 1° step first would lose
+    /// a third of its bits
+    fn to_radians(self) -> Self {
+        self * Self::PI / Self::from_int(180)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Worst absolute error of `fixed` against `float` over [lo, hi]
+    fn worst(lo: f32, hi: f32, fixed: impl Fn(Q16) -> Q16, float: impl Fn(f32) -> f32) -> f32 {
+        (0..=2000)
+            .map(|i| {
+                let x = q16(lo + (hi - lo) * i as f32 / 2000.0);
+                fabsf(fixed(x).to_f32() - float(x.to_f32()))
+            })
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn test_arithmetic_rounds_and_saturates() {
+        assert_eq!(q16(1.5) + q16(2.25), q16(3.75));
+        assert_eq!(q16(1.5) * q16(-2.0), q16(-3.0));
+        assert_eq!(q16(1.0) / q16(4.0), q16(0.25));
+        assert_eq!(q16(30000.0) + q16(30000.0), Q16::MAX);
+        assert_eq!(q16(-200.0) * q16(200.0), Q16::MIN);
+        assert_eq!(q16(1.0) / Q16::ZERO, Q16::MAX);
+        assert_eq!(q16(f32::NAN), Q16::ZERO);
+        assert_eq!(Q16::from_int(7).to_f32(), 7.0);
+        assert_eq!(Q16::from_ratio(75_000, 50_000), q16(1.5));
+    }
+
+    #[test]
+    fn test_to_i8_truncates_like_float() {
+        for x in [-300.0, -99.9, -0.7, 0.0, 0.7, 42.99, 127.5, 500.0] {
+            assert_eq!(q16(x).to_i8(), x as i8, "{}", x);
+        }
+    }
+
+    #[test]
+    fn test_functions_track_float() {
+        assert!(worst(-50.0, 50.0, Q16::sin, sinf) < 1e-4);
+        assert!(worst(-50.0, 50.0, Q16::cos, cosf) < 1e-4);
+        assert!(worst(0.0, 30000.0, Q16::sqrt, sqrtf) < 1e-2);
+        assert!(worst(0.0, 4.0, Q16::sqrt, sqrtf) < 1e-4);
+        // log₂ is only good to 16 bits, and exp₂ magnifies that
+        assert!(worst(0.001, 1.0, |b| b.powf(q16(2.5)), |b| powf(b, 2.5)) < 2e-4);
+        assert!(worst(0.0, 10.0, |e| q16(0.7).powf(e), |e| powf(0.7, e)) < 2e-4);
+    }
+
+    #[test]
+    fn test_atan2_all_quadrants() {
+        for i in 0..360 {
+            let angle = (i as f32 - 179.5).to_radians();
+            for radius in [0.01, 1.0, 300.0] {
+                let (y, x) = (radius * sinf(angle), radius * cosf(angle));
+                let fixed = q16(y).atan2(q16(x)).to_f32();
+                assert!(fabsf(fixed - atan2f(y, x)) < 2e-3, "{} {} {}", angle, radius, fixed);
+            }
+        }
+        assert_eq!(Q16::ZERO.atan2(Q16::ZERO), Q16::ZERO);
+    }
+}
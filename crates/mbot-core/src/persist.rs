@@ -0,0 +1,392 @@
+//! Persistent robot profile - personality, mood memory and calibration
+//!
+//! The binary format is a fixed little-endian layout that needs no heap, so
+//! the CyberPi can store it in flash as-is:
+//!
+//! ```text
+//! offset  size  field
+//! 0       4     magic "MBPF"
+//! 4       1     major version (incompatible layout changes)
+//! 5       1     minor version (fields appended to the payload)
+//! 6       2     payload length in bytes (u16)
+//! 8       N     payload
+//! 8+N     4     CRC-32 (IEEE) of bytes 0..8+N
+//! ```
+//!
+//! Version 1.0 stores the personality, the mood memory and the calibration.
+//! Payload fields are append-only within a major version: every 1.x file
+//! starts with the 1.0 payload, and readers ignore trailing fields they
+//! don't know (files from newer minor versions).
+
+use core::fmt;
+
+use crate::calibration::{Calibration, CalibrationError};
+use crate::mood::MoodMemory;
+use crate::personality::{PersonalityConfig, PersonalityError};
+
+/// File magic
+pub const PROFILE_MAGIC: [u8; 4] = *b"MBPF";
+/// Major format version written by this build
+pub const PROFILE_VERSION_MAJOR: u8 = 1;
+/// Minor format version written by this build
+pub const PROFILE_VERSION_MINOR: u8 = 0;
+
+const HEADER_LEN: usize = 8;
+const CHECKSUM_LEN: usize = 4;
+/// 14 personality traits as f32
+const PERSONALITY_BLOCK_LEN: usize = 14 * 4;
+/// energy, long_tension, long_coherence, startle_count, lifetime_us
+const MOOD_BLOCK_LEN: usize = 4 * 4 + 8;
+/// ticks_per_cm, wheel_base_cm
+const CALIBRATION_BLOCK_LEN: usize = 2 * 4;
+const PAYLOAD_LEN: usize = PERSONALITY_BLOCK_LEN + MOOD_BLOCK_LEN + CALIBRATION_BLOCK_LEN;
+
+/// Size of an encoded profile written by this build
+pub const PROFILE_LEN: usize = HEADER_LEN + PAYLOAD_LEN + CHECKSUM_LEN;
+/// Room a reader should leave for a stored profile: this build's
+/// `PROFILE_LEN` plus fields that later minor versions may append
+pub const PROFILE_MAX_LEN: usize = 256;
+
+/// Everything that makes a robot "itself" between runs
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RobotProfile {
+    pub personality: PersonalityConfig,
+    pub mood: MoodMemory,
+    pub calibration: Calibration,
+}
+
+/// Why a stored profile could not be loaded
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PersistError {
+    /// Fewer bytes than the header or payload length promise
+    Truncated,
+    /// Not a profile file
+    BadMagic,
+    /// Written by an incompatible major version
+    UnsupportedVersion { major: u8, minor: u8 },
+    /// Payload is shorter than the version 1.0 fields
+    Malformed,
+    /// Contents were corrupted after writing
+    ChecksumMismatch { expected: u32, actual: u32 },
+    /// Decoded personality is outside its safe bounds
+    InvalidPersonality(PersonalityError),
+    /// Decoded calibration is implausible
+    InvalidCalibration(CalibrationError),
+}
+
+impl fmt::Display for PersistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PersistError::Truncated => write!(f, "profile is truncated"),
+            PersistError::BadMagic => write!(f, "not an mBot profile"),
+            PersistError::UnsupportedVersion { major, minor } => {
+                write!(f, "unsupported profile version {}.{}", major, minor)
+            }
+            PersistError::Malformed => write!(f, "profile payload is malformed"),
+            PersistError::ChecksumMismatch { expected, actual } => {
+                write!(f, "checksum mismatch: stored {:08x}, computed {:08x}", expected, actual)
+            }
+            PersistError::InvalidPersonality(e) => write!(f, "invalid personality: {}", e),
+            PersistError::InvalidCalibration(e) => write!(f, "invalid calibration: {}", e),
+        }
+    }
+}
+
+impl core::error::Error for PersistError {}
+
+impl RobotProfile {
+    /// Encode into the fixed binary layout
+    pub fn encode(&self) -> [u8; PROFILE_LEN] {
+        let mut out = [0u8; PROFILE_LEN];
+        out[0..4].copy_from_slice(&PROFILE_MAGIC);
+        out[4] = PROFILE_VERSION_MAJOR;
+        out[5] = PROFILE_VERSION_MINOR;
+        out[6..8].copy_from_slice(&(PAYLOAD_LEN as u16).to_le_bytes());
+
+        let p = &self.personality;
+        let m = &self.mood;
+        let mut w = Writer { buf: &mut out, pos: HEADER_LEN };
+        for value in personality_fields(p) {
+            w.put(&value.to_le_bytes());
+        }
+        w.put(&m.energy.to_le_bytes());
+        w.put(&m.long_tension.to_le_bytes());
+        w.put(&m.long_coherence.to_le_bytes());
+        w.put(&m.startle_count.to_le_bytes());
+        w.put(&m.lifetime_us.to_le_bytes());
+        w.put(&self.calibration.ticks_per_cm.to_le_bytes());
+        w.put(&self.calibration.wheel_base_cm.to_le_bytes());
+
+        let crc = crc32(&out[..HEADER_LEN + PAYLOAD_LEN]);
+        out[HEADER_LEN + PAYLOAD_LEN..].copy_from_slice(&crc.to_le_bytes());
+        out
+    }
+
+    /// Decode a profile, rejecting corrupted or incompatible data
+    pub fn decode(bytes: &[u8]) -> Result<Self, PersistError> {
+        if bytes.len() < HEADER_LEN {
+            return Err(PersistError::Truncated);
+        }
+        if bytes[0..4] != PROFILE_MAGIC {
+            return Err(PersistError::BadMagic);
+        }
+        let (major, minor) = (bytes[4], bytes[5]);
+        if major != PROFILE_VERSION_MAJOR {
+            return Err(PersistError::UnsupportedVersion { major, minor });
+        }
+
+        let payload_len = u16::from_le_bytes([bytes[6], bytes[7]]) as usize;
+        let body_len = HEADER_LEN + payload_len;
+        if bytes.len() < body_len + CHECKSUM_LEN {
+            return Err(PersistError::Truncated);
+        }
+
+        let stored = u32::from_le_bytes([
+            bytes[body_len],
+            bytes[body_len + 1],
+            bytes[body_len + 2],
+            bytes[body_len + 3],
+        ]);
+        let computed = crc32(&bytes[..body_len]);
+        if stored != computed {
+            return Err(PersistError::ChecksumMismatch { expected: stored, actual: computed });
+        }
+
+        if payload_len < PAYLOAD_LEN {
+            return Err(PersistError::Malformed);
+        }
+
+        let mut r = Reader { buf: &bytes[HEADER_LEN..body_len], pos: 0 };
+        let personality = PersonalityConfig {
+            proximity_weight: r.f32(),
+            change_weight: r.f32(),
+            sound_weight: r.f32(),
+            movement_weight: r.f32(),
+            smoothing: r.f32(),
+            active_threshold: r.f32(),
+            spike_threshold: r.f32(),
+            protect_threshold: r.f32(),
+            fatigue_threshold: r.f32(),
+            energy_drain: r.f32(),
+            energy_recovery: r.f32(),
+            curiosity_low: r.f32(),
+            curiosity_high: r.f32(),
+            movement_expressiveness: r.f32(),
+        };
+        personality.validate().map_err(PersistError::InvalidPersonality)?;
+
+        let mood = MoodMemory {
+            energy: r.f32(),
+            long_tension: r.f32(),
+            long_coherence: r.f32(),
+            startle_count: r.u32(),
+            lifetime_us: r.u64(),
+        }
+        .clamped();
+
+        let calibration = Calibration {
+            ticks_per_cm: r.f32(),
+            wheel_base_cm: r.f32(),
+        };
+        calibration.validate().map_err(PersistError::InvalidCalibration)?;
+
+        Ok(Self { personality, mood, calibration })
+    }
+}
+
+fn personality_fields(p: &PersonalityConfig) -> [f32; 14] {
+    [
+        p.proximity_weight,
+        p.change_weight,
+        p.sound_weight,
+        p.movement_weight,
+        p.smoothing,
+        p.active_threshold,
+        p.spike_threshold,
+        p.protect_threshold,
+        p.fatigue_threshold,
+        p.energy_drain,
+        p.energy_recovery,
+        p.curiosity_low,
+        p.curiosity_high,
+        p.movement_expressiveness,
+    ]
+}
+
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl Writer<'_> {
+    fn put(&mut self, bytes: &[u8]) {
+        self.buf[self.pos..self.pos + bytes.len()].copy_from_slice(bytes);
+        self.pos += bytes.len();
+    }
+}
+
+/// Reads fixed-size fields; callers check the length up front
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let mut out = [0u8; N];
+        out.copy_from_slice(&self.buf[self.pos..self.pos + N]);
+        self.pos += N;
+        out
+    }
+
+    fn f32(&mut self) -> f32 {
+        f32::from_le_bytes(self.take())
+    }
+
+    fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.take())
+    }
+
+    fn u64(&mut self) -> u64 {
+        u64::from_le_bytes(self.take())
+    }
+}
+
+/// CRC-32 (IEEE 802.3), bitwise - small enough for the CyberPi
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> RobotProfile {
+        RobotProfile {
+            personality: PersonalityConfig::NERVOUS_NELLIE,
+            mood: MoodMemory {
+                energy: 0.42,
+                long_tension: 0.31,
+                long_coherence: 0.77,
+                startle_count: 12,
+                lifetime_us: 123_456_789,
+            },
+            calibration: Calibration {
+                ticks_per_cm: 18.3,
+                wheel_base_cm: 11.6,
+            },
+        }
+    }
+
+    /// Re-seal a hand-edited file with a valid checksum
+    fn reseal(bytes: &mut [u8]) {
+        let body = bytes.len() - CHECKSUM_LEN;
+        let crc = crc32(&bytes[..body]);
+        bytes[body..].copy_from_slice(&crc.to_le_bytes());
+    }
+
+    #[test]
+    fn test_crc32_known_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let profile = sample();
+        let bytes = profile.encode();
+        assert_eq!(bytes.len(), PROFILE_LEN);
+        assert_eq!(RobotProfile::decode(&bytes), Ok(profile));
+    }
+
+    #[test]
+    fn test_rejects_any_corrupted_byte() {
+        let bytes = sample().encode();
+        for i in HEADER_LEN..PROFILE_LEN {
+            let mut corrupted = bytes;
+            corrupted[i] ^= 0x5a;
+            assert!(
+                matches!(RobotProfile::decode(&corrupted), Err(PersistError::ChecksumMismatch { .. })),
+                "byte {} corruption not detected",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn test_rejects_truncated_and_foreign_files() {
+        let bytes = sample().encode();
+        assert_eq!(RobotProfile::decode(&bytes[..5]), Err(PersistError::Truncated));
+        assert_eq!(RobotProfile::decode(&bytes[..PROFILE_LEN - 1]), Err(PersistError::Truncated));
+        assert_eq!(RobotProfile::decode(b"GIF89a.................."), Err(PersistError::BadMagic));
+    }
+
+    #[test]
+    fn test_rejects_newer_major_version() {
+        let mut bytes = sample().encode();
+        bytes[4] = PROFILE_VERSION_MAJOR + 1;
+        reseal(&mut bytes);
+        assert_eq!(
+            RobotProfile::decode(&bytes),
+            Err(PersistError::UnsupportedVersion {
+                major: PROFILE_VERSION_MAJOR + 1,
+                minor: PROFILE_VERSION_MINOR,
+            })
+        );
+    }
+
+    #[test]
+    fn test_reads_newer_minor_version_with_extra_fields() {
+        // A future writer appended 8 bytes of fields we don't know about
+        let current = sample().encode();
+        let extra = 8;
+        let mut bytes = [0u8; PROFILE_LEN + 8];
+        bytes[..HEADER_LEN + PAYLOAD_LEN].copy_from_slice(&current[..HEADER_LEN + PAYLOAD_LEN]);
+        bytes[5] = PROFILE_VERSION_MINOR + 1;
+        bytes[6..8].copy_from_slice(&((PAYLOAD_LEN + extra) as u16).to_le_bytes());
+        bytes[HEADER_LEN + PAYLOAD_LEN..HEADER_LEN + PAYLOAD_LEN + extra].fill(0xab);
+        reseal(&mut bytes);
+
+        assert_eq!(RobotProfile::decode(&bytes), Ok(sample()));
+    }
+
+    #[test]
+    fn test_rejects_implausible_calibration() {
+        let mut bytes = sample().encode();
+        let at = HEADER_LEN + PAYLOAD_LEN - CALIBRATION_BLOCK_LEN;
+        bytes[at..at + 4].copy_from_slice(&(-3.0f32).to_le_bytes());
+        reseal(&mut bytes);
+        assert!(matches!(
+            RobotProfile::decode(&bytes),
+            Err(PersistError::InvalidCalibration(_))
+        ));
+    }
+
+    #[test]
+    fn test_rejects_short_payload_and_unsafe_personality() {
+        // Sealed properly, but without the last calibration field
+        let current = sample().encode();
+        let short = PAYLOAD_LEN - 4;
+        let mut bytes = [0u8; HEADER_LEN + PAYLOAD_LEN - 4 + CHECKSUM_LEN];
+        bytes[..HEADER_LEN + short].copy_from_slice(&current[..HEADER_LEN + short]);
+        bytes[6..8].copy_from_slice(&(short as u16).to_le_bytes());
+        reseal(&mut bytes);
+        assert_eq!(RobotProfile::decode(&bytes), Err(PersistError::Malformed));
+
+        let mut bytes = sample().encode();
+        bytes[HEADER_LEN..HEADER_LEN + 4].copy_from_slice(&9.0f32.to_le_bytes());
+        reseal(&mut bytes);
+        assert!(matches!(
+            RobotProfile::decode(&bytes),
+            Err(PersistError::InvalidPersonality(_))
+        ));
+    }
+}
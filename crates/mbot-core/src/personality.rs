@@ -0,0 +1,608 @@
+//! Personality configuration for the nervous system
+//!
+//! A personality is nothing more than a set of bounded numbers that shape
+//! how `MBotBrain` turns sensor input into tension, coherence, energy and
+//! curiosity. Different characters emerge from different parameters, not
+//! from scripted behaviors.
+
+use core::fmt;
+
+/// Tunable traits that shape the robot's homeostasis
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PersonalityConfig {
+    /// Weight of obstacle proximity in raw tension (0.0-1.0)
+    pub proximity_weight: f32,
+    /// Weight of sudden distance changes in raw tension (0.0-1.0)
+    pub change_weight: f32,
+    /// Weight of sound level in raw tension (0.0-1.0)
+    pub sound_weight: f32,
+    /// Weight of accelerometer magnitude in raw tension (0.0-1.0)
+    pub movement_weight: f32,
+
+    /// EMA smoothing factor per nominal 50ms tick - higher reacts faster (0.01-1.0)
+    pub smoothing: f32,
+
+    /// Tension above which the robot leaves Calm for Active
+    pub active_threshold: f32,
+    /// Tension above which the robot becomes Spike
+    pub spike_threshold: f32,
+    /// Tension above which the robot enters Protect
+    pub protect_threshold: f32,
+
+    /// Tension above which energy drains instead of recovering
+    pub fatigue_threshold: f32,
+    /// Energy lost per nominal 50ms tick while fatigued (0.0-0.01)
+    pub energy_drain: f32,
+    /// Energy regained per nominal 50ms tick while relaxed (0.0-0.01)
+    pub energy_recovery: f32,
+
+    /// Lower tension bound of the curiosity window
+    pub curiosity_low: f32,
+    /// Upper tension bound of the curiosity window
+    pub curiosity_high: f32,
+
+    /// How big and fast movements are - 0.5 is the stock robot (0.0-1.0)
+    pub movement_expressiveness: f32,
+}
+
+/// Why a personality failed validation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PersonalityError {
+    /// A parameter is NaN/infinite or outside its allowed range
+    OutOfRange {
+        field: &'static str,
+        value: f32,
+        min: f32,
+        max: f32,
+    },
+    /// Two parameters that must be ordered are not
+    Unordered {
+        lower: &'static str,
+        upper: &'static str,
+    },
+}
+
+impl fmt::Display for PersonalityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PersonalityError::OutOfRange { field, value, min, max } => {
+                write!(f, "{} = {} is outside {}..={}", field, value, min, max)
+            }
+            PersonalityError::Unordered { lower, upper } => {
+                write!(f, "{} must be below {}", lower, upper)
+            }
+        }
+    }
+}
+
+impl core::error::Error for PersonalityError {}
+
+/// Allowed (min, max) range for each parameter
+mod bounds {
+    pub const WEIGHT: (f32, f32) = (0.0, 1.0);
+    pub const SMOOTHING: (f32, f32) = (0.01, 1.0);
+    pub const ACTIVE: (f32, f32) = (0.05, 0.85);
+    pub const SPIKE: (f32, f32) = (0.10, 0.90);
+    pub const PROTECT: (f32, f32) = (0.15, 0.95);
+    pub const FATIGUE: (f32, f32) = (0.0, 1.0);
+    pub const ENERGY_RATE: (f32, f32) = (0.0, 0.01);
+    pub const CURIOSITY: (f32, f32) = (0.0, 1.0);
+    pub const EXPRESSIVENESS: (f32, f32) = (0.0, 1.0);
+
+    /// Minimum spacing between consecutive reflex thresholds
+    pub const THRESHOLD_GAP: f32 = 0.05;
+    /// Float slack when comparing threshold spacing
+    pub const GAP_EPSILON: f32 = 1e-4;
+}
+
+impl PersonalityConfig {
+    /// The original mBot2 character - matches the pre-personality brain exactly
+    pub const DEFAULT: Self = Self {
+        proximity_weight: 0.5,
+        change_weight: 0.2,
+        sound_weight: 0.15,
+        movement_weight: 0.15,
+
+        smoothing: 0.15,
+
+        active_threshold: 0.20,
+        spike_threshold: 0.55,
+        protect_threshold: 0.85,
+
+        fatigue_threshold: 0.5,
+        energy_drain: 0.001,
+        energy_recovery: 0.0005,
+
+        curiosity_low: 0.2,
+        curiosity_high: 0.6,
+
+        movement_expressiveness: 0.5,
+    };
+
+    /// Check every parameter against its safe range (ARCH-004)
+    pub fn validate(&self) -> Result<(), PersonalityError> {
+        let fields = [
+            ("proximity_weight", self.proximity_weight, bounds::WEIGHT),
+            ("change_weight", self.change_weight, bounds::WEIGHT),
+            ("sound_weight", self.sound_weight, bounds::WEIGHT),
+            ("movement_weight", self.movement_weight, bounds::WEIGHT),
+            ("smoothing", self.smoothing, bounds::SMOOTHING),
+            ("active_threshold", self.active_threshold, bounds::ACTIVE),
+            ("spike_threshold", self.spike_threshold, bounds::SPIKE),
+            ("protect_threshold", self.protect_threshold, bounds::PROTECT),
+            ("fatigue_threshold", self.fatigue_threshold, bounds::FATIGUE),
+            ("energy_drain", self.energy_drain, bounds::ENERGY_RATE),
+            ("energy_recovery", self.energy_recovery, bounds::ENERGY_RATE),
+            ("curiosity_low", self.curiosity_low, bounds::CURIOSITY),
+            ("curiosity_high", self.curiosity_high, bounds::CURIOSITY),
+            ("movement_expressiveness", self.movement_expressiveness, bounds::EXPRESSIVENESS),
+        ];
+
+        for (field, value, (min, max)) in fields {
+            if !value.is_finite() || value < min || value > max {
+                return Err(PersonalityError::OutOfRange { field, value, min, max });
+            }
+        }
+
+        if self.spike_threshold - self.active_threshold < bounds::THRESHOLD_GAP - bounds::GAP_EPSILON {
+            return Err(PersonalityError::Unordered {
+                lower: "active_threshold",
+                upper: "spike_threshold",
+            });
+        }
+        if self.protect_threshold - self.spike_threshold < bounds::THRESHOLD_GAP - bounds::GAP_EPSILON {
+            return Err(PersonalityError::Unordered {
+                lower: "spike_threshold",
+                upper: "protect_threshold",
+            });
+        }
+        if self.curiosity_high < self.curiosity_low {
+            return Err(PersonalityError::Unordered {
+                lower: "curiosity_low",
+                upper: "curiosity_high",
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Force every parameter into its safe range.
+    ///
+    /// Non-finite values fall back to the default trait, and thresholds are
+    /// pushed apart so the reflex modes always stay in order. The result
+    /// always passes `validate()`.
+    pub fn clamped(&self) -> Self {
+        let d = Self::DEFAULT;
+        let active = clamp_param(self.active_threshold, d.active_threshold, bounds::ACTIVE);
+        let spike = clamp_param(self.spike_threshold, d.spike_threshold, bounds::SPIKE)
+            .max(active + bounds::THRESHOLD_GAP)
+            .min(bounds::SPIKE.1);
+        let protect = clamp_param(self.protect_threshold, d.protect_threshold, bounds::PROTECT)
+            .max(spike + bounds::THRESHOLD_GAP)
+            .min(bounds::PROTECT.1);
+        let curiosity_low = clamp_param(self.curiosity_low, d.curiosity_low, bounds::CURIOSITY);
+        let curiosity_high = clamp_param(self.curiosity_high, d.curiosity_high, bounds::CURIOSITY)
+            .max(curiosity_low);
+
+        Self {
+            proximity_weight: clamp_param(self.proximity_weight, d.proximity_weight, bounds::WEIGHT),
+            change_weight: clamp_param(self.change_weight, d.change_weight, bounds::WEIGHT),
+            sound_weight: clamp_param(self.sound_weight, d.sound_weight, bounds::WEIGHT),
+            movement_weight: clamp_param(self.movement_weight, d.movement_weight, bounds::WEIGHT),
+            smoothing: clamp_param(self.smoothing, d.smoothing, bounds::SMOOTHING),
+            active_threshold: active,
+            spike_threshold: spike,
+            protect_threshold: protect,
+            fatigue_threshold: clamp_param(self.fatigue_threshold, d.fatigue_threshold, bounds::FATIGUE),
+            energy_drain: clamp_param(self.energy_drain, d.energy_drain, bounds::ENERGY_RATE),
+            energy_recovery: clamp_param(self.energy_recovery, d.energy_recovery, bounds::ENERGY_RATE),
+            curiosity_low,
+            curiosity_high,
+            movement_expressiveness: clamp_param(
+                self.movement_expressiveness,
+                d.movement_expressiveness,
+                bounds::EXPRESSIVENESS,
+            ),
+        }
+    }
+
+    /// Linear blend between two personalities (t = 0.0 is `self`, 1.0 is `other`).
+    ///
+    /// Blending two valid personalities always yields a valid one.
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        let t = t.clamp(0.0, 1.0);
+        let mix = |a: f32, b: f32| a + (b - a) * t;
+
+        Self {
+            proximity_weight: mix(self.proximity_weight, other.proximity_weight),
+            change_weight: mix(self.change_weight, other.change_weight),
+            sound_weight: mix(self.sound_weight, other.sound_weight),
+            movement_weight: mix(self.movement_weight, other.movement_weight),
+            smoothing: mix(self.smoothing, other.smoothing),
+            active_threshold: mix(self.active_threshold, other.active_threshold),
+            spike_threshold: mix(self.spike_threshold, other.spike_threshold),
+            protect_threshold: mix(self.protect_threshold, other.protect_threshold),
+            fatigue_threshold: mix(self.fatigue_threshold, other.fatigue_threshold),
+            energy_drain: mix(self.energy_drain, other.energy_drain),
+            energy_recovery: mix(self.energy_recovery, other.energy_recovery),
+            curiosity_low: mix(self.curiosity_low, other.curiosity_low),
+            curiosity_high: mix(self.curiosity_high, other.curiosity_high),
+            movement_expressiveness: mix(self.movement_expressiveness, other.movement_expressiveness),
+        }
+        .clamped()
+    }
+}
+
+impl Default for PersonalityConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+// === PRESET PERSONALITIES ===
+
+impl PersonalityConfig {
+    /// Must investigate everything: slips into Active easily and stays curious
+    pub const CURIOUS_GEORGE: Self = Self {
+        proximity_weight: 0.45,
+        change_weight: 0.35,
+        sound_weight: 0.15,
+        movement_weight: 0.1,
+
+        smoothing: 0.2,
+
+        active_threshold: 0.12,
+        spike_threshold: 0.65,
+        protect_threshold: 0.88,
+
+        fatigue_threshold: 0.6,
+        energy_drain: 0.0008,
+        energy_recovery: 0.001,
+
+        curiosity_low: 0.1,
+        curiosity_high: 0.8,
+
+        movement_expressiveness: 0.65,
+    };
+
+    /// Worried about everything: reacts fast, startles early, tires quickly
+    pub const NERVOUS_NELLIE: Self = Self {
+        proximity_weight: 0.8,
+        change_weight: 0.4,
+        sound_weight: 0.35,
+        movement_weight: 0.25,
+
+        smoothing: 0.3,
+
+        active_threshold: 0.15,
+        spike_threshold: 0.4,
+        protect_threshold: 0.7,
+
+        fatigue_threshold: 0.4,
+        energy_drain: 0.002,
+        energy_recovery: 0.0003,
+
+        curiosity_low: 0.15,
+        curiosity_high: 0.35,
+
+        movement_expressiveness: 0.4,
+    };
+
+    /// Nothing bothers them: dull reactions, slow smoothing, high thresholds
+    pub const CHILL_CHARLIE: Self = Self {
+        proximity_weight: 0.3,
+        change_weight: 0.1,
+        sound_weight: 0.05,
+        movement_weight: 0.05,
+
+        smoothing: 0.06,
+
+        active_threshold: 0.3,
+        spike_threshold: 0.7,
+        protect_threshold: 0.9,
+
+        fatigue_threshold: 0.7,
+        energy_drain: 0.0005,
+        energy_recovery: 0.001,
+
+        curiosity_low: 0.3,
+        curiosity_high: 0.7,
+
+        movement_expressiveness: 0.3,
+    };
+
+    /// Can't stop moving: big movements and energy that barely runs out
+    pub const BOUNCY_BETTY: Self = Self {
+        proximity_weight: 0.45,
+        change_weight: 0.3,
+        sound_weight: 0.2,
+        movement_weight: 0.15,
+
+        smoothing: 0.25,
+
+        active_threshold: 0.1,
+        spike_threshold: 0.45,
+        protect_threshold: 0.85,
+
+        fatigue_threshold: 0.7,
+        energy_drain: 0.0004,
+        energy_recovery: 0.003,
+
+        curiosity_low: 0.1,
+        curiosity_high: 0.7,
+
+        movement_expressiveness: 0.9,
+    };
+
+    /// Look up a built-in personality by id or display name (case-insensitive)
+    pub fn preset(name: &str) -> Option<Self> {
+        PersonalityPreset::find(name).map(|p| p.config)
+    }
+}
+
+/// A named built-in personality
+#[derive(Clone, Copy, Debug)]
+pub struct PersonalityPreset {
+    /// Stable identifier used on the command line and in saved files
+    pub id: &'static str,
+    /// Display name
+    pub name: &'static str,
+    /// Emoji shown in status output
+    pub icon: &'static str,
+    /// One-line description of the character
+    pub description: &'static str,
+    pub config: PersonalityConfig,
+}
+
+impl PersonalityPreset {
+    /// Find a built-in personality by id or display name (case-insensitive)
+    pub fn find(name: &str) -> Option<&'static PersonalityPreset> {
+        PRESETS
+            .iter()
+            .find(|p| p.id.eq_ignore_ascii_case(name) || p.name.eq_ignore_ascii_case(name))
+    }
+}
+
+/// Every personality that ships with mbot-core
+pub static PRESETS: [PersonalityPreset; 5] = [
+    PersonalityPreset {
+        id: "default",
+        name: "Default",
+        icon: "🤖",
+        description: "The stock mBot2 nervous system",
+        config: PersonalityConfig::DEFAULT,
+    },
+    PersonalityPreset {
+        id: "curious-george",
+        name: "Curious George",
+        icon: "🔍",
+        description: "Must investigate everything",
+        config: PersonalityConfig::CURIOUS_GEORGE,
+    },
+    PersonalityPreset {
+        id: "nervous-nellie",
+        name: "Nervous Nellie",
+        icon: "😰",
+        description: "Worried about everything",
+        config: PersonalityConfig::NERVOUS_NELLIE,
+    },
+    PersonalityPreset {
+        id: "chill-charlie",
+        name: "Chill Charlie",
+        icon: "😎",
+        description: "Nothing bothers them",
+        config: PersonalityConfig::CHILL_CHARLIE,
+    },
+    PersonalityPreset {
+        id: "bouncy-betty",
+        name: "Bouncy Betty",
+        icon: "🎉",
+        description: "Can't stop moving",
+        config: PersonalityConfig::BOUNCY_BETTY,
+    },
+];
+
+fn clamp_param(value: f32, fallback: f32, (min, max): (f32, f32)) -> f32 {
+    if value.is_finite() {
+        value.clamp(min, max)
+    } else {
+        fallback
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MBotBrain, MBotSensors, ReflexMode};
+
+    #[test]
+    fn test_default_is_valid() {
+        assert_eq!(PersonalityConfig::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_rejects_out_of_range() {
+        let config = PersonalityConfig {
+            smoothing: 0.0,
+            ..PersonalityConfig::DEFAULT
+        };
+        assert!(matches!(
+            config.validate(),
+            Err(PersonalityError::OutOfRange { field: "smoothing", .. })
+        ));
+
+        let config = PersonalityConfig {
+            sound_weight: f32::NAN,
+            ..PersonalityConfig::DEFAULT
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_rejects_unordered_thresholds() {
+        let config = PersonalityConfig {
+            active_threshold: 0.6,
+            spike_threshold: 0.5,
+            ..PersonalityConfig::DEFAULT
+        };
+        assert_eq!(
+            config.validate(),
+            Err(PersonalityError::Unordered {
+                lower: "active_threshold",
+                upper: "spike_threshold",
+            })
+        );
+    }
+
+    #[test]
+    fn test_clamped_is_always_valid() {
+        let wild = PersonalityConfig {
+            proximity_weight: 5.0,
+            change_weight: -1.0,
+            sound_weight: f32::INFINITY,
+            movement_weight: f32::NAN,
+            smoothing: 0.0,
+            active_threshold: 2.0,
+            spike_threshold: 0.0,
+            protect_threshold: 0.0,
+            fatigue_threshold: -3.0,
+            energy_drain: 1.0,
+            energy_recovery: -1.0,
+            curiosity_low: 0.9,
+            curiosity_high: 0.1,
+            movement_expressiveness: 3.0,
+        };
+
+        let safe = wild.clamped();
+        assert_eq!(safe.validate(), Ok(()));
+        assert_eq!(safe.proximity_weight, 1.0);
+        assert_eq!(safe.sound_weight, PersonalityConfig::DEFAULT.sound_weight);
+        assert!(safe.active_threshold < safe.spike_threshold);
+        assert!(safe.spike_threshold < safe.protect_threshold);
+    }
+
+    #[test]
+    fn test_presets_are_valid() {
+        for preset in PRESETS.iter() {
+            assert_eq!(preset.config.validate(), Ok(()), "{} is out of bounds", preset.id);
+        }
+    }
+
+    #[test]
+    fn test_preset_lookup() {
+        assert_eq!(
+            PersonalityConfig::preset("nervous-nellie"),
+            Some(PersonalityConfig::NERVOUS_NELLIE)
+        );
+        assert_eq!(
+            PersonalityConfig::preset("Chill Charlie"),
+            Some(PersonalityConfig::CHILL_CHARLIE)
+        );
+        assert_eq!(PersonalityConfig::preset("grumpy"), None);
+    }
+
+    /// A recorded play session: idle, a hand approaching and retreating,
+    /// a noisy moment, a close startle and a quiet cool-down.
+    fn recorded_session() -> impl Iterator<Item = MBotSensors> {
+        (0..600u64).map(|tick| {
+            let t = tick as f32;
+            let (distance, sound) = match tick {
+                0..=99 => (150.0, 0.05),
+                100..=249 => (150.0 - (t - 100.0) * 0.8, 0.1),
+                250..=329 => (30.0 + ((t * 0.7) as u32 % 25) as f32, 0.6),
+                330..=379 => (8.0 + (tick % 5) as f32, 0.3),
+                380..=449 => (40.0 + (t - 380.0), 0.2),
+                _ => (120.0, 0.05),
+            };
+            MBotSensors {
+                timestamp_us: tick * 50_000,
+                ultrasonic_cm: distance,
+                accel: [0.0, 0.0, 9.8],
+                sound_level: sound,
+                light_level: 0.5,
+                ..Default::default()
+            }
+        })
+    }
+
+    struct SessionStats {
+        /// Ticks spent in [Calm, Active, Spike, Protect]
+        modes: [u32; 4],
+        /// Average absolute motor power
+        mean_power: f32,
+    }
+
+    fn run_session(config: PersonalityConfig) -> SessionStats {
+        let mut brain = MBotBrain::with_personality(config);
+        let mut modes = [0u32; 4];
+        let mut power = 0.0;
+        let mut ticks = 0;
+
+        for sensors in recorded_session() {
+            let (state, cmd) = brain.tick(&sensors);
+            let slot = match state.reflex {
+                ReflexMode::Calm => 0,
+                ReflexMode::Active => 1,
+                ReflexMode::Spike => 2,
+                ReflexMode::Protect => 3,
+            };
+            modes[slot] += 1;
+            power += (cmd.left as f32).abs() + (cmd.right as f32).abs();
+            ticks += 1;
+        }
+
+        SessionStats {
+            modes,
+            mean_power: power / (2 * ticks) as f32,
+        }
+    }
+
+    #[test]
+    fn test_presets_differ_on_same_session() {
+        let default = run_session(PersonalityConfig::DEFAULT);
+        let george = run_session(PersonalityConfig::CURIOUS_GEORGE);
+        let nellie = run_session(PersonalityConfig::NERVOUS_NELLIE);
+        let charlie = run_session(PersonalityConfig::CHILL_CHARLIE);
+        let betty = run_session(PersonalityConfig::BOUNCY_BETTY);
+
+        let aroused = |s: &SessionStats| s.modes[2] + s.modes[3];
+
+        // Nellie is the most easily alarmed
+        assert!(aroused(&nellie) > aroused(&default));
+        assert!(aroused(&nellie) > aroused(&charlie));
+
+        // Charlie stays calm the longest
+        assert!(charlie.modes[0] > default.modes[0]);
+        assert!(charlie.modes[0] > nellie.modes[0]);
+        assert!(charlie.modes[0] > george.modes[0]);
+
+        // George spends more time exploring than the laid-back robots
+        assert!(george.modes[1] > default.modes[1]);
+        assert!(george.modes[1] > charlie.modes[1]);
+
+        // Betty moves more than anyone else
+        assert!(betty.mean_power > default.mean_power);
+        assert!(betty.mean_power > charlie.mean_power);
+        assert!(betty.mean_power > nellie.mean_power);
+    }
+
+    #[test]
+    fn test_lerp_endpoints_and_midpoint() {
+        let a = PersonalityConfig::CHILL_CHARLIE;
+        let b = PersonalityConfig::NERVOUS_NELLIE;
+
+        assert_eq!(a.lerp(&b, 0.0), a);
+        assert_eq!(a.lerp(&b, 1.0), b);
+
+        let mid = a.lerp(&b, 0.5);
+        assert_eq!(mid.validate(), Ok(()));
+        assert!((mid.smoothing - (a.smoothing + b.smoothing) / 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_clamped_keeps_valid_config() {
+        let config = PersonalityConfig::DEFAULT;
+        assert_eq!(config.clamped(), config);
+    }
+}
@@ -0,0 +1,346 @@
+//! Reflex state machine - hysteresis and dwell time on mode changes
+//!
+//! `ReflexMode::from_tension_with` is a pure threshold function: tension
+//! hovering near a boundary makes the mode (and with it the LEDs and the
+//! motors) flicker every tick. The state machine only steps down once
+//! tension has dropped a band below the boundary, and holds each mode for
+//! a minimum time. Escalating into Protect is never delayed.
+
+use crate::{PersonalityConfig, ReflexMode};
+
+/// How sticky each reflex mode is
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReflexConfig {
+    /// Band below each boundary [Calm/Active, Active/Spike, Spike/Protect]
+    /// that tension must fall through before stepping down (0.0-0.2)
+    pub hysteresis: [f32; 3],
+    /// Minimum milliseconds spent in [Calm, Active, Spike, Protect] before
+    /// leaving (0-10000). Entering Protect ignores this.
+    pub min_dwell_ms: [u32; 4],
+}
+
+const MAX_HYSTERESIS: f32 = 0.2;
+const MAX_DWELL_MS: u32 = 10_000;
+
+impl ReflexConfig {
+    /// Enough to stop boundary flicker without feeling sluggish
+    pub const DEFAULT: Self = Self {
+        hysteresis: [0.03, 0.05, 0.05],
+        min_dwell_ms: [250, 250, 250, 500],
+    };
+
+    /// Plain thresholds with no hysteresis or dwell (the original behavior)
+    pub const NONE: Self = Self {
+        hysteresis: [0.0; 3],
+        min_dwell_ms: [0; 4],
+    };
+
+    /// Force every value into its safe range
+    pub fn clamped(&self) -> Self {
+        let mut out = *self;
+        for band in out.hysteresis.iter_mut() {
+            *band = if band.is_finite() { band.clamp(0.0, MAX_HYSTERESIS) } else { 0.0 };
+        }
+        for dwell in out.min_dwell_ms.iter_mut() {
+            *dwell = (*dwell).min(MAX_DWELL_MS);
+        }
+        out
+    }
+}
+
+impl Default for ReflexConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// A reflex mode change, stamped with the brain tick it happened on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReflexTransition {
+    pub from: ReflexMode,
+    pub to: ReflexMode,
+    pub tick: u64,
+}
+
+/// Number of unread transitions kept before the oldest is dropped
+pub const TRANSITION_QUEUE_LEN: usize = 16;
+
+/// Fixed-capacity FIFO of transitions (no heap)
+#[derive(Clone, Debug)]
+pub(crate) struct TransitionQueue {
+    items: [Option<ReflexTransition>; TRANSITION_QUEUE_LEN],
+    head: usize,
+    len: usize,
+}
+
+impl TransitionQueue {
+    pub(crate) const fn new() -> Self {
+        Self {
+            items: [None; TRANSITION_QUEUE_LEN],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub(crate) fn push(&mut self, transition: ReflexTransition) {
+        let tail = (self.head + self.len) % TRANSITION_QUEUE_LEN;
+        self.items[tail] = Some(transition);
+        if self.len == TRANSITION_QUEUE_LEN {
+            // Full: the oldest event is overwritten
+            self.head = (self.head + 1) % TRANSITION_QUEUE_LEN;
+        } else {
+            self.len += 1;
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<ReflexTransition> {
+        if self.len == 0 {
+            return None;
+        }
+        let item = self.items[self.head].take();
+        self.head = (self.head + 1) % TRANSITION_QUEUE_LEN;
+        self.len -= 1;
+        item
+    }
+}
+
+/// Tracks the current reflex mode with hysteresis and dwell
+#[derive(Clone, Debug)]
+pub struct ReflexStateMachine {
+    config: ReflexConfig,
+    mode: ReflexMode,
+    time_in_mode_us: u64,
+}
+
+impl ReflexStateMachine {
+    pub fn new(config: ReflexConfig) -> Self {
+        Self {
+            config: config.clamped(),
+            mode: ReflexMode::Calm,
+            time_in_mode_us: 0,
+        }
+    }
+
+    /// Current mode
+    pub fn mode(&self) -> ReflexMode {
+        self.mode
+    }
+
+    pub fn config(&self) -> &ReflexConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: ReflexConfig) {
+        self.config = config.clamped();
+    }
+
+    /// Time spent in the current mode so far
+    pub fn time_in_mode_us(&self) -> u64 {
+        self.time_in_mode_us
+    }
+
+    /// Resume in `mode` as if it had been held for `time_in_mode_us`
+    pub(crate) fn resume(&mut self, mode: ReflexMode, time_in_mode_us: u64) {
+        self.mode = mode;
+        self.time_in_mode_us = time_in_mode_us;
+    }
+
+    /// Feed one tick of tension, `dt_us` after the previous one; returns the
+    /// transition if the mode changed
+    pub fn update(
+        &mut self,
+        tension: f32,
+        personality: &PersonalityConfig,
+        tick: u64,
+        dt_us: u64,
+    ) -> Option<ReflexTransition> {
+        self.time_in_mode_us = self.time_in_mode_us.saturating_add(dt_us);
+
+        let target = self.target(tension, personality);
+        if target == self.mode {
+            return None;
+        }
+
+        let dwell_us = self.config.min_dwell_ms[level(self.mode)] as u64 * 1000;
+        if target != ReflexMode::Protect && self.time_in_mode_us < dwell_us {
+            return None;
+        }
+
+        let transition = ReflexTransition {
+            from: self.mode,
+            to: target,
+            tick,
+        };
+        self.mode = target;
+        self.time_in_mode_us = 0;
+        Some(transition)
+    }
+
+    /// Where tension wants the mode to go, with hysteresis on the way down
+    fn target(&self, tension: f32, personality: &PersonalityConfig) -> ReflexMode {
+        let raw = ReflexMode::from_tension_with(tension, personality);
+        if level(raw) >= level(self.mode) {
+            return raw;
+        }
+
+        let thresholds = [
+            personality.active_threshold,
+            personality.spike_threshold,
+            personality.protect_threshold,
+        ];
+        let mut current = level(self.mode);
+        while current > level(raw) {
+            let boundary = current - 1;
+            if tension >= thresholds[boundary] - self.config.hysteresis[boundary] {
+                break;
+            }
+            current -= 1;
+        }
+        from_level(current)
+    }
+}
+
+impl Default for ReflexStateMachine {
+    fn default() -> Self {
+        Self::new(ReflexConfig::DEFAULT)
+    }
+}
+
+fn level(mode: ReflexMode) -> usize {
+    match mode {
+        ReflexMode::Calm => 0,
+        ReflexMode::Active => 1,
+        ReflexMode::Spike => 2,
+        ReflexMode::Protect => 3,
+    }
+}
+
+fn from_level(level: usize) -> ReflexMode {
+    match level {
+        0 => ReflexMode::Calm,
+        1 => ReflexMode::Active,
+        2 => ReflexMode::Spike,
+        _ => ReflexMode::Protect,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const P: PersonalityConfig = PersonalityConfig::DEFAULT;
+    const DT: u64 = 50_000;
+
+    /// Tension jittering +/-0.04 around the Active/Spike boundary (0.55)
+    fn noisy_boundary(tick: u64) -> f32 {
+        let jitter = [0.03, -0.04, 0.01, -0.02, 0.04, -0.03, 0.02, -0.01];
+        0.55 + jitter[(tick % 8) as usize]
+    }
+
+    #[test]
+    fn test_suppresses_flicker_on_noisy_tension() {
+        let mut machine = ReflexStateMachine::new(ReflexConfig::DEFAULT);
+        let mut raw_flips = 0;
+        let mut last_raw = ReflexMode::Calm;
+        let mut transitions = 0;
+
+        for tick in 0..200 {
+            let tension = noisy_boundary(tick);
+            let raw = ReflexMode::from_tension(tension);
+            if raw != last_raw {
+                raw_flips += 1;
+                last_raw = raw;
+            }
+            if machine.update(tension, &P, tick, DT).is_some() {
+                transitions += 1;
+            }
+        }
+
+        assert!(raw_flips > 50, "test input should flicker, got {}", raw_flips);
+        // Calm -> Active/Spike once, then held by the hysteresis band
+        assert!(transitions <= 2, "machine flickered {} times", transitions);
+    }
+
+    #[test]
+    fn test_none_config_matches_thresholds() {
+        let mut machine = ReflexStateMachine::new(ReflexConfig::NONE);
+        for tick in 0..200 {
+            let tension = noisy_boundary(tick);
+            machine.update(tension, &P, tick, DT);
+            assert_eq!(machine.mode(), ReflexMode::from_tension(tension));
+        }
+    }
+
+    #[test]
+    fn test_steps_down_below_band() {
+        let mut machine = ReflexStateMachine::new(ReflexConfig {
+            hysteresis: [0.05; 3],
+            min_dwell_ms: [0; 4],
+        });
+        machine.update(0.7, &P, 0, DT);
+        assert_eq!(machine.mode(), ReflexMode::Spike);
+
+        // Just under the threshold but inside the band: stay
+        assert_eq!(machine.update(0.52, &P, 1, DT), None);
+        assert_eq!(machine.mode(), ReflexMode::Spike);
+
+        // Through the band: step down
+        let t = machine.update(0.49, &P, 2, DT).unwrap();
+        assert_eq!((t.from, t.to, t.tick), (ReflexMode::Spike, ReflexMode::Active, 2));
+
+        // A big drop passes several bands at once
+        machine.update(0.7, &P, 3, DT);
+        let t = machine.update(0.05, &P, 4, DT).unwrap();
+        assert_eq!((t.from, t.to), (ReflexMode::Spike, ReflexMode::Calm));
+    }
+
+    #[test]
+    fn test_min_dwell_holds_mode() {
+        let mut machine = ReflexStateMachine::new(ReflexConfig {
+            hysteresis: [0.0; 3],
+            min_dwell_ms: [0, 250, 0, 0],
+        });
+        assert!(machine.update(0.3, &P, 0, DT).is_some());
+
+        // Four more 50ms ticks in Active before it may leave
+        for tick in 1..5 {
+            assert_eq!(machine.update(0.05, &P, tick, DT), None);
+        }
+        assert!(machine.update(0.05, &P, 5, DT).is_some());
+        assert_eq!(machine.mode(), ReflexMode::Calm);
+    }
+
+    #[test]
+    fn test_protect_ignores_dwell() {
+        let mut machine = ReflexStateMachine::new(ReflexConfig {
+            hysteresis: [0.0; 3],
+            min_dwell_ms: [5000; 4],
+        });
+        machine.update(0.3, &P, 0, DT);
+        let t = machine.update(0.95, &P, 1, DT).unwrap();
+        assert_eq!(t.to, ReflexMode::Protect);
+    }
+
+    #[test]
+    fn test_transition_queue_drops_oldest() {
+        let mut queue = TransitionQueue::new();
+        for tick in 0..(TRANSITION_QUEUE_LEN as u64 + 4) {
+            queue.push(ReflexTransition {
+                from: ReflexMode::Calm,
+                to: ReflexMode::Active,
+                tick,
+            });
+        }
+        assert_eq!(queue.pop().unwrap().tick, 4);
+        let mut remaining = 1;
+        while queue.pop().is_some() {
+            remaining += 1;
+        }
+        assert_eq!(remaining, TRANSITION_QUEUE_LEN);
+    }
+}
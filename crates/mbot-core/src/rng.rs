@@ -0,0 +1,116 @@
+//! Seeded deterministic randomness (ARCH-002)
+//!
+//! The brain never touches a global or OS random source: all variety comes
+//! from this generator, so a run is reproducible from its seed plus the
+//! sensor log.
+
+/// Seed used when none is given
+pub const DEFAULT_SEED: u64 = 0x6d62_6f74_3220_5256; // "mbot2 RV"
+
+/// Small, fast xorshift64* generator - not for cryptography
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Create a generator from any seed (including zero)
+    pub fn new(seed: u64) -> Self {
+        // SplitMix64 scrambles similar seeds apart and never yields zero
+        // for xorshift's state
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        Self::from_state(z)
+    }
+
+    /// Resume from a state captured with `state()`
+    pub fn from_state(state: u64) -> Self {
+        Self {
+            state: if state == 0 { DEFAULT_SEED } else { state },
+        }
+    }
+
+    /// Internal state, for snapshots
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    /// Uniform in [0.0, 1.0)
+    pub fn next_f32(&mut self) -> f32 {
+        // 24 random mantissa bits
+        (self.next_u64() >> 40) as f32 / (1u32 << 24) as f32
+    }
+
+    /// Uniform in [low, high)
+    pub fn range_f32(&mut self, low: f32, high: f32) -> f32 {
+        low + (high - low) * self.next_f32()
+    }
+
+    /// True with the given probability (0.0-1.0)
+    pub fn chance(&mut self, probability: f32) -> bool {
+        self.next_f32() < probability
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self::new(DEFAULT_SEED)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+    }
+
+    #[test]
+    fn test_different_seeds_diverge() {
+        let mut a = Rng::new(1);
+        let mut b = Rng::new(2);
+        assert_ne!(a.next_u64(), b.next_u64());
+    }
+
+    #[test]
+    fn test_state_round_trip() {
+        let mut rng = Rng::new(0);
+        rng.next_u64();
+        let mut resumed = Rng::from_state(rng.state());
+        assert_eq!(rng.next_u64(), resumed.next_u64());
+    }
+
+    #[test]
+    fn test_f32_range() {
+        let mut rng = Rng::new(7);
+        let mut sum = 0.0;
+        for _ in 0..10_000 {
+            let x = rng.next_f32();
+            assert!((0.0..1.0).contains(&x));
+            sum += x;
+        }
+        // Roughly uniform
+        assert!((sum / 10_000.0 - 0.5).abs() < 0.02);
+    }
+}
@@ -0,0 +1,323 @@
+//! Brain snapshots - checkpoint and resume a brain mid-session
+//!
+//! A snapshot holds everything that shapes the brain's future outputs, so a
+//! restored brain fed the same sensor frames produces the same commands as
+//! the original would have. Unread reflex transitions are notifications,
+//! not state, and are not captured.
+
+use crate::{
+    Calibration, FusionConfig, MBotBrain, MoodMemory, PersonalityConfig, PersonalityTransition, Pose,
+    ReflexConfig, ReflexMode, Rng, WANDER_NOISE_MAX,
+};
+
+/// Complete copy of a brain's internal state
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BrainSnapshot {
+    // Personality
+    pub personality: PersonalityConfig,
+    pub transition: Option<PersonalityTransition>,
+
+    // Homeostasis
+    pub tension_ema: f32,
+    pub coherence_ema: f32,
+    pub energy: f32,
+
+    // Last sensor values
+    pub last_distance: f32,
+    pub last_encoder_left: i32,
+    pub last_encoder_right: i32,
+
+    // Behavior parameters
+    pub base_speed: f32,
+    pub turn_gain: f32,
+    pub approach_distance: f32,
+    pub danger_distance: f32,
+
+    // Pose and pen
+    pub pen_down: bool,
+    pub pose: Pose,
+    pub fusion_config: FusionConfig,
+    pub calibration: Calibration,
+
+    // Randomness
+    pub rng_state: u64,
+    pub wander_noise: f32,
+    pub investigate_side: f32,
+
+    // Clock
+    pub last_timestamp_us: Option<u64>,
+    pub elapsed_us: u64,
+    pub tick_count: u64,
+
+    // Reflex
+    pub reflex_config: ReflexConfig,
+    pub reflex_mode: ReflexMode,
+    pub reflex_time_in_mode_us: u64,
+
+    // Long-term mood
+    pub mood: MoodMemory,
+}
+
+impl MBotBrain {
+    /// Capture the brain's full state
+    pub fn snapshot(&self) -> BrainSnapshot {
+        BrainSnapshot {
+            personality: self.personality,
+            transition: self.transition,
+            tension_ema: self.tension_ema,
+            coherence_ema: self.coherence_ema,
+            energy: self.energy,
+            last_distance: self.last_distance,
+            last_encoder_left: self.last_encoder_left,
+            last_encoder_right: self.last_encoder_right,
+            base_speed: self.base_speed,
+            turn_gain: self.turn_gain,
+            approach_distance: self.approach_distance,
+            danger_distance: self.danger_distance,
+            pen_down: self.pen_down,
+            pose: *self.pose.pose(),
+            fusion_config: *self.pose.config(),
+            calibration: self.calibration,
+            rng_state: self.rng.state(),
+            wander_noise: self.wander_noise,
+            investigate_side: self.investigate_side,
+            last_timestamp_us: self.last_timestamp_us,
+            elapsed_us: self.elapsed_us,
+            tick_count: self.tick_count,
+            reflex_config: *self.reflex.config(),
+            reflex_mode: self.reflex.mode(),
+            reflex_time_in_mode_us: self.reflex.time_in_mode_us(),
+            mood: self.mood,
+        }
+    }
+
+    /// Resume from a snapshot, discarding unread reflex transitions.
+    ///
+    /// Personality and reflex settings are clamped into their safe bounds
+    /// (ARCH-004), as they are everywhere else they enter the brain; an
+    /// implausible calibration falls back to the default. Homeostasis is
+    /// clamped to 0-1 and any other value that isn't a finite number takes
+    /// a new brain's, as the profile decoder does, so one corrupt field
+    /// can't turn every later output into NaN.
+    pub fn restore(&mut self, snapshot: &BrainSnapshot) {
+        let fresh = Self::new();
+        let finite = |v: f32, fallback: f32| if v.is_finite() { v } else { fallback };
+        let unit = |v: f32, fallback: f32| finite(v, fallback).clamp(0.0, 1.0);
+
+        self.personality = snapshot.personality.clamped();
+        self.transition = snapshot.transition.map(|t| {
+            PersonalityTransition::new(*t.from(), *t.to(), t.duration_us(), t.elapsed_us())
+        });
+        self.tension_ema = unit(snapshot.tension_ema, fresh.tension_ema);
+        self.coherence_ema = unit(snapshot.coherence_ema, fresh.coherence_ema);
+        self.energy = unit(snapshot.energy, fresh.energy);
+        self.last_distance = match snapshot.last_distance {
+            d if d.is_finite() && d > 0.0 => d,
+            _ => fresh.last_distance,
+        };
+        self.last_encoder_left = snapshot.last_encoder_left;
+        self.last_encoder_right = snapshot.last_encoder_right;
+        self.base_speed = finite(snapshot.base_speed, fresh.base_speed);
+        self.turn_gain = finite(snapshot.turn_gain, fresh.turn_gain);
+        self.approach_distance = finite(snapshot.approach_distance, fresh.approach_distance);
+        self.danger_distance = finite(snapshot.danger_distance, fresh.danger_distance);
+        self.pen_down = snapshot.pen_down;
+        self.pose.set_pose(snapshot.pose.clamped());
+        self.pose.set_config(snapshot.fusion_config);
+        if self.set_calibration(snapshot.calibration).is_err() {
+            self.calibration = Calibration::DEFAULT;
+        }
+        self.rng = Rng::from_state(snapshot.rng_state);
+        self.wander_noise =
+            finite(snapshot.wander_noise, 0.0).clamp(-WANDER_NOISE_MAX, WANDER_NOISE_MAX);
+        self.investigate_side = if snapshot.investigate_side < 0.0 { -1.0 } else { 1.0 };
+        self.last_timestamp_us = snapshot.last_timestamp_us;
+        self.elapsed_us = snapshot.elapsed_us;
+        self.tick_count = snapshot.tick_count;
+        self.reflex.set_config(snapshot.reflex_config);
+        self.reflex.resume(snapshot.reflex_mode, snapshot.reflex_time_in_mode_us);
+        self.transitions.clear();
+        self.mood = snapshot.mood.clamped();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MBotSensors;
+
+    /// A snapshot where every field differs from a new brain's
+    fn unusual() -> BrainSnapshot {
+        BrainSnapshot {
+            personality: PersonalityConfig::NERVOUS_NELLIE,
+            transition: Some(PersonalityTransition::new(
+                PersonalityConfig::NERVOUS_NELLIE,
+                PersonalityConfig::CHILL_CHARLIE,
+                4_000_000,
+                1_500_000,
+            )),
+            tension_ema: 0.42,
+            coherence_ema: 0.77,
+            energy: 0.63,
+            last_distance: 57.5,
+            last_encoder_left: -1234,
+            last_encoder_right: 5678,
+            base_speed: 35.0,
+            turn_gain: 25.0,
+            approach_distance: 45.0,
+            danger_distance: 12.0,
+            pen_down: true,
+            pose: Pose {
+                x: 12.5,
+                y: -7.25,
+                heading: 1.3,
+                heading_variance: 0.004,
+                position_variance: 2.5,
+                gyro_bias_dps: -0.4,
+            },
+            fusion_config: FusionConfig {
+                gyro_noise_dps: 2.0,
+                encoder_noise_per_cm: 0.03,
+                distance_noise_per_cm: 0.05,
+                slip_gate: 4.0,
+                use_gyro: false,
+            },
+            calibration: Calibration {
+                ticks_per_cm: 17.5,
+                wheel_base_cm: 11.2,
+            },
+            rng_state: 0x0123_4567_89ab_cdef,
+            wander_noise: -2.5,
+            investigate_side: -1.0,
+            last_timestamp_us: Some(987_654_321),
+            elapsed_us: 49_350_000,
+            tick_count: 987,
+            reflex_config: ReflexConfig {
+                hysteresis: [0.01, 0.02, 0.03],
+                min_dwell_ms: [100, 200, 300, 400],
+            },
+            reflex_mode: ReflexMode::Spike,
+            reflex_time_in_mode_us: 150_000,
+            mood: MoodMemory {
+                energy: 0.63,
+                long_tension: 0.3,
+                long_coherence: 0.85,
+                startle_count: 7,
+                lifetime_us: 6_172_800_000,
+            },
+        }
+    }
+
+    fn sensors(tick: u64) -> MBotSensors {
+        MBotSensors {
+            timestamp_us: tick * 50_000,
+            ultrasonic_cm: 30.0 + ((tick * 23) % 70) as f32,
+            encoder_left: tick as i32 * 6,
+            encoder_right: tick as i32 * 4,
+            accel: [0.0, 0.0, 9.8],
+            sound_level: 0.3,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_round_trip_covers_every_field() {
+        let mut brain = MBotBrain::new();
+        assert_ne!(brain.snapshot(), unusual());
+
+        brain.restore(&unusual());
+        assert_eq!(brain.snapshot(), unusual());
+    }
+
+    #[test]
+    fn test_round_trip_mid_session() {
+        let mut brain = MBotBrain::seeded(PersonalityConfig::BOUNCY_BETTY, 99);
+        brain.transition_to(PersonalityConfig::CURIOUS_GEORGE, 5_000_000);
+        for tick in 0..250 {
+            brain.tick(&sensors(tick));
+        }
+
+        let snapshot = brain.snapshot();
+        let mut restored = MBotBrain::new();
+        restored.restore(&snapshot);
+        assert_eq!(restored.snapshot(), snapshot);
+    }
+
+    #[test]
+    fn test_restored_brain_continues_identically() {
+        let mut brain = MBotBrain::seeded(PersonalityConfig::CURIOUS_GEORGE, 5);
+        for tick in 0..200 {
+            brain.tick(&sensors(tick));
+        }
+
+        let mut restored = MBotBrain::new();
+        restored.restore(&brain.snapshot());
+        for tick in 200..400 {
+            let (a_state, a_cmd) = brain.tick(&sensors(tick));
+            let (b_state, b_cmd) = restored.tick(&sensors(tick));
+            assert_eq!((a_cmd.left, a_cmd.right), (b_cmd.left, b_cmd.right));
+            assert_eq!(a_state.reflex, b_state.reflex);
+            assert_eq!(a_state.tension, b_state.tension);
+        }
+        assert_eq!(brain.position(), restored.position());
+        assert_eq!(brain.snapshot(), restored.snapshot());
+    }
+
+    #[test]
+    fn test_restore_clamps_unsafe_personality() {
+        let mut snapshot = unusual();
+        snapshot.personality.smoothing = 50.0;
+        snapshot.reflex_config.min_dwell_ms[0] = u32::MAX;
+
+        let mut brain = MBotBrain::new();
+        brain.restore(&snapshot);
+        assert!(brain.personality().validate().is_ok());
+        assert_eq!(brain.snapshot().reflex_config, snapshot.reflex_config.clamped());
+    }
+
+    #[test]
+    fn test_restore_rejects_non_finite_state() {
+        let mut snapshot = unusual();
+        snapshot.tension_ema = f32::NAN;
+        snapshot.coherence_ema = f32::INFINITY;
+        snapshot.energy = f32::NEG_INFINITY;
+        snapshot.last_distance = f32::NAN;
+        snapshot.wander_noise = f32::INFINITY;
+        snapshot.investigate_side = f32::NAN;
+        snapshot.pose.heading = f32::NAN;
+
+        let mut brain = MBotBrain::new();
+        brain.restore(&snapshot);
+        let restored = brain.snapshot();
+        assert_eq!(restored.tension_ema, 0.0);
+        assert_eq!(restored.coherence_ema, 1.0);
+        assert_eq!(restored.energy, 1.0);
+        assert_eq!(restored.last_distance, 100.0);
+        assert_eq!(restored.wander_noise, 0.0);
+        assert_eq!(restored.investigate_side, 1.0);
+        assert_eq!(
+            restored.pose,
+            Pose { gyro_bias_dps: snapshot.pose.gyro_bias_dps, ..Pose::ORIGIN }
+        );
+
+        for tick in 0..50 {
+            let (state, cmd) = brain.tick(&sensors(tick));
+            assert!(state.tension.is_finite());
+            assert!((-100..=100).contains(&cmd.left) && (-100..=100).contains(&cmd.right));
+        }
+        let (x, y) = brain.position();
+        assert!(x.is_finite() && y.is_finite());
+    }
+
+    #[test]
+    fn test_restore_drops_unread_transitions() {
+        let mut brain = MBotBrain::new();
+        brain.tick(&MBotSensors {
+            ultrasonic_cm: 5.0,
+            ..Default::default()
+        });
+        brain.restore(&unusual());
+        assert_eq!(brain.next_transition(), None);
+    }
+}
@@ -0,0 +1,32 @@
+//! Build and run `no_alloc_firmware`, a no_std binary with no global
+//! allocator, against mbot-core's `no_alloc` configuration.
+//!
+//! rustc refuses to link a binary that needs a heap but has no allocator,
+//! so this fails as soon as anything reachable from the brain or the shape
+//! generators allocates.
+
+// The fixture borrows the C runtime's entry point, as laid out on Linux
+#![cfg(target_os = "linux")]
+
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn test_links_and_runs_without_allocator() {
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/no_alloc_firmware/Cargo.toml");
+    let target_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("no_alloc_firmware");
+
+    let build = Command::new(env!("CARGO"))
+        .args(["build", "--offline", "--quiet", "--manifest-path"])
+        .arg(&manifest)
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .status()
+        .expect("failed to run cargo");
+    assert!(build.success(), "no_alloc firmware failed to build");
+
+    let run = Command::new(target_dir.join("debug/no-alloc-firmware"))
+        .status()
+        .expect("failed to run no_alloc firmware");
+    assert_eq!(run.code(), Some(0), "no_alloc firmware check failed");
+}
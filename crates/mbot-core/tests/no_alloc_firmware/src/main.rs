@@ -0,0 +1,66 @@
+//! Drives the brain and every shape generator with no global allocator.
+//! Exits 0 if everything ran and gave sane answers.
+
+#![no_std]
+#![no_main]
+
+use mbot_core::{
+    circle_points, circle_points_array, drive_to_point, x_points, MBotBrain, MBotSensors, RobotProfile,
+};
+
+// The C runtime provides the entry point, memcpy and friends - but no
+// heap is ever asked for
+#[link(name = "c")]
+extern "C" {
+    fn abort() -> !;
+}
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    unsafe { abort() }
+}
+
+/// The prebuilt `core` still refers to this though nothing unwinds with
+/// panic = "abort"
+#[no_mangle]
+pub extern "C" fn rust_eh_personality() {}
+
+#[no_mangle]
+pub extern "C" fn main(_argc: i32, _argv: *const *const u8) -> i32 {
+    let mut brain = MBotBrain::new();
+    let mut stop_close = false;
+    for tick in 0..200u64 {
+        let sensors = MBotSensors {
+            timestamp_us: tick * 50_000,
+            ultrasonic_cm: 100.0 - tick as f32 * 0.45,
+            encoder_left: tick as i32 * 10,
+            encoder_right: tick as i32 * 10,
+            ..Default::default()
+        };
+        let (_, cmd) = brain.tick(&sensors);
+        if !(-100..=100).contains(&cmd.left) || !(-100..=100).contains(&cmd.right) {
+            return 1;
+        }
+        stop_close |= cmd.left < 0 && cmd.right < 0;
+    }
+    if !stop_close {
+        return 2;
+    }
+
+    let profile = RobotProfile::decode(&brain.profile().encode());
+    if profile != Ok(brain.profile()) {
+        return 3;
+    }
+    brain.restore(&brain.snapshot());
+
+    let ring: [(f32, f32); 25] = circle_points_array((10.0, 10.0), 5.0);
+    if circle_points((10.0, 10.0), 5.0, 24).zip(ring).any(|(a, b)| a != b) {
+        return 4;
+    }
+    let (x, y) = x_points((0.0, 0.0), 4.0)[0];
+    let (left, right) = drive_to_point((0.0, 0.0), 0.0, (x, y), 50.0);
+    if left <= right {
+        return 5;
+    }
+    0
+}
@@ -0,0 +1,60 @@
+//! The hardware the on-robot code runs against
+//!
+//! Everything here is a trait so the same logic runs on the CyberPi and in
+//! host tests. A port implements these over its board support crate
+//! (esp-idf-hal on the CyberPi); `mock` has host versions.
+
+use mbot_core::{MBotSensors, MotorCommand};
+
+/// Where sensor readings come from
+pub trait SensorSource {
+    type Error;
+
+    /// Read every sensor once. `timestamp_us` is filled in by the caller
+    /// from its `Clock`, so sources may leave it zero.
+    fn read(&mut self) -> Result<MBotSensors, Self::Error>;
+}
+
+/// Where motor, pen, LED and buzzer commands go
+pub trait MotorSink {
+    type Error;
+
+    fn apply(&mut self, cmd: &MotorCommand) -> Result<(), Self::Error>;
+}
+
+/// A monotonic microsecond clock
+pub trait Clock {
+    /// Microseconds since some fixed point (usually boot); never goes back
+    fn now_us(&self) -> u64;
+
+    /// Return once `now_us` has reached `deadline_us`; spins by default,
+    /// boards with a timer or RTOS should sleep instead
+    fn wait_until(&self, deadline_us: u64) {
+        while self.now_us() < deadline_us {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now_us(&self) -> u64 {
+        (**self).now_us()
+    }
+
+    fn wait_until(&self, deadline_us: u64) {
+        (**self).wait_until(deadline_us)
+    }
+}
+
+/// A small block of non-volatile memory (a flash partition or NVS key)
+/// holding one record
+pub trait Storage {
+    type Error;
+
+    /// Copy the stored record into `buf`, returning its length; 0 if
+    /// nothing has been stored
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error>;
+
+    /// Replace the stored record
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error>;
+}
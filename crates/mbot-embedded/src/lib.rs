@@ -16,11 +16,27 @@
 //! # Build
 //! cargo build --target xtensa-esp32-espidf --release
 //! ```
+//!
+//! ## Porting
+//!
+//! Implement the `hal` traits over the board's drivers, then hand them to
+//! `run_brain_loop`. The same loop runs on a laptop against the `mock`
+//! hardware (`--features mock`), which is how it's tested.
 
 #![no_std]
 
 pub use mbot_core::*;
 
+pub mod hal;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod runner;
+pub mod watchdog;
+
+pub use hal::{Clock, MotorSink, SensorSource, Storage};
+pub use runner::{load_profile, run_brain_loop, save_profile, LoopConfig};
+pub use watchdog::{Watchdog, WatchdogConfig};
+
 /// Embedded-specific utilities
 pub mod embedded {
     /// Busy-wait delay (when no OS available)
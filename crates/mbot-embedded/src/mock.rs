@@ -0,0 +1,169 @@
+//! Host stand-ins for the robot's hardware
+//!
+//! Enough to run `run_brain_loop` and the watchdog on a laptop: a clock that
+//! only moves when told to (waiting is instant), scripted sensors, motors
+//! that record what they're sent, and storage in memory. Enabled in this
+//! crate's tests and by the `mock` feature for other crates'.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::fmt;
+
+use crate::hal::{Clock, MotorSink, SensorSource, Storage};
+use mbot_core::{MBotSensors, MotorCommand};
+
+/// Failures the mocks can be told to produce
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MockError {
+    /// The device stopped answering
+    Unplugged,
+    /// More bytes than the storage holds
+    Full,
+}
+
+impl fmt::Display for MockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MockError::Unplugged => write!(f, "device unplugged"),
+            MockError::Full => write!(f, "storage full"),
+        }
+    }
+}
+
+impl core::error::Error for MockError {}
+
+/// A clock that jumps straight to whatever time is waited for
+#[derive(Debug, Default)]
+pub struct MockClock {
+    now_us: Cell<u64>,
+}
+
+impl MockClock {
+    pub fn new(start_us: u64) -> Self {
+        Self {
+            now_us: Cell::new(start_us),
+        }
+    }
+
+    /// Let time pass, as work on real hardware would
+    pub fn advance_us(&self, us: u64) {
+        self.now_us.set(self.now_us.get() + us);
+    }
+}
+
+impl Clock for MockClock {
+    fn now_us(&self) -> u64 {
+        self.now_us.get()
+    }
+
+    fn wait_until(&self, deadline_us: u64) {
+        self.now_us.set(self.now_us.get().max(deadline_us));
+    }
+}
+
+/// Sensors answered by a function of the read count; None unplugs them
+pub struct MockSensors<F> {
+    script: F,
+    reads: u64,
+}
+
+impl<F: FnMut(u64) -> Option<MBotSensors>> MockSensors<F> {
+    pub fn new(script: F) -> Self {
+        Self { script, reads: 0 }
+    }
+
+    pub fn reads(&self) -> u64 {
+        self.reads
+    }
+}
+
+impl<F: FnMut(u64) -> Option<MBotSensors>> SensorSource for MockSensors<F> {
+    type Error = MockError;
+
+    fn read(&mut self) -> Result<MBotSensors, MockError> {
+        let reading = (self.script)(self.reads).ok_or(MockError::Unplugged);
+        self.reads += 1;
+        reading
+    }
+}
+
+/// Motors that record every command
+#[derive(Debug, Default)]
+pub struct MockMotors {
+    commands: Vec<MotorCommand>,
+    fail_after: Option<usize>,
+}
+
+impl MockMotors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept `count` commands, then fail
+    pub fn failing_after(count: usize) -> Self {
+        Self {
+            commands: Vec::new(),
+            fail_after: Some(count),
+        }
+    }
+
+    pub fn commands(&self) -> &[MotorCommand] {
+        &self.commands
+    }
+}
+
+impl MotorSink for MockMotors {
+    type Error = MockError;
+
+    fn apply(&mut self, cmd: &MotorCommand) -> Result<(), MockError> {
+        if self.fail_after.is_some_and(|n| self.commands.len() >= n) {
+            return Err(MockError::Unplugged);
+        }
+        self.commands.push(cmd.clone());
+        Ok(())
+    }
+}
+
+/// Storage held in memory, with a fixed capacity like a flash partition
+#[derive(Debug)]
+pub struct MockStorage {
+    bytes: Vec<u8>,
+    capacity: usize,
+}
+
+impl MockStorage {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            bytes: Vec::new(),
+            capacity,
+        }
+    }
+
+    /// Flip the bits of the byte at `offset`, as a worn flash cell might
+    pub fn corrupt(&mut self, offset: usize) {
+        if let Some(byte) = self.bytes.get_mut(offset) {
+            *byte = !*byte;
+        }
+    }
+}
+
+impl Storage for MockStorage {
+    type Error = MockError;
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, MockError> {
+        let len = self.bytes.len().min(buf.len());
+        buf[..len].copy_from_slice(&self.bytes[..len]);
+        Ok(len)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), MockError> {
+        if bytes.len() > self.capacity {
+            return Err(MockError::Full);
+        }
+        self.bytes.clear();
+        self.bytes.extend_from_slice(bytes);
+        Ok(())
+    }
+}
@@ -0,0 +1,287 @@
+//! The brain running standalone on the robot
+//!
+//! `run_brain_loop` is the whole on-robot program once the hardware is set
+//! up: read the sensors, tick the brain, drive the motors, wait for the next
+//! tick. Profiles are loaded before and saved after with `load_profile` and
+//! `save_profile`.
+
+use core::fmt;
+
+use crate::hal::{Clock, MotorSink, SensorSource, Storage};
+use mbot_core::persist::PROFILE_MAX_LEN;
+use mbot_core::{MBotBrain, MotorCommand, PersistError, RobotProfile, NOMINAL_TICK_US, PEN_UP};
+
+/// How fast and how long to run
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoopConfig {
+    /// Time between ticks
+    pub period_us: u64,
+    /// Stop after this many ticks; None runs until a hardware error
+    pub max_ticks: Option<u64>,
+}
+
+impl Default for LoopConfig {
+    /// 20Hz, forever
+    fn default() -> Self {
+        Self {
+            period_us: NOMINAL_TICK_US,
+            max_ticks: None,
+        }
+    }
+}
+
+/// What happened while the loop ran
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LoopStats {
+    pub ticks: u64,
+    /// Ticks that took longer than the period
+    pub overruns: u64,
+}
+
+/// Which piece of hardware failed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoopError<SE, ME> {
+    Sensors(SE),
+    Motors(ME),
+}
+
+impl<SE: fmt::Display, ME: fmt::Display> fmt::Display for LoopError<SE, ME> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoopError::Sensors(e) => write!(f, "sensor read failed: {}", e),
+            LoopError::Motors(e) => write!(f, "motor write failed: {}", e),
+        }
+    }
+}
+
+impl<SE: fmt::Debug + fmt::Display, ME: fmt::Debug + fmt::Display> core::error::Error for LoopError<SE, ME> {}
+
+/// Drive `brain` from the hardware at a fixed rate.
+///
+/// On a sensor error the motors are stopped (best effort) before the error
+/// is returned, so a loose cable doesn't leave the robot driving blind.
+pub fn run_brain_loop<S, M, C>(
+    brain: &mut MBotBrain,
+    sensors: &mut S,
+    motors: &mut M,
+    clock: &C,
+    config: LoopConfig,
+) -> Result<LoopStats, LoopError<S::Error, M::Error>>
+where
+    S: SensorSource,
+    M: MotorSink,
+    C: Clock,
+{
+    let mut stats = LoopStats::default();
+    let mut next_tick_us = clock.now_us();
+
+    while config.max_ticks.is_none_or(|max| stats.ticks < max) {
+        let mut reading = match sensors.read() {
+            Ok(reading) => reading,
+            Err(e) => {
+                let _ = motors.apply(&MotorCommand {
+                    pen_angle: PEN_UP,
+                    ..Default::default()
+                });
+                return Err(LoopError::Sensors(e));
+            }
+        };
+        reading.timestamp_us = clock.now_us();
+
+        let (_, cmd) = brain.tick(&reading);
+        motors.apply(&cmd).map_err(LoopError::Motors)?;
+        stats.ticks += 1;
+
+        next_tick_us += config.period_us;
+        let now = clock.now_us();
+        if now > next_tick_us {
+            // Running late: start the next tick now rather than trying to
+            // catch up with a burst
+            stats.overruns += 1;
+            next_tick_us = now;
+        } else {
+            clock.wait_until(next_tick_us);
+        }
+    }
+    Ok(stats)
+}
+
+/// Why the stored profile couldn't be loaded
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProfileError<E> {
+    Storage(E),
+    Corrupt(PersistError),
+}
+
+impl<E: fmt::Display> fmt::Display for ProfileError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileError::Storage(e) => write!(f, "storage failed: {}", e),
+            ProfileError::Corrupt(e) => write!(f, "stored profile is unusable: {}", e),
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> core::error::Error for ProfileError<E> {}
+
+/// The stored profile, or None on first boot.
+///
+/// The buffer has room beyond this build's own profile, so one saved by a
+/// newer minor version still loads instead of being cut short.
+pub fn load_profile<T: Storage>(storage: &mut T) -> Result<Option<RobotProfile>, ProfileError<T::Error>> {
+    let mut buf = [0u8; PROFILE_MAX_LEN];
+    let len = storage.read(&mut buf).map_err(ProfileError::Storage)?;
+    if len == 0 {
+        return Ok(None);
+    }
+    RobotProfile::decode(&buf[..len.min(PROFILE_MAX_LEN)])
+        .map(Some)
+        .map_err(ProfileError::Corrupt)
+}
+
+/// Store who the robot has become, for the next boot
+pub fn save_profile<T: Storage>(storage: &mut T, brain: &MBotBrain) -> Result<(), T::Error> {
+    storage.write(&brain.profile().encode())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockClock, MockError, MockMotors, MockSensors, MockStorage};
+    use mbot_core::{MBotSensors, PersonalityPreset};
+
+    fn open_floor() -> MockSensors<impl FnMut(u64) -> Option<MBotSensors>> {
+        MockSensors::new(|_| {
+            Some(MBotSensors {
+                ultrasonic_cm: 200.0,
+                ..Default::default()
+            })
+        })
+    }
+
+    #[test]
+    fn test_runs_at_fixed_rate() {
+        let clock = MockClock::new(1_000);
+        let mut motors = MockMotors::new();
+        let mut brain = MBotBrain::new();
+
+        let config = LoopConfig {
+            max_ticks: Some(40),
+            ..Default::default()
+        };
+        let stats = run_brain_loop(&mut brain, &mut open_floor(), &mut motors, &clock, config).unwrap();
+
+        assert_eq!(stats, LoopStats { ticks: 40, overruns: 0 });
+        assert_eq!(clock.now_us(), 1_000 + 40 * NOMINAL_TICK_US);
+        assert_eq!(motors.commands().len(), 40);
+        assert_eq!(brain.snapshot().elapsed_us, 40 * NOMINAL_TICK_US);
+    }
+
+    #[test]
+    fn test_slow_ticks_are_counted_not_bunched() {
+        let clock = MockClock::new(0);
+        // Each read takes 80ms of a 50ms period
+        let mut sensors = MockSensors::new(|_| {
+            clock.advance_us(80_000);
+            Some(MBotSensors::default())
+        });
+        let config = LoopConfig {
+            max_ticks: Some(10),
+            ..Default::default()
+        };
+        let stats = run_brain_loop(&mut MBotBrain::new(), &mut sensors, &mut MockMotors::new(), &clock, config)
+            .unwrap();
+        assert_eq!(stats.overruns, 10);
+        assert_eq!(clock.now_us(), 800_000);
+    }
+
+    #[test]
+    fn test_backs_away_from_obstacle() {
+        let clock = MockClock::new(0);
+        // Something approaches steadily and stops 3cm away
+        let mut sensors = MockSensors::new(|i| {
+            Some(MBotSensors {
+                ultrasonic_cm: (100.0 - i as f32 * 5.0).max(3.0),
+                ..Default::default()
+            })
+        });
+        let mut motors = MockMotors::new();
+        let config = LoopConfig {
+            max_ticks: Some(60),
+            ..Default::default()
+        };
+        run_brain_loop(&mut MBotBrain::new(), &mut sensors, &mut motors, &clock, config).unwrap();
+
+        let last = motors.commands().last().unwrap();
+        assert!(last.left < 0 && last.right < 0, "{:?}", last);
+        assert!(motors
+            .commands()
+            .iter()
+            .all(|c| (-100..=100).contains(&c.left) && (-100..=100).contains(&c.right)));
+    }
+
+    #[test]
+    fn test_sensor_failure_stops_motors() {
+        let clock = MockClock::new(0);
+        let mut sensors = MockSensors::new(|i| (i < 5).then(MBotSensors::default));
+        let mut motors = MockMotors::new();
+        let result = run_brain_loop(&mut MBotBrain::new(), &mut sensors, &mut motors, &clock, LoopConfig::default());
+
+        assert_eq!(result, Err(LoopError::Sensors(MockError::Unplugged)));
+        assert_eq!(motors.commands().len(), 6);
+        let stop = motors.commands().last().unwrap();
+        assert_eq!((stop.left, stop.right, stop.pen_angle), (0, 0, PEN_UP));
+    }
+
+    #[test]
+    fn test_motor_failure_ends_loop() {
+        let clock = MockClock::new(0);
+        let mut motors = MockMotors::failing_after(3);
+        let result = run_brain_loop(&mut MBotBrain::new(), &mut open_floor(), &mut motors, &clock, LoopConfig::default());
+        assert_eq!(result, Err(LoopError::Motors(MockError::Unplugged)));
+        assert_eq!(motors.commands().len(), 3);
+    }
+
+    #[test]
+    fn test_profile_survives_reboot() {
+        let mut storage = MockStorage::new(256);
+        assert_eq!(load_profile(&mut storage), Ok(None));
+
+        let nervous = PersonalityPreset::find("nervous-nellie").unwrap().config;
+        let mut brain = MBotBrain::with_personality(nervous);
+        let config = LoopConfig {
+            max_ticks: Some(25),
+            ..Default::default()
+        };
+        run_brain_loop(&mut brain, &mut open_floor(), &mut MockMotors::new(), &MockClock::new(0), config).unwrap();
+        save_profile(&mut storage, &brain).unwrap();
+
+        let reborn = MBotBrain::with_profile(&load_profile(&mut storage).unwrap().unwrap());
+        assert_eq!(reborn.profile(), brain.profile());
+        assert_eq!(reborn.profile().mood.lifetime_us, 25 * NOMINAL_TICK_US);
+
+        storage.corrupt(10);
+        assert!(matches!(load_profile(&mut storage), Err(ProfileError::Corrupt(_))));
+        assert_eq!(save_profile(&mut MockStorage::new(8), &brain), Err(MockError::Full));
+    }
+
+    #[test]
+    fn test_loads_profile_from_newer_minor_version() {
+        use mbot_core::persist::{crc32, PROFILE_LEN, PROFILE_VERSION_MINOR};
+
+        // A later firmware appended 24 bytes of fields this one doesn't know
+        let profile = MBotBrain::with_personality(PersonalityPreset::find("chill-charlie").unwrap().config).profile();
+        let extra = 24;
+        let mut bytes = profile.encode()[..PROFILE_LEN - 4].to_vec();
+        bytes[5] = PROFILE_VERSION_MINOR + 1;
+        let payload_len = u16::from_le_bytes([bytes[6], bytes[7]]) as usize + extra;
+        bytes[6..8].copy_from_slice(&(payload_len as u16).to_le_bytes());
+        bytes.resize(bytes.len() + extra, 0xab);
+        let crc = crc32(&bytes);
+        bytes.extend_from_slice(&crc.to_le_bytes());
+
+        let mut storage = MockStorage::new(256);
+        storage.write(&bytes).unwrap();
+        assert_eq!(load_profile(&mut storage), Ok(Some(profile)));
+    }
+}
@@ -0,0 +1,290 @@
+//! Command watchdog - stops the motors when the companion goes quiet
+//!
+//! The firmware hands every `MotorCommand` it receives to `feed` and drives
+//! the hardware from `output`. While commands keep arriving within the
+//! timeout they pass straight through. Once they stop, motor power ramps
+//! down to zero (a sudden stop can tip the robot and skid the pen), the pen
+//! lifts, and the LED turns red until the next valid command.
+
+use core::fmt;
+
+use crate::hal::Clock;
+use mbot_core::{MotorCommand, PEN_UP};
+
+/// Largest motor power a command may carry (ARCH-003)
+pub const MAX_POWER: i8 = 100;
+
+/// LED colour shown while the watchdog has the motors
+pub const FAULT_LED: [u8; 3] = [255, 0, 0];
+
+/// How long to wait for commands and how gently to stop
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WatchdogConfig {
+    /// Longest gap between commands before the watchdog takes over
+    pub timeout_us: u64,
+    /// Time taken to ramp from the last commanded power to zero
+    pub ramp_us: u64,
+}
+
+impl Default for WatchdogConfig {
+    /// Six missed ticks at 20Hz, then a fifth of a second to stop
+    fn default() -> Self {
+        Self {
+            timeout_us: 300_000,
+            ramp_us: 200_000,
+        }
+    }
+}
+
+/// Why a command was refused
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommandError {
+    /// Motor power outside -100..=100; likely a corrupted frame
+    PowerOutOfRange { left: i8, right: i8 },
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::PowerOutOfRange { left, right } => {
+                write!(f, "motor power {}/{} is outside -{max}..={max}", left, right, max = MAX_POWER)
+            }
+        }
+    }
+}
+
+impl core::error::Error for CommandError {}
+
+/// A lapse in commands the watchdog is covering for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CommandTimeout {
+    /// When the last valid command arrived (or the watchdog started)
+    pub last_command_us: u64,
+    /// How long it has been quiet
+    pub silent_us: u64,
+    /// Whether the motors have reached zero
+    pub stopped: bool,
+}
+
+/// Tracks the last valid command and takes over when they stop coming
+pub struct Watchdog<C: Clock> {
+    clock: C,
+    config: WatchdogConfig,
+    last: MotorCommand,
+    last_us: u64,
+    timeouts: u32,
+    in_fault: bool,
+}
+
+impl<C: Clock> Watchdog<C> {
+    /// Start watching; the motors stay off until the first command, and a
+    /// fault is raised if none arrives within the timeout
+    pub fn new(clock: C, config: WatchdogConfig) -> Self {
+        let last_us = clock.now_us();
+        Self {
+            clock,
+            config,
+            last: MotorCommand {
+                pen_angle: PEN_UP,
+                ..Default::default()
+            },
+            last_us,
+            timeouts: 0,
+            in_fault: false,
+        }
+    }
+
+    pub fn config(&self) -> WatchdogConfig {
+        self.config
+    }
+
+    /// Accept a command from the companion, restarting the timeout.
+    ///
+    /// Invalid commands are refused and do not count as a sign of life.
+    pub fn feed(&mut self, cmd: &MotorCommand) -> Result<(), CommandError> {
+        let in_range = |power: i8| (-MAX_POWER..=MAX_POWER).contains(&power);
+        if !in_range(cmd.left) || !in_range(cmd.right) {
+            return Err(CommandError::PowerOutOfRange {
+                left: cmd.left,
+                right: cmd.right,
+            });
+        }
+        self.last = cmd.clone();
+        self.last_us = self.clock.now_us();
+        self.in_fault = false;
+        Ok(())
+    }
+
+    /// The current timeout, if commands have stopped
+    pub fn fault(&self) -> Option<CommandTimeout> {
+        let silent_us = self.clock.now_us().saturating_sub(self.last_us);
+        (silent_us > self.config.timeout_us).then(|| CommandTimeout {
+            last_command_us: self.last_us,
+            silent_us,
+            stopped: silent_us >= self.config.timeout_us.saturating_add(self.config.ramp_us),
+        })
+    }
+
+    /// How many times commands have stopped since the watchdog started
+    pub fn timeouts(&self) -> u32 {
+        self.timeouts
+    }
+
+    /// The command to apply to the hardware now
+    pub fn output(&mut self) -> MotorCommand {
+        let Some(fault) = self.fault() else {
+            return self.last.clone();
+        };
+        if !self.in_fault {
+            self.in_fault = true;
+            self.timeouts += 1;
+        }
+
+        // Linear ramp from the last commanded power down to zero
+        let into_ramp = fault.silent_us - self.config.timeout_us;
+        let remaining = self.config.ramp_us.saturating_sub(into_ramp);
+        let scale = |power: i8| {
+            if self.config.ramp_us == 0 {
+                0
+            } else {
+                (power as i64 * remaining as i64 / self.config.ramp_us as i64) as i8
+            }
+        };
+        MotorCommand {
+            left: scale(self.last.left),
+            right: scale(self.last.right),
+            pen_angle: PEN_UP,
+            led_color: FAULT_LED,
+            buzzer_hz: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    /// A clock the test moves by hand
+    struct TestClock(Cell<u64>);
+
+    impl TestClock {
+        fn advance_ms(&self, ms: u64) {
+            self.0.set(self.0.get() + ms * 1000);
+        }
+    }
+
+    impl Clock for TestClock {
+        fn now_us(&self) -> u64 {
+            self.0.get()
+        }
+    }
+
+    fn drive(left: i8, right: i8) -> MotorCommand {
+        MotorCommand {
+            left,
+            right,
+            pen_angle: mbot_core::PEN_DOWN,
+            led_color: [0, 255, 0],
+            buzzer_hz: 0,
+        }
+    }
+
+    #[test]
+    fn test_commands_pass_through_while_fed() {
+        let clock = TestClock(Cell::new(5_000_000));
+        let mut dog = Watchdog::new(&clock, WatchdogConfig::default());
+        for _ in 0..100 {
+            dog.feed(&drive(80, -40)).unwrap();
+            clock.advance_ms(50);
+            let out = dog.output();
+            assert_eq!((out.left, out.right, out.pen_angle), (80, -40, 90));
+        }
+        assert_eq!(dog.fault(), None);
+        assert_eq!(dog.timeouts(), 0);
+    }
+
+    #[test]
+    fn test_ramps_to_zero_after_timeout() {
+        let clock = TestClock(Cell::new(0));
+        let mut dog = Watchdog::new(&clock, WatchdogConfig::default());
+        dog.feed(&drive(100, -60)).unwrap();
+
+        clock.advance_ms(300);
+        assert_eq!(dog.output().left, 100);
+
+        // Halfway down the ramp
+        clock.advance_ms(100);
+        let out = dog.output();
+        assert_eq!((out.left, out.right), (50, -30));
+        assert_eq!(out.pen_angle, PEN_UP);
+        assert_eq!(out.led_color, FAULT_LED);
+        let fault = dog.fault().unwrap();
+        assert_eq!(fault.silent_us, 400_000);
+        assert!(!fault.stopped);
+
+        // Power only ever falls
+        let mut previous = out.left;
+        for _ in 0..20 {
+            clock.advance_ms(10);
+            let left = dog.output().left;
+            assert!(left <= previous);
+            previous = left;
+        }
+        let out = dog.output();
+        assert_eq!((out.left, out.right), (0, 0));
+        assert!(dog.fault().unwrap().stopped);
+        assert_eq!(dog.timeouts(), 1);
+    }
+
+    #[test]
+    fn test_recovers_on_next_command() {
+        let clock = TestClock(Cell::new(0));
+        let mut dog = Watchdog::new(&clock, WatchdogConfig::default());
+        for _ in 0..3 {
+            dog.feed(&drive(50, 50)).unwrap();
+            clock.advance_ms(1000);
+            assert_eq!(dog.output().left, 0);
+            assert_eq!(dog.output().left, 0);
+        }
+        dog.feed(&drive(50, 50)).unwrap();
+        assert_eq!(dog.output().left, 50);
+        assert_eq!(dog.fault(), None);
+        // Each lapse is counted once however often output is polled
+        assert_eq!(dog.timeouts(), 3);
+    }
+
+    #[test]
+    fn test_invalid_command_is_not_a_sign_of_life() {
+        let clock = TestClock(Cell::new(0));
+        let mut dog = Watchdog::new(&clock, WatchdogConfig::default());
+        dog.feed(&drive(30, 30)).unwrap();
+        clock.advance_ms(250);
+        assert_eq!(
+            dog.feed(&drive(127, 30)),
+            Err(CommandError::PowerOutOfRange { left: 127, right: 30 })
+        );
+        clock.advance_ms(100);
+        assert!(dog.fault().is_some());
+        assert!(dog.output().left < 30);
+    }
+
+    #[test]
+    fn test_silent_from_boot_and_zero_ramp() {
+        let clock = TestClock(Cell::new(0));
+        let config = WatchdogConfig {
+            timeout_us: 100_000,
+            ramp_us: 0,
+        };
+        let mut dog = Watchdog::new(&clock, config);
+        assert_eq!(dog.output().left, 0);
+        assert_eq!(dog.fault(), None);
+        clock.advance_ms(150);
+        assert!(dog.fault().unwrap().stopped);
+
+        dog.feed(&drive(-90, 90)).unwrap();
+        clock.advance_ms(101);
+        let out = dog.output();
+        assert_eq!((out.left, out.right), (0, 0));
+    }
+}